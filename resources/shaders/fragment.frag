@@ -7,8 +7,11 @@ uniform vec3 u_color;
 
 out vec4 o_color;
 
+#include "srgb.glsl"
+
 void main() {
 //    vec3 posColor = vec3(f_pos * .5 + .5, 0.);
 //    o_color = vec4((posColor + u_color) * .5, 1.);
-    o_color = vec4((f_normal * .5 + .5 + u_color) * .5, 1.);
+    // The debug colors are display values, the post stack expects linear
+    o_color = vec4(srgbToLinear((f_normal * .5 + .5 + u_color) * .5), 1.);
 }
@@ -0,0 +1,10 @@
+#version 330 core
+
+out vec2 f_uv;
+
+void main() {
+    // One triangle with uvs (0, 0), (2, 0) and (0, 2) covers the screen
+    vec2 uv = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    f_uv = uv;
+    gl_Position = vec4(uv * 2. - 1., 0., 1.);
+}
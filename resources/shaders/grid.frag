@@ -0,0 +1,64 @@
+#version 330 core
+
+in vec3 f_near;
+in vec3 f_far;
+
+uniform mat4 u_viewProjection;
+uniform vec3 u_cameraPos;
+// Minor spacing, major spacing, how far the minor lines have faded, see gridSpacing in grid.rs
+uniform vec3 u_spacing;
+uniform vec3 u_minorColor;
+uniform vec3 u_majorColor;
+uniform bool u_axes;
+uniform float u_lineWidth;
+uniform float u_fadeDistance;
+uniform float u_opacity;
+
+out vec4 o_color;
+
+const vec3 X_AXIS_COLOR = vec3(.9, .15, .15);
+const vec3 Z_AXIS_COLOR = vec3(.15, .3, .9);
+
+// Coverage of lines across the 1D `coord` at integer values, `width` pixels wide
+float line(float coord, float width) {
+    float derivative = max(fwidth(coord), 1e-6);
+    float pixels = abs(fract(coord - .5) - .5) / derivative;
+    // Lines closer than a couple of pixels apart turn into moire, fade them instead
+    float density = 1. - smoothstep(.25, .5, derivative);
+    return (1. - clamp(pixels - (width - 1.) * .5, 0., 1.)) * density;
+}
+
+float lines(vec2 p, float spacing, float width) {
+    return max(line(p.x / spacing, width), line(p.y / spacing, width));
+}
+
+void main() {
+    vec3 ray = f_far - f_near;
+    // Behind the camera when t <= 0, discarded at the end to keep the derivatives defined
+    float t = -f_near.y / ray.y;
+    vec3 world = f_near + ray * t;
+    vec4 clip = u_viewProjection * vec4(world, 1.);
+    gl_FragDepth = clip.z / clip.w * .5 + .5;
+
+    vec2 p = world.xz;
+    float minor = lines(p, u_spacing.x, u_lineWidth) * (1. - u_spacing.z);
+    float major = lines(p, u_spacing.y, u_lineWidth);
+    vec4 color = vec4(u_minorColor, minor);
+    color = mix(color, vec4(u_majorColor, 1.), major);
+    if (u_axes) {
+        // Wider than the other lines, the X axis runs along z = 0 and the Z axis along x = 0
+        float dz = max(fwidth(p.y), 1e-6);
+        float dx = max(fwidth(p.x), 1e-6);
+        float xAxis = 1. - clamp(abs(p.y) / dz - u_lineWidth, 0., 1.);
+        float zAxis = 1. - clamp(abs(p.x) / dx - u_lineWidth, 0., 1.);
+        color = mix(color, vec4(X_AXIS_COLOR, 1.), xAxis);
+        color = mix(color, vec4(Z_AXIS_COLOR, 1.), zAxis);
+    }
+
+    float distance = length(p - u_cameraPos.xz);
+    color.a *= u_opacity * (1. - smoothstep(u_fadeDistance * .25, u_fadeDistance, distance));
+    if (t <= 0. || color.a <= 0.) {
+        discard;
+    }
+    o_color = color;
+}
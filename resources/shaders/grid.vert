@@ -0,0 +1,21 @@
+#version 330 core
+
+uniform mat4 u_inverseViewProjection;
+
+// World space points under the pixel on the near and far planes
+out vec3 f_near;
+out vec3 f_far;
+
+vec3 unproject(vec2 ndc, float z) {
+    vec4 p = u_inverseViewProjection * vec4(ndc, z, 1.);
+    return p.xyz / p.w;
+}
+
+void main() {
+    // Full-screen triangle as in fullscreen.vert. Points on a plane of constant NDC depth are
+    // affine in NDC, so unprojecting per vertex is exact.
+    vec2 ndc = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2) * 2. - 1.;
+    gl_Position = vec4(ndc, 0., 1.);
+    f_near = unproject(ndc, -1.);
+    f_far = unproject(ndc, 1.);
+}
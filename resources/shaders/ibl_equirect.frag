@@ -0,0 +1,35 @@
+#version 330 core
+
+const float PI = 3.14159265359;
+
+in vec2 f_uv;
+
+uniform sampler2D u_equirect;
+
+out vec4 o_color;
+
+// Cube face being rendered, +X, -X, +Y, -Y, +Z, -Z
+uniform int u_face;
+
+// Mirrors cubeFaceDirection in pbr.rs
+vec3 faceDirection(vec2 uv) {
+    vec2 p = uv * 2. - 1.;
+    vec3 d;
+    if (u_face == 0) d = vec3(1., -p.y, -p.x);
+    else if (u_face == 1) d = vec3(-1., -p.y, p.x);
+    else if (u_face == 2) d = vec3(p.x, 1., p.y);
+    else if (u_face == 3) d = vec3(p.x, -1., -p.y);
+    else if (u_face == 4) d = vec3(p.x, -p.y, 1.);
+    else d = vec3(-p.x, -p.y, -1.);
+    return normalize(d);
+}
+
+// Mirrors equirectUv in pbr.rs
+vec2 equirectUv(vec3 d) {
+    return vec2(atan(d.z, d.x) / (2. * PI) + .5, acos(clamp(d.y, -1., 1.)) / PI);
+}
+
+void main() {
+    // Level 0, derivatives jump where the longitude wraps around
+    o_color = vec4(textureLod(u_equirect, equirectUv(faceDirection(f_uv)), 0.).rgb, 1.);
+}
@@ -0,0 +1,48 @@
+#version 330 core
+
+const float PI = 3.14159265359;
+const float STEP = .025;
+// Reading a blurrier level keeps small bright spots (the sun) from aliasing
+const float SOURCE_LOD = 3.;
+
+in vec2 f_uv;
+
+uniform samplerCube u_environment;
+
+out vec4 o_color;
+
+// Cube face being rendered, +X, -X, +Y, -Y, +Z, -Z
+uniform int u_face;
+
+// Mirrors cubeFaceDirection in pbr.rs
+vec3 faceDirection(vec2 uv) {
+    vec2 p = uv * 2. - 1.;
+    vec3 d;
+    if (u_face == 0) d = vec3(1., -p.y, -p.x);
+    else if (u_face == 1) d = vec3(-1., -p.y, p.x);
+    else if (u_face == 2) d = vec3(p.x, 1., p.y);
+    else if (u_face == 3) d = vec3(p.x, -1., -p.y);
+    else if (u_face == 4) d = vec3(p.x, -p.y, 1.);
+    else d = vec3(-p.x, -p.y, -1.);
+    return normalize(d);
+}
+
+// Cosine weighted integral of the incoming light over the hemisphere around the normal
+void main() {
+    vec3 n = faceDirection(f_uv);
+    vec3 up = abs(n.y) < .999 ? vec3(0., 1., 0.) : vec3(1., 0., 0.);
+    vec3 right = normalize(cross(up, n));
+    up = cross(n, right);
+
+    vec3 sum = vec3(0.);
+    float count = 0.;
+    for (float phi = 0.; phi < 2. * PI; phi += STEP) {
+        for (float theta = 0.; theta < .5 * PI; theta += STEP) {
+            vec3 t = vec3(sin(theta) * cos(phi), sin(theta) * sin(phi), cos(theta));
+            vec3 l = t.x * right + t.y * up + t.z * n;
+            sum += textureLod(u_environment, l, SOURCE_LOD).rgb * cos(theta) * sin(theta);
+            count++;
+        }
+    }
+    o_color = vec4(PI * sum / count, 1.);
+}
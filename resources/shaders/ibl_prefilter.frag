@@ -0,0 +1,81 @@
+#version 460 core
+
+const float PI = 3.14159265359;
+const uint SAMPLES = 512u;
+
+in vec2 f_uv;
+
+uniform samplerCube u_environment;
+// Face size of level 0 of u_environment
+uniform float u_resolution;
+uniform float u_roughness;
+
+out vec4 o_color;
+
+// Cube face being rendered, +X, -X, +Y, -Y, +Z, -Z
+uniform int u_face;
+
+// Mirrors cubeFaceDirection in pbr.rs
+vec3 faceDirection(vec2 uv) {
+    vec2 p = uv * 2. - 1.;
+    vec3 d;
+    if (u_face == 0) d = vec3(1., -p.y, -p.x);
+    else if (u_face == 1) d = vec3(-1., -p.y, p.x);
+    else if (u_face == 2) d = vec3(p.x, 1., p.y);
+    else if (u_face == 3) d = vec3(p.x, -1., -p.y);
+    else if (u_face == 4) d = vec3(p.x, -p.y, 1.);
+    else d = vec3(-p.x, -p.y, -1.);
+    return normalize(d);
+}
+
+// Mirrors hammersley and importanceSampleGgx in pbr.rs
+vec2 hammersley(uint i, uint count) {
+    return vec2(float(i) / float(count), float(bitfieldReverse(i)) * 2.3283064365386963e-10);
+}
+
+vec3 importanceSampleGgx(vec2 xi, vec3 n, float roughness) {
+    float a = roughness * roughness;
+    float phi = 2. * PI * xi.x;
+    float cosTheta = sqrt((1. - xi.y) / (1. + (a * a - 1.) * xi.y));
+    float sinTheta = sqrt(1. - cosTheta * cosTheta);
+    vec3 h = vec3(cos(phi) * sinTheta, sin(phi) * sinTheta, cosTheta);
+    vec3 up = abs(n.z) < .999 ? vec3(0., 0., 1.) : vec3(1., 0., 0.);
+    vec3 tangent = normalize(cross(up, n));
+    vec3 bitangent = cross(n, tangent);
+    return normalize(tangent * h.x + bitangent * h.y + n * h.z);
+}
+
+float distributionGgx(float nDotH, float roughness) {
+    float a2 = roughness * roughness * roughness * roughness;
+    float d = nDotH * nDotH * (a2 - 1.) + 1.;
+    return a2 / (PI * d * d);
+}
+
+// Specular lobe of u_roughness around each direction, assuming it's also the view direction
+void main() {
+    vec3 n = faceDirection(f_uv);
+    if (u_roughness == 0.) {
+        o_color = vec4(textureLod(u_environment, n, 0.).rgb, 1.);
+        return;
+    }
+    vec3 v = n;
+    vec3 sum = vec3(0.);
+    float weight = 0.;
+    for (uint i = 0u; i < SAMPLES; i++) {
+        vec3 h = importanceSampleGgx(hammersley(i, SAMPLES), n, u_roughness);
+        vec3 l = normalize(2. * dot(v, h) * h - v);
+        float nDotL = dot(n, l);
+        if (nDotL > 0.) {
+            // Sample a level whose texels cover about as much solid angle as the sample,
+            // rare samples would otherwise show up as bright dots
+            float nDotH = max(dot(n, h), 0.);
+            float pdf = distributionGgx(nDotH, u_roughness) * .25 + 1e-4;
+            float texelAngle = 4. * PI / (6. * u_resolution * u_resolution);
+            float sampleAngle = 1. / (float(SAMPLES) * pdf);
+            float lod = max(.5 * log2(sampleAngle / texelAngle), 0.);
+            sum += textureLod(u_environment, l, lod).rgb * nDotL;
+            weight += nDotL;
+        }
+    }
+    o_color = vec4(sum / weight, 1.);
+}
@@ -0,0 +1,18 @@
+#version 330 core
+
+in vec3 f_normal;
+in vec4 f_color;
+
+// Towards the light, zero for none
+uniform vec3 u_sunDirection;
+uniform vec3 u_ambientLight;
+
+out vec4 o_color;
+
+void main() {
+    vec3 n = normalize(f_normal);
+    float diffuse = max(dot(n, u_sunDirection), 0.);
+    // Hemisphere term so the unlit side still shows its shape
+    vec3 ambient = u_ambientLight * (n.y * .25 + .75);
+    o_color = vec4(f_color.rgb * (ambient + diffuse), f_color.a);
+}
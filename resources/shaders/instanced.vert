@@ -0,0 +1,19 @@
+#version 330 core
+
+layout (location = 0) in vec3 i_position;
+layout (location = 1) in vec3 i_normal;
+// Per instance, see Instance in instancing.rs
+layout (location = 5) in mat4 i_model;
+layout (location = 9) in vec4 i_color;
+
+uniform mat4 u_viewProjection;
+
+out vec3 f_normal;
+out vec4 f_color;
+
+void main() {
+    gl_Position = u_viewProjection * i_model * vec4(i_position, 1.);
+    // Instances are only rotated and uniformly scaled
+    f_normal = mat3(i_model) * i_normal;
+    f_color = i_color;
+}
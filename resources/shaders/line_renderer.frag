@@ -2,8 +2,18 @@
 
 in vec3 f_color;
 
+uniform int u_occluded;
+
 out vec4 o_color;
 
 void main() {
+	if (u_occluded != 0) {
+		// x-ray: hidden parts are dashed in screen space and dimmed
+		if (mod(floor((gl_FragCoord.x + gl_FragCoord.y) * .25), 2.) > .5) {
+			discard;
+		}
+		o_color = vec4(f_color * .4, 1.);
+		return;
+	}
 	o_color = vec4(f_color, 1.);
 }
\ No newline at end of file
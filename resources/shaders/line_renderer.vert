@@ -1,13 +1,17 @@
 #version 330 core
 
 uniform mat4 u_pvm;
+// Drawn straight to the window, where colors are display values like ImGui's
+uniform int u_display;
 
 in vec3 i_position;
 in vec3 i_color;
 
 out vec3 f_color;
 
+#include "srgb.glsl"
+
 void main() {
 	gl_Position = u_pvm * vec4(i_position, 1.);
-	f_color = i_color;
+	f_color = u_display != 0 ? i_color : srgbToLinear(i_color);
 }
\ No newline at end of file
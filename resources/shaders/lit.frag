@@ -0,0 +1,130 @@
+#version 430 core
+
+in vec3 f_worldPos;
+in vec3 f_normal;
+in vec2 f_uv;
+
+const uint DIRECTIONAL = 0u;
+const uint POINT = 1u;
+const uint SPOT = 2u;
+
+// Mirrors lights.rs
+struct Light {
+    vec4 positionKind;   // xyz position, w kind
+    vec4 directionRange; // xyz direction the light travels in, w range
+    vec4 colorIntensity;
+    vec4 cone;           // x cos(inner angle), y cos(outer angle), z shadow layer
+};
+
+layout (std430, binding = 0) readonly buffer Lights {
+    uint lightCount;
+    Light lights[];
+};
+
+// See shadows.rs, a light's first layer is in cone.z (-1 without shadows)
+uniform bool u_shadows;
+uniform mat4 u_shadowMatrices[8];
+// View space distance where each cascade ends
+uniform vec4 u_cascadeSplits;
+uniform int u_cascadeCount;
+uniform mat4 u_view;
+uniform float u_shadowBias;
+uniform float u_shadowSlopeBias;
+uniform float u_shadowNormalBias;
+uniform int u_pcfRadius;
+layout (binding = 8) uniform sampler2DArrayShadow u_shadowMap;
+
+uniform vec3 u_viewPos;
+uniform vec3 u_ambientLight;
+
+uniform vec3 u_ambient;
+uniform vec3 u_diffuse;
+uniform vec3 u_specular;
+uniform float u_shininess;
+// Multiplies u_diffuse when set
+uniform bool u_hasDiffuseMap;
+uniform sampler2D u_diffuseMap;
+
+out vec4 o_color;
+
+// Fraction of the light reaching f_worldPos, PCF over (2 * u_pcfRadius + 1)^2 comparisons
+float shadow(Light light, vec3 n, vec3 l) {
+    int layer = int(light.cone.z);
+    if (!u_shadows || layer < 0) {
+        return 1.;
+    }
+    if (uint(light.positionKind.w) == DIRECTIONAL) {
+        float depth = -(u_view * vec4(f_worldPos, 1.)).z;
+        int cascade = 0;
+        while (cascade < u_cascadeCount - 1 && depth > u_cascadeSplits[cascade]) {
+            cascade++;
+        }
+        if (u_cascadeCount == 0 || depth > u_cascadeSplits[cascade]) {
+            return 1.;
+        }
+        layer += cascade;
+    }
+    float nDotL = clamp(dot(n, l), 0., 1.);
+    // Grazing surfaces need more bias, moving along the normal avoids most acne
+    vec3 p = f_worldPos + n * u_shadowNormalBias * (1. - nDotL);
+    vec4 clip = u_shadowMatrices[layer] * vec4(p, 1.);
+    vec3 coord = clip.xyz / clip.w * .5 + .5;
+    if (coord.z > 1.) {
+        return 1.;
+    }
+    float reference = coord.z - u_shadowBias - u_shadowSlopeBias * (1. - nDotL);
+    vec2 texel = 1. / vec2(textureSize(u_shadowMap, 0).xy);
+    float lit = 0.;
+    for (int x = -u_pcfRadius; x <= u_pcfRadius; x++) {
+        for (int y = -u_pcfRadius; y <= u_pcfRadius; y++) {
+            lit += texture(u_shadowMap, vec4(coord.xy + vec2(x, y) * texel, float(layer), reference));
+        }
+    }
+    float side = float(2 * u_pcfRadius + 1);
+    return lit / (side * side);
+}
+
+float attenuation(float distance, float range) {
+    float ratio = distance / max(range, 1e-4);
+    float window = clamp(1. - ratio * ratio * ratio * ratio, 0., 1.);
+    return window * window / (distance * distance + 1.);
+}
+
+void main() {
+    vec3 n = normalize(f_normal);
+    // Two-sided, open meshes (planes, the star) are lit from both sides
+    if (!gl_FrontFacing) {
+        n = -n;
+    }
+    vec3 v = normalize(u_viewPos - f_worldPos);
+    vec3 albedo = u_diffuse;
+    if (u_hasDiffuseMap) {
+        albedo *= texture(u_diffuseMap, f_uv).rgb;
+    }
+
+    vec3 color = u_ambient + albedo * u_ambientLight;
+    for (uint i = 0u; i < lightCount; i++) {
+        Light light = lights[i];
+        uint kind = uint(light.positionKind.w);
+        vec3 direction = light.directionRange.xyz;
+        vec3 l;
+        float strength = light.colorIntensity.w;
+        if (kind == DIRECTIONAL) {
+            l = -direction;
+        } else {
+            vec3 toLight = light.positionKind.xyz - f_worldPos;
+            float distance = length(toLight);
+            l = toLight / max(distance, 1e-4);
+            strength *= attenuation(distance, light.directionRange.w);
+            if (kind == SPOT) {
+                strength *= smoothstep(light.cone.y, light.cone.x, dot(-l, direction));
+            }
+        }
+        strength *= shadow(light, n, l);
+        vec3 h = normalize(l + v);
+        float diffuse = max(dot(n, l), 0.);
+        float specular = diffuse > 0. ? pow(max(dot(n, h), 0.), max(u_shininess, 1.)) : 0.;
+        color += light.colorIntensity.rgb * strength * (albedo * diffuse + u_specular * specular);
+    }
+    o_color = vec4(color, 1.);
+}
@@ -0,0 +1,19 @@
+#version 330 core
+
+layout (location = 0) in vec3 i_position;
+layout (location = 1) in vec3 i_normal;
+layout (location = 2) in vec2 i_uv;
+
+uniform mat4 u_pvm;
+uniform mat4 u_model;
+
+out vec3 f_worldPos;
+out vec3 f_normal;
+out vec2 f_uv;
+
+void main() {
+    gl_Position = u_pvm * vec4(i_position, 1.);
+    f_worldPos = (u_model * vec4(i_position, 1.)).xyz;
+    f_normal = mat3(transpose(inverse(u_model))) * i_normal;
+    f_uv = i_uv;
+}
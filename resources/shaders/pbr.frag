@@ -0,0 +1,235 @@
+#version 430 core
+
+const float PI = 3.14159265359;
+
+const uint DIRECTIONAL = 0u;
+const uint POINT = 1u;
+const uint SPOT = 2u;
+
+// Bits of u_maps, see MapSlot in pbr.rs
+const uint BASE_COLOR_MAP = 1u;
+const uint METALLIC_ROUGHNESS_MAP = 2u;
+const uint NORMAL_MAP = 4u;
+const uint OCCLUSION_MAP = 8u;
+const uint EMISSIVE_MAP = 16u;
+
+in vec3 f_worldPos;
+in vec3 f_normal;
+in vec2 f_uv;
+
+// Mirrors lights.rs
+struct Light {
+    vec4 positionKind;   // xyz position, w kind
+    vec4 directionRange; // xyz direction the light travels in, w range
+    vec4 colorIntensity;
+    vec4 cone;           // x cos(inner angle), y cos(outer angle), z shadow layer
+};
+
+layout (std430, binding = 0) readonly buffer Lights {
+    uint lightCount;
+    Light lights[];
+};
+
+// See shadows.rs, a light's first layer is in cone.z (-1 without shadows)
+uniform bool u_shadows;
+uniform mat4 u_shadowMatrices[8];
+// View space distance where each cascade ends
+uniform vec4 u_cascadeSplits;
+uniform int u_cascadeCount;
+uniform mat4 u_view;
+uniform float u_shadowBias;
+uniform float u_shadowSlopeBias;
+uniform float u_shadowNormalBias;
+uniform int u_pcfRadius;
+layout (binding = 8) uniform sampler2DArrayShadow u_shadowMap;
+
+uniform vec3 u_viewPos;
+uniform vec3 u_ambientLight;
+
+uniform vec4 u_baseColor;
+uniform float u_metallic;
+uniform float u_roughness;
+uniform vec3 u_emissive;
+uniform uint u_maps;
+layout (binding = 0) uniform sampler2D u_baseColorMap;
+layout (binding = 1) uniform sampler2D u_metallicRoughnessMap;
+layout (binding = 2) uniform sampler2D u_normalMap;
+layout (binding = 3) uniform sampler2D u_occlusionMap;
+layout (binding = 4) uniform sampler2D u_emissiveMap;
+
+uniform bool u_hasEnvironment;
+uniform float u_environmentIntensity;
+uniform float u_prefilteredLevels;
+layout (binding = 5) uniform samplerCube u_irradiance;
+layout (binding = 6) uniform samplerCube u_prefiltered;
+layout (binding = 7) uniform sampler2D u_brdfLut;
+
+out vec4 o_color;
+
+// Fraction of the light reaching f_worldPos, PCF over (2 * u_pcfRadius + 1)^2 comparisons
+float shadow(Light light, vec3 n, vec3 l) {
+    int layer = int(light.cone.z);
+    if (!u_shadows || layer < 0) {
+        return 1.;
+    }
+    if (uint(light.positionKind.w) == DIRECTIONAL) {
+        float depth = -(u_view * vec4(f_worldPos, 1.)).z;
+        int cascade = 0;
+        while (cascade < u_cascadeCount - 1 && depth > u_cascadeSplits[cascade]) {
+            cascade++;
+        }
+        if (u_cascadeCount == 0 || depth > u_cascadeSplits[cascade]) {
+            return 1.;
+        }
+        layer += cascade;
+    }
+    float nDotL = clamp(dot(n, l), 0., 1.);
+    // Grazing surfaces need more bias, moving along the normal avoids most acne
+    vec3 p = f_worldPos + n * u_shadowNormalBias * (1. - nDotL);
+    vec4 clip = u_shadowMatrices[layer] * vec4(p, 1.);
+    vec3 coord = clip.xyz / clip.w * .5 + .5;
+    if (coord.z > 1.) {
+        return 1.;
+    }
+    float reference = coord.z - u_shadowBias - u_shadowSlopeBias * (1. - nDotL);
+    vec2 texel = 1. / vec2(textureSize(u_shadowMap, 0).xy);
+    float lit = 0.;
+    for (int x = -u_pcfRadius; x <= u_pcfRadius; x++) {
+        for (int y = -u_pcfRadius; y <= u_pcfRadius; y++) {
+            lit += texture(u_shadowMap, vec4(coord.xy + vec2(x, y) * texel, float(layer), reference));
+        }
+    }
+    float side = float(2 * u_pcfRadius + 1);
+    return lit / (side * side);
+}
+
+float attenuation(float distance, float range) {
+    float ratio = distance / max(range, 1e-4);
+    float window = clamp(1. - ratio * ratio * ratio * ratio, 0., 1.);
+    return window * window / (distance * distance + 1.);
+}
+
+// Tangent frame from screen space derivatives, so meshes don't need tangents. The bitangent
+// is flipped since V runs down the (unflipped) glTF images.
+mat3 cotangentFrame(vec3 n, vec3 p, vec2 uv) {
+    vec3 dp1 = dFdx(p);
+    vec3 dp2 = dFdy(p);
+    vec2 duv1 = dFdx(uv);
+    vec2 duv2 = dFdy(uv);
+    vec3 dp2perp = cross(dp2, n);
+    vec3 dp1perp = cross(n, dp1);
+    vec3 t = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 b = dp2perp * duv1.y + dp1perp * duv2.y;
+    float invMax = inversesqrt(max(max(dot(t, t), dot(b, b)), 1e-12));
+    return mat3(t * invMax, -b * invMax, n);
+}
+
+float distributionGgx(float nDotH, float roughness) {
+    float a2 = roughness * roughness * roughness * roughness;
+    float d = nDotH * nDotH * (a2 - 1.) + 1.;
+    return a2 / (PI * d * d);
+}
+
+// Smith-Schlick with the remapping for analytic lights
+float geometrySmith(float nDotV, float nDotL, float roughness) {
+    float r = roughness + 1.;
+    float k = r * r / 8.;
+    return nDotV / (nDotV * (1. - k) + k) * nDotL / (nDotL * (1. - k) + k);
+}
+
+vec3 fresnelSchlick(float cosTheta, vec3 f0) {
+    return f0 + (1. - f0) * pow(clamp(1. - cosTheta, 0., 1.), 5.);
+}
+
+vec3 fresnelSchlickRoughness(float cosTheta, vec3 f0, float roughness) {
+    return f0 + (max(vec3(1. - roughness), f0) - f0) * pow(clamp(1. - cosTheta, 0., 1.), 5.);
+}
+
+void main() {
+    vec4 baseColor = u_baseColor;
+    if ((u_maps & BASE_COLOR_MAP) != 0u) {
+        baseColor *= texture(u_baseColorMap, f_uv);
+    }
+    float metallic = u_metallic;
+    float roughness = u_roughness;
+    if ((u_maps & METALLIC_ROUGHNESS_MAP) != 0u) {
+        vec4 texel = texture(u_metallicRoughnessMap, f_uv);
+        roughness *= texel.g;
+        metallic *= texel.b;
+    }
+    // Perfect mirrors make the highlight of point lights vanish
+    roughness = clamp(roughness, .04, 1.);
+
+    vec3 n = normalize(f_normal);
+    // Two-sided, open meshes (planes, the star) are lit from both sides
+    if (!gl_FrontFacing) {
+        n = -n;
+    }
+    if ((u_maps & NORMAL_MAP) != 0u) {
+        vec3 tangentNormal = texture(u_normalMap, f_uv).xyz * 2. - 1.;
+        n = normalize(cotangentFrame(n, f_worldPos, f_uv) * tangentNormal);
+    }
+    vec3 v = normalize(u_viewPos - f_worldPos);
+    float nDotV = max(dot(n, v), 1e-4);
+    vec3 albedo = baseColor.rgb;
+    vec3 f0 = mix(vec3(.04), albedo, metallic);
+
+    vec3 color = vec3(0.);
+    for (uint i = 0u; i < lightCount; i++) {
+        Light light = lights[i];
+        uint kind = uint(light.positionKind.w);
+        vec3 direction = light.directionRange.xyz;
+        vec3 l;
+        float strength = light.colorIntensity.w;
+        if (kind == DIRECTIONAL) {
+            l = -direction;
+        } else {
+            vec3 toLight = light.positionKind.xyz - f_worldPos;
+            float distance = length(toLight);
+            l = toLight / max(distance, 1e-4);
+            strength *= attenuation(distance, light.directionRange.w);
+            if (kind == SPOT) {
+                strength *= smoothstep(light.cone.y, light.cone.x, dot(-l, direction));
+            }
+        }
+        float nDotL = dot(n, l);
+        if (nDotL <= 0.) {
+            continue;
+        }
+        strength *= shadow(light, n, l);
+        vec3 h = normalize(l + v);
+        vec3 f = fresnelSchlick(max(dot(h, v), 0.), f0);
+        float d = distributionGgx(max(dot(n, h), 0.), roughness);
+        float g = geometrySmith(nDotV, nDotL, roughness);
+        vec3 specular = d * g * f / (4. * nDotV * nDotL + 1e-4);
+        vec3 diffuse = (1. - f) * (1. - metallic) * albedo / PI;
+        color += (diffuse + specular) * light.colorIntensity.rgb * strength * nDotL;
+    }
+
+    float occlusion = 1.;
+    if ((u_maps & OCCLUSION_MAP) != 0u) {
+        occlusion = texture(u_occlusionMap, f_uv).r;
+    }
+    if (u_hasEnvironment) {
+        // Split sum approximation
+        vec3 f = fresnelSchlickRoughness(nDotV, f0, roughness);
+        vec3 kd = (1. - f) * (1. - metallic);
+        vec3 diffuse = texture(u_irradiance, n).rgb * albedo;
+        vec3 r = reflect(-v, n);
+        vec3 prefiltered = textureLod(u_prefiltered, r, roughness * (u_prefilteredLevels - 1.)).rgb;
+        vec2 brdf = texture(u_brdfLut, vec2(nDotV, roughness)).rg;
+        vec3 specular = prefiltered * (f * brdf.x + brdf.y);
+        color += (kd * diffuse + specular) * occlusion * u_environmentIntensity;
+    } else {
+        color += u_ambientLight * albedo * (1. - metallic) * occlusion;
+    }
+
+    vec3 emissive = u_emissive;
+    if ((u_maps & EMISSIVE_MAP) != 0u) {
+        emissive *= texture(u_emissiveMap, f_uv).rgb;
+    }
+    color += emissive;
+
+    // Linear HDR, exposure and tone mapping happen in post_process.rs
+    o_color = vec4(color, baseColor.a);
+}
@@ -0,0 +1,14 @@
+#version 330 core
+
+in vec2 f_uv;
+
+uniform sampler2D u_source;
+uniform sampler2D u_bloom;
+uniform float u_intensity;
+
+out vec4 o_color;
+
+void main() {
+    vec3 color = texture(u_source, f_uv).rgb + texture(u_bloom, f_uv).rgb * u_intensity;
+    o_color = vec4(color, 1.);
+}
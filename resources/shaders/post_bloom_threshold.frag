@@ -0,0 +1,23 @@
+#version 330 core
+
+in vec2 f_uv;
+
+uniform sampler2D u_source;
+uniform float u_threshold;
+// Width of the soft transition around u_threshold
+uniform float u_knee;
+
+out vec4 o_color;
+
+// Mirrors bloomWeight in post_process.rs
+float bloomWeight(float brightness) {
+    float soft = clamp(brightness - u_threshold + u_knee, 0., 2. * u_knee);
+    soft = soft * soft / (4. * u_knee + 1e-4);
+    return max(soft, brightness - u_threshold) / max(brightness, 1e-4);
+}
+
+void main() {
+    vec3 color = texture(u_source, f_uv).rgb;
+    float brightness = max(color.r, max(color.g, color.b));
+    o_color = vec4(color * bloomWeight(brightness), 1.);
+}
@@ -0,0 +1,23 @@
+#version 330 core
+
+const int MAX_TAPS = 8;
+
+in vec2 f_uv;
+
+uniform sampler2D u_source;
+// One texel along the blur axis
+uniform vec2 u_direction;
+// Center weight first, see gaussianWeights in post_process.rs
+uniform float u_weights[MAX_TAPS];
+uniform int u_taps;
+
+out vec4 o_color;
+
+void main() {
+    vec3 sum = texture(u_source, f_uv).rgb * u_weights[0];
+    for (int i = 1; i < u_taps; i++) {
+        vec2 offset = u_direction * float(i);
+        sum += (texture(u_source, f_uv + offset).rgb + texture(u_source, f_uv - offset).rgb) * u_weights[i];
+    }
+    o_color = vec4(sum, 1.);
+}
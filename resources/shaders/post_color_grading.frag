@@ -0,0 +1,20 @@
+#version 330 core
+
+in vec2 f_uv;
+
+uniform sampler2D u_source;
+uniform float u_brightness;
+uniform float u_contrast;
+uniform float u_saturation;
+uniform vec3 u_tint;
+
+out vec4 o_color;
+
+void main() {
+    vec3 color = texture(u_source, f_uv).rgb * u_tint + u_brightness;
+    // Contrast around middle grey, saturation against Rec. 709 luminance
+    color = (color - .5) * u_contrast + .5;
+    float luma = dot(color, vec3(.2126, .7152, .0722));
+    color = mix(vec3(luma), color, u_saturation);
+    o_color = vec4(max(color, 0.), 1.);
+}
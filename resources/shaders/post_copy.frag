@@ -0,0 +1,12 @@
+#version 330 core
+
+in vec2 f_uv;
+
+uniform sampler2D u_source;
+
+out vec4 o_color;
+
+// Copies the last pass onto the window, which encodes it when GL_FRAMEBUFFER_SRGB is on
+void main() {
+    o_color = vec4(texture(u_source, f_uv).rgb, 1.);
+}
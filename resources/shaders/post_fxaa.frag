@@ -0,0 +1,52 @@
+#version 330 core
+
+// FXAA on display values, after tone mapping and gamma: blends across edges found from
+// luma contrast.
+
+in vec2 f_uv;
+
+uniform sampler2D u_source;
+uniform vec2 u_resolution;
+// Minimum local contrast that counts as an edge, relative and absolute
+uniform float u_edgeThreshold;
+uniform float u_edgeThresholdMin;
+uniform float u_subpixel;
+
+out vec4 o_color;
+
+const float SPAN_MAX = 8.;
+const float REDUCE_MUL = 1. / 8.;
+
+float luma(vec3 color) {
+    return dot(color, vec3(.299, .587, .114));
+}
+
+void main() {
+    vec2 texel = 1. / u_resolution;
+    vec3 center = texture(u_source, f_uv).rgb;
+    float lumaM = luma(center);
+    float lumaNW = luma(texture(u_source, f_uv + vec2(-1., -1.) * texel).rgb);
+    float lumaNE = luma(texture(u_source, f_uv + vec2(1., -1.) * texel).rgb);
+    float lumaSW = luma(texture(u_source, f_uv + vec2(-1., 1.) * texel).rgb);
+    float lumaSE = luma(texture(u_source, f_uv + vec2(1., 1.) * texel).rgb);
+    float lumaMin = min(lumaM, min(min(lumaNW, lumaNE), min(lumaSW, lumaSE)));
+    float lumaMax = max(lumaM, max(max(lumaNW, lumaNE), max(lumaSW, lumaSE)));
+    if (lumaMax - lumaMin < max(u_edgeThresholdMin, lumaMax * u_edgeThreshold)) {
+        o_color = vec4(center, 1.);
+        return;
+    }
+
+    // Blend along the edge, perpendicular to the luma gradient
+    vec2 direction = vec2(-((lumaNW + lumaNE) - (lumaSW + lumaSE)), (lumaNW + lumaSW) - (lumaNE + lumaSE));
+    float reduce = max((lumaNW + lumaNE + lumaSW + lumaSE) * .25 * REDUCE_MUL * u_subpixel, 1. / 128.);
+    float scale = 1. / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, -SPAN_MAX, SPAN_MAX) * texel;
+
+    vec3 near = .5 * (texture(u_source, f_uv + direction * (1. / 3. - .5)).rgb
+        + texture(u_source, f_uv + direction * (2. / 3. - .5)).rgb);
+    vec3 far = near * .5 + .25 * (texture(u_source, f_uv - direction * .5).rgb
+        + texture(u_source, f_uv + direction * .5).rgb);
+    // The wide blend overshoots on thin features, fall back to the narrow one there
+    float lumaFar = luma(far);
+    o_color = vec4(lumaFar < lumaMin || lumaFar > lumaMax ? near : far, 1.);
+}
@@ -0,0 +1,13 @@
+#version 330 core
+
+in vec2 f_uv;
+
+uniform sampler2D u_source;
+uniform float u_gamma;
+
+out vec4 o_color;
+
+void main() {
+    vec3 color = texture(u_source, f_uv).rgb;
+    o_color = vec4(pow(max(color, 0.), vec3(1. / u_gamma)), 1.);
+}
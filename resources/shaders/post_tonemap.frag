@@ -0,0 +1,25 @@
+#version 330 core
+
+in vec2 f_uv;
+
+uniform sampler2D u_source;
+uniform float u_exposure;
+// 0 Reinhard, 1 ACES, see Tonemapper in post_process.rs
+uniform int u_tonemapper;
+
+out vec4 o_color;
+
+// Mirrors acesFilm in post_process.rs
+vec3 acesFilm(vec3 x) {
+    return clamp(x * (2.51 * x + .03) / (x * (2.43 * x + .59) + .14), 0., 1.);
+}
+
+void main() {
+    vec3 color = texture(u_source, f_uv).rgb * u_exposure;
+    if (u_tonemapper == 1) {
+        color = acesFilm(color);
+    } else {
+        color = color / (1. + color);
+    }
+    o_color = vec4(color, 1.);
+}
@@ -0,0 +1,20 @@
+#version 330 core
+
+in vec2 f_uv;
+
+uniform sampler2D u_source;
+// Distance from the center, in half screen heights, where darkening starts
+uniform float u_radius;
+uniform float u_softness;
+uniform float u_strength;
+uniform vec2 u_resolution;
+
+out vec4 o_color;
+
+void main() {
+    vec3 color = texture(u_source, f_uv).rgb;
+    // Round on any aspect ratio
+    vec2 p = (f_uv - .5) * 2. * vec2(u_resolution.x / u_resolution.y, 1.);
+    float vignette = smoothstep(u_radius, u_radius + u_softness, length(p));
+    o_color = vec4(color * (1. - vignette * u_strength), 1.);
+}
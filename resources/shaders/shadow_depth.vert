@@ -0,0 +1,10 @@
+#version 330 core
+
+layout (location = 0) in vec3 i_position;
+
+uniform mat4 u_lightViewProjection;
+uniform mat4 u_model;
+
+void main() {
+    gl_Position = u_lightViewProjection * u_model * vec4(i_position, 1.);
+}
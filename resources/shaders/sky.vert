@@ -0,0 +1,14 @@
+#version 330 core
+
+uniform mat4 u_inverseSky;
+
+out vec3 f_direction;
+
+void main() {
+    // Full-screen triangle as in fullscreen.vert, at the far plane (z = w)
+    vec2 ndc = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2) * 2. - 1.;
+    gl_Position = vec4(ndc, 1., 1.);
+    // Far plane points are affine in NDC, so the divide can happen per vertex
+    vec4 far = u_inverseSky * vec4(ndc, 1., 1.);
+    f_direction = far.xyz / far.w;
+}
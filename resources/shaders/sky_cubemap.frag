@@ -0,0 +1,12 @@
+#version 330 core
+
+in vec3 f_direction;
+
+uniform samplerCube u_sky;
+uniform float u_intensity;
+
+out vec4 o_color;
+
+void main() {
+    o_color = vec4(texture(u_sky, normalize(f_direction)).rgb * u_intensity, 1.);
+}
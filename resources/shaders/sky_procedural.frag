@@ -0,0 +1,42 @@
+#version 330 core
+
+in vec3 f_direction;
+
+// Linear RGB, see SkySettings in sky.rs
+uniform vec3 u_zenith;
+uniform vec3 u_horizon;
+uniform vec3 u_ground;
+uniform vec3 u_sunset;
+uniform bool u_hasSun;
+// Towards the sun
+uniform vec3 u_sunDirection;
+uniform vec3 u_sunColor;
+// Cosine of the sun disc's angular radius
+uniform float u_sunCos;
+uniform float u_sunIntensity;
+uniform float u_intensity;
+
+out vec4 o_color;
+
+void main() {
+    vec3 d = normalize(f_direction);
+    // Gradient up to the zenith, with a thin band blending into the ground
+    vec3 sky = mix(u_horizon, u_zenith, sqrt(max(d.y, 0.)));
+    vec3 color = mix(u_ground, sky, smoothstep(-.02, .02, d.y));
+
+    if (u_hasSun) {
+        float sunHeight = u_sunDirection.y;
+        // Dimmer sky at dusk, dark once the sun is well below the horizon
+        color *= mix(.05, 1., smoothstep(-.2, .3, sunHeight));
+        float cosAngle = dot(d, u_sunDirection);
+        // Warm glow around a low sun, spread along the horizon
+        float low = 1. - smoothstep(0., .4, abs(sunHeight));
+        float horizonBand = exp(-abs(d.y) * 6.);
+        color += u_sunset * low * (pow(max(cosAngle, 0.), 8.) * .8 + horizonBand * max(cosAngle, 0.) * .4);
+        // Halo, then the disc with a soft edge, hidden by the ground
+        color += u_sunColor * pow(max(cosAngle, 0.), 256.) * .5;
+        float disc = smoothstep(u_sunCos, mix(u_sunCos, 1., .2), cosAngle) * smoothstep(-.01, .01, d.y);
+        color += u_sunColor * disc * u_sunIntensity;
+    }
+    o_color = vec4(color * u_intensity, 1.);
+}
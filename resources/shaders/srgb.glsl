@@ -0,0 +1,5 @@
+// Decodes sRGB display values (picked colors, vertex colors) to the linear values lighting,
+// blending and the post stack work with
+vec3 srgbToLinear(vec3 c) {
+    return mix(c / 12.92, pow((c + .055) / 1.055, vec3(2.4)), step(.04045, c));
+}
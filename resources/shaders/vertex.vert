@@ -1,6 +1,7 @@
 #version 330 core
 
 layout (location = 0) in vec3 i_pos;
+layout (location = 1) in vec3 i_normal;
 
 uniform mat4 u_pvm;
 
@@ -10,5 +11,6 @@ out vec3 f_normal;
 void main() {
     gl_Position = u_pvm * vec4(i_pos, 1.);
     f_pos = i_pos.xy;
-    f_normal = normalize(i_pos);
+    // Object space, so the colors stay put while the model moves
+    f_normal = normalize(i_normal);
 }
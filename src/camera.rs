@@ -97,6 +97,15 @@ impl Camera {
 		self.updateVectors();
 	}
 	
+	/// Moves the camera to `pos` looking along `front`, keeping `worldUp` (so roll is lost).
+	pub fn setPose(&mut self, pos: Point3, front: Vector3) {
+		let front = front.normalize();
+		self.pos = pos;
+		self.pitch = front.y.clamp(-1.0, 1.0).asin().to_degrees().clamp(-89.0, 89.0);
+		self.yaw = front.z.atan2(front.x).to_degrees();
+		self.updateVectors();
+	}
+	
 	pub fn processMouseScroll(&mut self, yo: f32) {
 		self.zoom = (self.zoom - yo).clamp(1.0, 45.0);
 	}
@@ -0,0 +1,147 @@
+#![allow(non_snake_case)]
+#![cfg_attr(not(feature = "debug-draw"), allow(unused_variables))]
+
+//! Global immediate-mode debug drawing, callable from any thread without a `LineRenderer`.
+//!
+//! Calls are queued and replayed into the renderer by `drain`, once per frame before
+//! `drawFlush`. Without the `debug-draw` cargo feature (on by default) every call is an
+//! empty function, so release builds can use `--no-default-features` to strip them.
+
+use crate::line_renderer::LineSink;
+use crate::line_renderer::shapes::DebugShapes;
+use cgmath::{InnerSpace, SquareMatrix};
+
+type Vector3 = cgmath::Vector3<f32>;
+type Matrix4 = cgmath::Matrix4<f32>;
+
+#[cfg_attr(not(feature = "debug-draw"), allow(dead_code))]
+const SPHERE_SEGMENTS: u32 = 24;
+
+#[allow(dead_code)]
+enum Command {
+    Line(Vector3, Vector3, Vector3),
+    Aabb(Vector3, Vector3, Vector3),
+    Box(Matrix4, Vector3, Vector3),
+    Sphere(Vector3, f32, Vector3),
+    Arrow(Vector3, Vector3, Vector3),
+}
+
+#[cfg(feature = "debug-draw")]
+static QUEUE: std::sync::Mutex<Vec<Command>> = std::sync::Mutex::new(Vec::new());
+
+#[inline(always)]
+fn submit(command: Command) {
+    #[cfg(feature = "debug-draw")]
+    QUEUE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).push(command);
+}
+
+#[allow(dead_code)]
+#[inline(always)]
+pub fn line(a: Vector3, b: Vector3, color: Vector3) {
+    submit(Command::Line(a, b, color));
+}
+
+/// Axis-aligned box between `min` and `max` (`box` itself is a keyword).
+#[allow(dead_code)]
+#[inline(always)]
+pub fn aabb(min: Vector3, max: Vector3, color: Vector3) {
+    submit(Command::Aabb(min, max, color));
+}
+
+/// Box of `halfExtents` around the origin of `transform`.
+#[allow(dead_code)]
+#[inline(always)]
+pub fn orientedBox(transform: &Matrix4, halfExtents: Vector3, color: Vector3) {
+    submit(Command::Box(*transform, halfExtents, color));
+}
+
+#[inline(always)]
+pub fn sphere(center: Vector3, radius: f32, color: Vector3) {
+    submit(Command::Sphere(center, radius, color));
+}
+
+#[allow(dead_code)]
+#[inline(always)]
+pub fn arrow(from: Vector3, to: Vector3, color: Vector3) {
+    submit(Command::Arrow(from, to, color));
+}
+
+#[cfg_attr(not(feature = "debug-draw"), allow(dead_code))]
+fn replay(commands: &[Command], sink: &mut impl LineSink) {
+    let identity = Matrix4::identity();
+    for command in commands {
+        match *command {
+            Command::Line(a, b, color) => sink.pushLine(a, color, b, color),
+            Command::Aabb(min, max, color) => sink.pushAabb(&identity, min, max, color),
+            Command::Box(transform, halfExtents, color) => sink.pushBox(&transform, halfExtents, color),
+            Command::Sphere(center, radius, color) => {
+                sink.pushSphere(&Matrix4::from_translation(center), radius, SPHERE_SEGMENTS, color)
+            }
+            Command::Arrow(from, to, color) => {
+                let headSize = (to - from).magnitude() * 0.1;
+                sink.pushArrow(&identity, from, to, headSize.min(0.25), color)
+            }
+        }
+    }
+}
+
+/// Moves everything queued so far into `sink`, usually the `LineRenderer` right before
+/// `drawFlush`.
+pub fn drain(sink: &mut impl LineSink) {
+    #[cfg(feature = "debug-draw")]
+    {
+        // Swap the queue out so other threads aren't blocked while replaying
+        let commands = std::mem::take(&mut *QUEUE.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+        replay(&commands, sink);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_renderer::LineBuffer;
+    use cgmath::vec3;
+
+    const WHITE: Vector3 = Vector3::new(1.0, 1.0, 1.0);
+
+    #[test]
+    fn replayMatchesDirectCalls() {
+        let commands = [
+            Command::Line(vec3(0.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0), WHITE),
+            Command::Aabb(vec3(-1.0, -1.0, -1.0), vec3(1.0, 1.0, 1.0), WHITE),
+        ];
+        let mut replayed = LineBuffer::new();
+        replay(&commands, &mut replayed);
+
+        let mut direct = LineBuffer::new();
+        direct.pushLine(vec3(0.0, 0.0, 0.0), WHITE, vec3(1.0, 0.0, 0.0), WHITE);
+        direct.pushAabb(&Matrix4::identity(), vec3(-1.0, -1.0, -1.0), vec3(1.0, 1.0, 1.0), WHITE);
+        assert_eq!(replayed.floats(), direct.floats());
+        assert_eq!(replayed.segmentCount(), 13);
+    }
+
+    // The only test touching the global queue, others would race with it
+    #[cfg(feature = "debug-draw")]
+    #[test]
+    fn drainCollectsLinesFromWorkerThreads() {
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                std::thread::spawn(move || {
+                    for i in 0..25 {
+                        line(vec3(t as f32, i as f32, 0.0), vec3(t as f32, i as f32, 1.0), WHITE);
+                    }
+                })
+            })
+            .collect();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        let mut lines = LineBuffer::new();
+        drain(&mut lines);
+        assert_eq!(lines.lineCount(), 100);
+
+        let mut again = LineBuffer::new();
+        drain(&mut again);
+        assert!(again.isEmpty());
+    }
+}
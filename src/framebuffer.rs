@@ -0,0 +1,245 @@
+#![allow(non_snake_case)]
+
+use crate::texture::{SamplerState, Texture};
+use gl::types::{GLenum, GLint, GLsizei, GLuint};
+
+/// The most MSAA samples render targets support.
+pub fn maxSamples() -> u32 {
+    let mut samples: GLint = 1;
+    unsafe {
+        gl::GetIntegerv(gl::MAX_SAMPLES, &mut samples);
+    }
+    samples.max(1) as u32
+}
+
+/// Offscreen render target with texture attachments, or none at all to render into other
+/// textures' layers and mip levels with `attachLayer`.
+pub struct Framebuffer {
+    id: GLuint,
+    width: u32,
+    height: u32,
+    colors: Vec<Texture>,
+    depth: Option<Texture>,
+    samples: u32,
+}
+
+#[allow(dead_code)]
+impl Framebuffer {
+    /// One color texture per entry of `colorFormats`, drawn to in order, and an optional
+    /// depth texture.
+    pub fn new(width: u32, height: u32, colorFormats: &[GLenum], depthFormat: Option<GLenum>) -> Framebuffer {
+        Framebuffer::multisampled(width, height, 1, colorFormats, depthFormat)
+    }
+
+    /// Like `new` with `samples` per pixel, more than 1 needs `resolve` before the colors can
+    /// be sampled.
+    pub fn multisampled(width: u32, height: u32, samples: u32, colorFormats: &[GLenum], depthFormat: Option<GLenum>) -> Framebuffer {
+        let mut framebuffer = Framebuffer::empty();
+        framebuffer.width = width;
+        framebuffer.height = height;
+        framebuffer.samples = samples.max(1);
+        let attachment = |format| {
+            if samples > 1 {
+                Texture::storageMultisample(width, height, samples, format)
+            } else {
+                let texture = Texture::storage(gl::TEXTURE_2D, width, height, 1, 1, format);
+                texture.setSampler(&SamplerState::clamped());
+                texture
+            }
+        };
+        for (i, &format) in colorFormats.iter().enumerate() {
+            let texture = attachment(format);
+            unsafe {
+                gl::NamedFramebufferTexture(framebuffer.id, gl::COLOR_ATTACHMENT0 + i as GLenum, texture.id(), 0);
+            }
+            framebuffer.colors.push(texture);
+        }
+        let drawBuffers: Vec<GLenum> = (0..colorFormats.len() as GLenum).map(|i| gl::COLOR_ATTACHMENT0 + i).collect();
+        unsafe {
+            gl::NamedFramebufferDrawBuffers(framebuffer.id, drawBuffers.len() as GLsizei, drawBuffers.as_ptr());
+        }
+        if let Some(format) = depthFormat {
+            let texture = attachment(format);
+            unsafe {
+                gl::NamedFramebufferTexture(framebuffer.id, gl::DEPTH_ATTACHMENT, texture.id(), 0);
+            }
+            framebuffer.depth = Some(texture);
+        }
+        framebuffer.checkStatus();
+        framebuffer
+    }
+
+    /// Reallocates the owned attachments at the new size, keeping their formats. Their old
+    /// contents are lost.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if (width, height) == (self.width, self.height) {
+            return;
+        }
+        let colorFormats: Vec<GLenum> = self.colors.iter().map(|texture| texture.internalFormat()).collect();
+        let depthFormat = self.depth.as_ref().map(|texture| texture.internalFormat());
+        self.destroy();
+        *self = Framebuffer::multisampled(width, height, self.samples, &colorFormats, depthFormat);
+    }
+
+    /// Averages the samples of the first color attachment into `target`'s, which must be
+    /// the same size.
+    pub fn resolve(&self, target: &Framebuffer) {
+        unsafe {
+            gl::BlitNamedFramebuffer(
+                self.id,
+                target.id,
+                0,
+                0,
+                self.width as GLint,
+                self.height as GLint,
+                0,
+                0,
+                target.width as GLint,
+                target.height as GLint,
+                gl::COLOR_BUFFER_BIT,
+                gl::NEAREST,
+            );
+        }
+    }
+
+    pub fn empty() -> Framebuffer {
+        let mut id = 0;
+        unsafe {
+            gl::CreateFramebuffers(1, &mut id);
+        }
+        Framebuffer {
+            id,
+            width: 0,
+            height: 0,
+            colors: Vec::new(),
+            depth: None,
+            samples: 1,
+        }
+    }
+
+    /// Points color attachment `index` at one `layer` (cube face, array layer) and mip `level`
+    /// of `texture`, which this framebuffer doesn't own. The viewport follows the level size.
+    pub fn attachLayer(&mut self, index: u32, texture: &Texture, level: u32, layer: u32) {
+        unsafe {
+            gl::NamedFramebufferTextureLayer(
+                self.id,
+                gl::COLOR_ATTACHMENT0 + index,
+                texture.id(),
+                level as GLint,
+                layer as GLint,
+            );
+            gl::NamedFramebufferDrawBuffer(self.id, gl::COLOR_ATTACHMENT0 + index);
+        }
+        self.width = (texture.width() >> level).max(1);
+        self.height = (texture.height() >> level).max(1);
+    }
+
+    /// Like `attachLayer`, for 2D textures.
+    pub fn attach(&mut self, index: u32, texture: &Texture, level: u32) {
+        unsafe {
+            gl::NamedFramebufferTexture(self.id, gl::COLOR_ATTACHMENT0 + index, texture.id(), level as GLint);
+            gl::NamedFramebufferDrawBuffer(self.id, gl::COLOR_ATTACHMENT0 + index);
+        }
+        self.width = (texture.width() >> level).max(1);
+        self.height = (texture.height() >> level).max(1);
+    }
+
+    /// Points the depth attachment at `layer` of the depth `texture`, with no color output,
+    /// for depth-only passes like shadow maps.
+    pub fn attachDepthLayer(&mut self, texture: &Texture, layer: u32) {
+        unsafe {
+            gl::NamedFramebufferTextureLayer(self.id, gl::DEPTH_ATTACHMENT, texture.id(), 0, layer as GLint);
+            gl::NamedFramebufferDrawBuffer(self.id, gl::NONE);
+            gl::NamedFramebufferReadBuffer(self.id, gl::NONE);
+        }
+        self.width = texture.width();
+        self.height = texture.height();
+    }
+
+    fn checkStatus(&self) {
+        let status = unsafe { gl::CheckNamedFramebufferStatus(self.id, gl::FRAMEBUFFER) };
+        if status != gl::FRAMEBUFFER_COMPLETE {
+            panic!("Framebuffer incomplete ({:#x})", status);
+        }
+    }
+
+    /// Binds for drawing and sets the viewport to cover it.
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+            gl::Viewport(0, 0, self.width as GLsizei, self.height as GLsizei);
+        }
+    }
+
+    /// Goes back to the window, `width` x `height` being its framebuffer size.
+    pub fn bindDefault(width: u32, height: u32) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::Viewport(0, 0, width as GLsizei, height as GLsizei);
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn color(&self, index: usize) -> &Texture {
+        &self.colors[index]
+    }
+
+    pub fn depth(&self) -> Option<&Texture> {
+        self.depth.as_ref()
+    }
+
+    pub fn destroy(&self) {
+        for texture in self.colors.iter().chain(self.depth.iter()) {
+            texture.destroy();
+        }
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.id);
+        }
+    }
+}
+
+/// Draws one triangle covering the screen, its vertices made up from `gl_VertexID` (see
+/// `fullscreen.vert`), for full-screen passes.
+pub struct FullscreenTriangle {
+    vao: GLuint,
+}
+
+#[allow(dead_code)]
+impl FullscreenTriangle {
+    pub fn new() -> FullscreenTriangle {
+        let mut vao = 0;
+        // Core profile draws need a vertex array, even an empty one
+        unsafe {
+            gl::CreateVertexArrays(1, &mut vao);
+        }
+        FullscreenTriangle { vao }
+    }
+
+    pub fn draw(&self) {
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        }
+    }
+
+    pub fn destroy(&self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+#![allow(non_snake_case)]
+
+//! Infinite reference grid on the XZ plane, drawn by a full-screen shader instead of lines so
+//! it costs nothing of `LineRenderer`'s capacity.
+//!
+//! `grid.frag` intersects each pixel's view ray with y = 0 and draws anti-aliased minor and
+//! major lines there. The spacing steps by 10x as the camera rises (see `gridSpacing`), the
+//! minor lines fading out before each step.
+
+use crate::framebuffer::FullscreenTriangle;
+use crate::shader::Shader;
+use crate::texture::srgbToLinear;
+use cgmath::{Matrix4, SquareMatrix};
+use gl::types::{GLenum, GLint};
+use imgui::Ui;
+
+type Vector3 = cgmath::Vector3<f32>;
+
+/// Colors are picked as sRGB and converted when drawing.
+#[derive(Clone, Debug)]
+pub struct GridSettings {
+    pub enabled: bool,
+    /// Minor spacing with the camera close to the plane, in world units.
+    pub spacing: f32,
+    pub minorColor: [f32; 3],
+    pub majorColor: [f32; 3],
+    pub axes: bool,
+    /// Line width in pixels.
+    pub lineWidth: f32,
+    /// Horizontal distance from the camera where the grid has faded out.
+    pub fadeDistance: f32,
+    pub opacity: f32,
+}
+
+impl Default for GridSettings {
+    fn default() -> GridSettings {
+        GridSettings {
+            enabled: true,
+            spacing: 0.1,
+            minorColor: [0.58, 0.58, 0.58],
+            majorColor: [0.8, 0.8, 0.8],
+            axes: true,
+            lineWidth: 1.0,
+            fadeDistance: 60.0,
+            opacity: 0.8,
+        }
+    }
+}
+
+/// Minor and major spacing for a camera `height` above the plane, and how far the minor lines
+/// have faded (0 to 1) on their way to the next step.
+pub fn gridSpacing(height: f32, spacing: f32) -> (f32, f32, f32) {
+    // One minor cell per 10 units of height covers the screen with a readable density
+    let level = (height.abs() / (spacing * 10.0)).max(1.0).log10();
+    let minor = spacing * 10f32.powf(level.floor());
+    (minor, minor * 10.0, level.fract())
+}
+
+pub struct Grid {
+    pub settings: GridSettings,
+    shader: Shader,
+    triangle: FullscreenTriangle,
+}
+
+#[allow(dead_code)]
+impl Grid {
+    pub fn new() -> Grid {
+        Grid {
+            settings: GridSettings::default(),
+            shader: Shader::new("resources/shaders/grid.vert", "resources/shaders/grid.frag"),
+            triangle: FullscreenTriangle::new(),
+        }
+    }
+
+    /// Blends the grid over what's drawn, depth tested against it. Draw after the opaque meshes.
+    pub fn draw(&self, view: &Matrix4<f32>, projection: &Matrix4<f32>, cameraPos: Vector3) {
+        let settings = &self.settings;
+        if !settings.enabled {
+            return;
+        }
+        let viewProjection = projection * view;
+        let (minor, major, fade) = gridSpacing(cameraPos.y, settings.spacing);
+        let shader = &self.shader;
+        shader.bind();
+        shader.setMatrix4f("u_viewProjection", &viewProjection);
+        shader.setMatrix4f("u_inverseViewProjection", &viewProjection.invert().unwrap_or_else(Matrix4::identity));
+        shader.setUniform3fv("u_cameraPos", &cameraPos);
+        shader.setUniform3f("u_spacing", minor, major, fade);
+        shader.setUniform3fv("u_minorColor", &settings.minorColor.map(srgbToLinear).into());
+        shader.setUniform3fv("u_majorColor", &settings.majorColor.map(srgbToLinear).into());
+        shader.setUniform1i("u_axes", settings.axes as i32);
+        shader.setUniform1f("u_lineWidth", settings.lineWidth);
+        shader.setUniform1f("u_fadeDistance", settings.fadeDistance);
+        shader.setUniform1f("u_opacity", settings.opacity);
+
+        let mut polygonMode: [GLint; 2] = [0; 2];
+        unsafe {
+            gl::GetIntegerv(gl::POLYGON_MODE, polygonMode.as_mut_ptr());
+            gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+            let blend = gl::IsEnabled(gl::BLEND) == gl::TRUE;
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            // Transparent, so it mustn't hide lines drawn after it
+            gl::DepthMask(gl::FALSE);
+            self.triangle.draw();
+            gl::DepthMask(gl::TRUE);
+            if !blend {
+                gl::Disable(gl::BLEND);
+            }
+            gl::PolygonMode(gl::FRONT_AND_BACK, polygonMode[0] as GLenum);
+        }
+    }
+
+    pub fn destroy(&self) {
+        self.shader.delete();
+        self.triangle.destroy();
+    }
+}
+
+pub fn editGrid(ui: &Ui, settings: &mut GridSettings) {
+    ui.checkbox("Grid", &mut settings.enabled);
+    if !settings.enabled {
+        return;
+    }
+    ui.checkbox("Axes", &mut settings.axes);
+    imgui::Drag::new("Spacing").speed(0.01).range(0.01, 10.0).build(ui, &mut settings.spacing);
+    ui.slider("Line width", 0.5, 4.0, &mut settings.lineWidth);
+    ui.slider("Fade distance", 5.0, 500.0, &mut settings.fadeDistance);
+    ui.slider("Opacity", 0.0, 1.0, &mut settings.opacity);
+    ui.color_edit3("Minor", &mut settings.minorColor);
+    ui.color_edit3("Major", &mut settings.majorColor);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4 * b.max(1.0)
+    }
+
+    #[test]
+    fn spacingStepsWithHeight() {
+        let (minor, major, fade) = gridSpacing(0.5, 0.1);
+        assert!(close(minor, 0.1) && close(major, 1.0) && close(fade, 0.0));
+        // Below or above the plane alike
+        let (minor, major, _) = gridSpacing(-20.0, 0.1);
+        assert!(close(minor, 1.0) && close(major, 10.0), "{} {}", minor, major);
+        let (minor, _, _) = gridSpacing(250.0, 0.1);
+        assert!(close(minor, 10.0), "{}", minor);
+    }
+
+    #[test]
+    fn minorLinesFadeTowardsTheNextStep() {
+        let (_, _, low) = gridSpacing(2.0, 0.1);
+        let (_, _, high) = gridSpacing(9.0, 0.1);
+        assert!(0.0 < low && low < high && high < 1.0, "{} {}", low, high);
+        // Same spacing until the step
+        assert_eq!(gridSpacing(2.0, 0.1).0, gridSpacing(9.0, 0.1).0);
+    }
+}
@@ -0,0 +1,403 @@
+#![allow(non_snake_case)]
+
+//! Instanced drawing: many copies of a mesh from one draw call, each with its own transform
+//! and color read from a per-instance vertex buffer.
+//!
+//! `InstanceBuffer` feeds one mesh through `Mesh::drawInstanced`. `MeshPool` packs several
+//! meshes into shared buffers so a whole mixed batch goes out as one
+//! `glMultiDrawElementsIndirect`, one command per mesh.
+
+use crate::mesh::{Attribute, MeshData, VertexLayout};
+use crate::shader::Shader;
+use cgmath::{Matrix4, Rad, vec3};
+use gl::types::{GLint, GLsizei, GLsizeiptr, GLuint};
+use imgui::Ui;
+use std::os::raw::c_void;
+
+type Vector3 = cgmath::Vector3<f32>;
+
+/// First shader location of the instance attributes, after the `mesh::Attribute` ones: the
+/// model matrix takes four (one per column), the color the next.
+pub const INSTANCE_LOCATION: GLuint = 5;
+/// Vertex buffer binding index of the instance buffer, the mesh's vertices being on 0.
+const INSTANCE_BINDING: GLuint = 1;
+
+/// Per-instance attributes as laid out in the buffer, see `instanced.vert`.
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Instance {
+    pub model: [[f32; 4]; 4],
+    /// Linear RGBA.
+    pub color: [f32; 4],
+}
+
+impl Instance {
+    pub fn new(model: Matrix4<f32>, color: Vector3) -> Instance {
+        Instance {
+            model: model.into(),
+            color: [color.x, color.y, color.z, 1.0],
+        }
+    }
+}
+
+/// Points locations `INSTANCE_LOCATION..` of `vao` at `buffer`, advancing once per instance.
+fn attachInstances(vao: GLuint, buffer: GLuint) {
+    let vec4Size = 4 * size_of::<f32>();
+    unsafe {
+        gl::VertexArrayVertexBuffer(vao, INSTANCE_BINDING, buffer, 0, size_of::<Instance>() as GLsizei);
+        gl::VertexArrayBindingDivisor(vao, INSTANCE_BINDING, 1);
+        for i in 0..5 {
+            let location = INSTANCE_LOCATION + i;
+            gl::VertexArrayAttribFormat(vao, location, 4, gl::FLOAT, gl::FALSE, i * vec4Size as GLuint);
+            gl::VertexArrayAttribBinding(vao, location, INSTANCE_BINDING);
+            gl::EnableVertexArrayAttrib(vao, location);
+        }
+    }
+}
+
+/// Growable GPU copy of an `Instance` list.
+pub struct InstanceBuffer {
+    buffer: GLuint,
+    allocated: usize,
+    count: usize,
+}
+
+#[allow(dead_code)]
+impl InstanceBuffer {
+    pub fn new() -> InstanceBuffer {
+        let mut buffer = 0;
+        unsafe {
+            gl::CreateBuffers(1, &mut buffer);
+        }
+        InstanceBuffer {
+            buffer,
+            allocated: 0,
+            count: 0,
+        }
+    }
+
+    pub fn upload(&mut self, instances: &[Instance]) {
+        self.count = instances.len();
+        let bytes = size_of_val(instances);
+        unsafe {
+            if bytes > self.allocated {
+                self.allocated = bytes.max(self.allocated * 2);
+                gl::NamedBufferData(self.buffer, self.allocated as GLsizeiptr, std::ptr::null(), gl::DYNAMIC_DRAW);
+            }
+            gl::NamedBufferSubData(self.buffer, 0, bytes as GLsizeiptr, instances.as_ptr() as *const c_void);
+        }
+    }
+
+    /// Instances in the last upload.
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Makes `vao` read its instance attributes from this buffer.
+    pub fn attach(&self, vao: GLuint) {
+        attachInstances(vao, self.buffer);
+    }
+
+    pub fn destroy(&self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.buffer);
+        }
+    }
+}
+
+/// Where one mesh lives in a `MeshPool`'s shared buffers.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct MeshRange {
+    pub firstIndex: u32,
+    pub indexCount: u32,
+    pub baseVertex: i32,
+}
+
+/// `DrawElementsIndirectCommand` as the GL reads it from the indirect buffer.
+#[repr(C)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct DrawCommand {
+    pub count: u32,
+    pub instanceCount: u32,
+    pub firstIndex: u32,
+    pub baseVertex: i32,
+    pub baseInstance: u32,
+}
+
+/// Concatenates `meshes`, which must share a layout. Indices stay relative to each mesh's
+/// first vertex (its `baseVertex`), meshes without indices get sequential ones.
+pub fn mergeMeshes(meshes: &[MeshData]) -> (MeshData, Vec<MeshRange>) {
+    let layout = meshes.first().map_or_else(|| VertexLayout::new(&[Attribute::Position]), |mesh| mesh.layout.clone());
+    let mut merged = MeshData::new(layout);
+    let mut ranges = Vec::with_capacity(meshes.len());
+    for mesh in meshes {
+        assert_eq!(mesh.layout, merged.layout, "Pooled meshes need the same vertex layout");
+        mesh.validate();
+        let range = MeshRange {
+            firstIndex: merged.indices.len() as u32,
+            indexCount: 0,
+            baseVertex: merged.vertexCount() as i32,
+        };
+        if mesh.indices.is_empty() {
+            merged.indices.extend(0..mesh.vertexCount() as u32);
+        } else {
+            merged.indices.extend_from_slice(&mesh.indices);
+        }
+        merged.vertices.extend_from_slice(&mesh.vertices);
+        ranges.push(MeshRange {
+            indexCount: merged.indices.len() as u32 - range.firstIndex,
+            ..range
+        });
+    }
+    (merged, ranges)
+}
+
+/// Concatenates `batches`, the instances of each mesh in `ranges` order, and makes one draw
+/// command per non-empty batch starting at its first instance.
+pub fn packBatches(ranges: &[MeshRange], batches: &[&[Instance]]) -> (Vec<Instance>, Vec<DrawCommand>) {
+    let mut instances = Vec::with_capacity(batches.iter().map(|batch| batch.len()).sum());
+    let mut commands = Vec::new();
+    for (range, batch) in ranges.iter().zip(batches) {
+        if batch.is_empty() {
+            continue;
+        }
+        commands.push(DrawCommand {
+            count: range.indexCount,
+            instanceCount: batch.len() as u32,
+            firstIndex: range.firstIndex,
+            baseVertex: range.baseVertex,
+            baseInstance: instances.len() as u32,
+        });
+        instances.extend_from_slice(batch);
+    }
+    (instances, commands)
+}
+
+/// Several meshes in one vertex and index buffer, drawn together with multi-draw-indirect.
+pub struct MeshPool {
+    vao: GLuint,
+    vbo: GLuint,
+    ebo: GLuint,
+    indirect: GLuint,
+    indirectAllocated: usize,
+    ranges: Vec<MeshRange>,
+    instances: InstanceBuffer,
+}
+
+#[allow(dead_code)]
+impl MeshPool {
+    pub fn new(meshes: &[MeshData]) -> MeshPool {
+        let (data, ranges) = mergeMeshes(meshes);
+        assert!(!data.vertices.is_empty(), "Mesh pool has no vertices");
+        let mut pool = MeshPool {
+            vao: 0,
+            vbo: 0,
+            ebo: 0,
+            indirect: 0,
+            indirectAllocated: 0,
+            ranges,
+            instances: InstanceBuffer::new(),
+        };
+        unsafe {
+            gl::CreateVertexArrays(1, &mut pool.vao);
+            gl::CreateBuffers(1, &mut pool.vbo);
+            gl::NamedBufferStorage(
+                pool.vbo,
+                size_of_val(data.vertices.as_slice()) as GLsizeiptr,
+                data.vertices.as_ptr() as *const c_void,
+                0,
+            );
+            gl::VertexArrayVertexBuffer(pool.vao, 0, pool.vbo, 0, data.layout.stride() as GLsizei);
+            for &attribute in data.layout.attributes() {
+                let location = attribute.location();
+                let offset = data.layout.offset(attribute).unwrap() * size_of::<f32>();
+                gl::VertexArrayAttribFormat(pool.vao, location, attribute.components() as GLint, gl::FLOAT, gl::FALSE, offset as GLuint);
+                gl::VertexArrayAttribBinding(pool.vao, location, 0);
+                gl::EnableVertexArrayAttrib(pool.vao, location);
+            }
+            // Always 32-bit, base vertices keep the indices small but the commands share a type
+            gl::CreateBuffers(1, &mut pool.ebo);
+            gl::NamedBufferStorage(
+                pool.ebo,
+                size_of_val(data.indices.as_slice()) as GLsizeiptr,
+                data.indices.as_ptr() as *const c_void,
+                0,
+            );
+            gl::VertexArrayElementBuffer(pool.vao, pool.ebo);
+            gl::CreateBuffers(1, &mut pool.indirect);
+        }
+        pool.instances.attach(pool.vao);
+        pool
+    }
+
+    pub fn meshCount(&self) -> usize {
+        self.ranges.len()
+    }
+
+    /// Draws `batches[i]` instances of mesh `i` in one call. Returns the number of commands.
+    pub fn draw(&mut self, batches: &[&[Instance]]) -> usize {
+        let (instances, commands) = packBatches(&self.ranges, batches);
+        if commands.is_empty() {
+            return 0;
+        }
+        self.instances.upload(&instances);
+        let bytes = size_of_val(commands.as_slice());
+        unsafe {
+            if bytes > self.indirectAllocated {
+                self.indirectAllocated = bytes.max(self.indirectAllocated * 2);
+                gl::NamedBufferData(self.indirect, self.indirectAllocated as GLsizeiptr, std::ptr::null(), gl::DYNAMIC_DRAW);
+            }
+            gl::NamedBufferSubData(self.indirect, 0, bytes as GLsizeiptr, commands.as_ptr() as *const c_void);
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, self.indirect);
+            gl::MultiDrawElementsIndirect(gl::TRIANGLES, gl::UNSIGNED_INT, std::ptr::null(), commands.len() as GLsizei, 0);
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, 0);
+        }
+        commands.len()
+    }
+
+    pub fn destroy(&self) {
+        self.instances.destroy();
+        unsafe {
+            gl::DeleteBuffers(1, &self.indirect);
+            gl::DeleteBuffers(1, &self.ebo);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum StressMode {
+    Off,
+    /// One mesh, `glDrawElementsInstanced`.
+    Instanced,
+    /// Every pooled mesh, `glMultiDrawElementsIndirect`.
+    Indirect,
+}
+
+#[derive(Clone, Debug)]
+pub struct StressSettings {
+    pub mode: StressMode,
+    pub count: i32,
+    pub spacing: f32,
+    pub spin: bool,
+}
+
+impl Default for StressSettings {
+    fn default() -> StressSettings {
+        StressSettings {
+            mode: StressMode::Off,
+            count: 10_000,
+            spacing: 1.5,
+            spin: true,
+        }
+    }
+}
+
+/// Fully saturated color for a hue in [0, 1).
+fn hue(h: f32) -> Vector3 {
+    let channel = |offset: f32| (((h + offset).fract() * 6.0 - 3.0).abs() - 1.0).clamp(0.0, 1.0);
+    vec3(channel(0.0), channel(2.0 / 3.0), channel(1.0 / 3.0))
+}
+
+/// `count` instances on a square of the XZ plane centered on the origin, lifted to y = 1, each
+/// turning at its own rate when `time` advances. Instance `i` is colored by its angle around
+/// the center.
+pub fn stressInstances(count: usize, spacing: f32, time: f32) -> Vec<Instance> {
+    let side = (count as f32).sqrt().ceil().max(1.0) as usize;
+    let half = (side - 1) as f32 * 0.5;
+    (0..count)
+        .map(|i| {
+            let (x, z) = ((i % side) as f32 - half, (i / side) as f32 - half);
+            let position = vec3(x * spacing, 1.0, z * spacing);
+            let angle = Rad(time * (0.5 + (i % 7) as f32 * 0.25));
+            let model = Matrix4::from_translation(position) * Matrix4::from_angle_y(angle) * Matrix4::from_scale(0.5);
+            let color = hue(z.atan2(x) / std::f32::consts::TAU + 0.5);
+            Instance::new(model, color)
+        })
+        .collect()
+}
+
+/// Binds `instanced.vert`/`instanced.frag`, lit by a single directional light towards `sun`.
+pub fn bindInstancedShader(shader: &Shader, viewProjection: &Matrix4<f32>, sun: Vector3, ambient: Vector3) {
+    shader.bind();
+    shader.setMatrix4f("u_viewProjection", viewProjection);
+    shader.setUniform3fv("u_sunDirection", &sun);
+    shader.setUniform3fv("u_ambientLight", &ambient);
+}
+
+pub fn editStressTest(ui: &Ui, settings: &mut StressSettings, meshCount: usize) {
+    let modes = [StressMode::Off, StressMode::Instanced, StressMode::Indirect];
+    let mut item = settings.mode as usize;
+    if ui.combo_simple_string("Stress test", &mut item, &["Off", "Instanced", "Multi-draw indirect"]) {
+        settings.mode = modes[item];
+    }
+    if settings.mode == StressMode::Off {
+        return;
+    }
+    ui.slider("Instances", 1, 200_000, &mut settings.count);
+    ui.slider("Spacing", 0.5, 4.0, &mut settings.spacing);
+    ui.checkbox("Spin", &mut settings.spin);
+    if settings.mode == StressMode::Indirect {
+        ui.text(format!("{} meshes, one draw call", meshCount));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::generators;
+
+    #[test]
+    fn instanceLayoutMatchesShader() {
+        // Four model columns and a color, one vec4 each
+        assert_eq!(size_of::<Instance>(), 5 * 16);
+        assert_eq!(size_of::<DrawCommand>(), 20);
+        let instance = Instance::new(Matrix4::from_translation(vec3(1.0, 2.0, 3.0)), vec3(0.5, 0.25, 1.0));
+        assert_eq!(instance.model[3], [1.0, 2.0, 3.0, 1.0]);
+        assert_eq!(instance.color, [0.5, 0.25, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn mergedMeshesKeepTheirRanges() {
+        let cube = generators::cube(1.0);
+        let sphere = generators::uvSphere(0.5, 8, 4);
+        let (merged, ranges) = mergeMeshes(&[cube.clone(), sphere.clone()]);
+        merged.validate();
+        assert_eq!(merged.vertexCount(), cube.vertexCount() + sphere.vertexCount());
+        assert_eq!(ranges[0], MeshRange { firstIndex: 0, indexCount: cube.indices.len() as u32, baseVertex: 0 });
+        assert_eq!(ranges[1].firstIndex, cube.indices.len() as u32);
+        assert_eq!(ranges[1].baseVertex, cube.vertexCount() as i32);
+        // Indices stay local, base vertex does the offset
+        let first = ranges[1].firstIndex as usize;
+        assert_eq!(&merged.indices[first..], sphere.indices.as_slice());
+    }
+
+    #[test]
+    fn batchesBecomeOneCommandEach() {
+        let ranges = [
+            MeshRange { firstIndex: 0, indexCount: 36, baseVertex: 0 },
+            MeshRange { firstIndex: 36, indexCount: 60, baseVertex: 24 },
+            MeshRange { firstIndex: 96, indexCount: 12, baseVertex: 40 },
+        ];
+        let white = vec3(1.0, 1.0, 1.0);
+        let a = vec![Instance::new(Matrix4::from_scale(1.0), white); 3];
+        let c = vec![Instance::new(Matrix4::from_scale(2.0), white); 2];
+        let (instances, commands) = packBatches(&ranges, &[&a, &[], &c]);
+        assert_eq!(instances.len(), 5);
+        assert_eq!(commands.len(), 2);
+        assert_eq!(commands[0], DrawCommand { count: 36, instanceCount: 3, firstIndex: 0, baseVertex: 0, baseInstance: 0 });
+        assert_eq!(commands[1], DrawCommand { count: 12, instanceCount: 2, firstIndex: 96, baseVertex: 40, baseInstance: 3 });
+        assert_eq!(instances[3], c[0]);
+    }
+
+    #[test]
+    fn stressGridIsCentered() {
+        let instances = stressInstances(9, 2.0, 0.0);
+        assert_eq!(instances.len(), 9);
+        assert_eq!(instances[0].model[3], [-2.0, 1.0, -2.0, 1.0]);
+        assert_eq!(instances[4].model[3], [0.0, 1.0, 0.0, 1.0]);
+        assert_eq!(instances[8].model[3], [2.0, 1.0, 2.0, 1.0]);
+    }
+}
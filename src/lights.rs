@@ -0,0 +1,330 @@
+#![allow(non_snake_case)]
+
+//! Directional, point and spot lights for the lit mesh shader. The enabled lights are packed
+//! into a shader storage buffer (`Lights` at binding `LIGHTS_BINDING` in `lit.frag`).
+
+use crate::line_renderer::shapes::DebugShapes;
+use crate::shadows::shadowLayers;
+use crate::texture::srgbToLinear;
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Quaternion, Transform, vec3};
+use gl::types::{GLsizeiptr, GLuint};
+use imgui::Ui;
+use std::os::raw::c_void;
+
+type Vector3 = cgmath::Vector3<f32>;
+
+pub const LIGHTS_BINDING: GLuint = 0;
+/// One std430 `Light`: four vec4s.
+pub const LIGHT_FLOATS: usize = 16;
+/// The count, padded to the vec4 alignment of the array after it.
+const HEADER_FLOATS: usize = 4;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum LightKind {
+    /// Parallel rays, like the sun. Only `direction` matters.
+    Directional = 0,
+    Point = 1,
+    Spot = 2,
+}
+
+impl LightKind {
+    pub const ALL: [LightKind; 3] = [LightKind::Directional, LightKind::Point, LightKind::Spot];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LightKind::Directional => "Directional",
+            LightKind::Point => "Point",
+            LightKind::Spot => "Spot",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Light {
+    pub kind: LightKind,
+    pub enabled: bool,
+    /// Picked as sRGB and decoded to linear when packed, scaled by `intensity`.
+    pub color: Vector3,
+    pub intensity: f32,
+    /// Unused for directional lights, except to place their gizmo.
+    pub position: Vector3,
+    /// The direction light travels in.
+    pub direction: Vector3,
+    /// Distance at which point and spot lights have faded out completely.
+    pub range: f32,
+    /// Spot cone half-angles in degrees: full intensity inside `innerAngle`, none outside
+    /// `outerAngle`.
+    pub innerAngle: f32,
+    pub outerAngle: f32,
+    /// Only directional and spot lights have shadow maps, see `shadows`.
+    pub castsShadows: bool,
+}
+
+#[allow(dead_code)]
+impl Light {
+    pub fn directional(direction: Vector3, color: Vector3, intensity: f32) -> Light {
+        Light {
+            kind: LightKind::Directional,
+            enabled: true,
+            color,
+            intensity,
+            position: vec3(0.0, 2.0, 0.0),
+            direction: direction.normalize(),
+            range: 10.0,
+            innerAngle: 20.0,
+            outerAngle: 30.0,
+            castsShadows: true,
+        }
+    }
+
+    pub fn point(position: Vector3, color: Vector3, intensity: f32, range: f32) -> Light {
+        Light {
+            kind: LightKind::Point,
+            position,
+            range,
+            ..Light::directional(vec3(0.0, -1.0, 0.0), color, intensity)
+        }
+    }
+
+    pub fn spot(position: Vector3, direction: Vector3, color: Vector3, intensity: f32, range: f32, innerAngle: f32, outerAngle: f32) -> Light {
+        Light {
+            kind: LightKind::Spot,
+            position,
+            direction: direction.normalize(),
+            range,
+            innerAngle,
+            outerAngle,
+            ..Light::directional(direction, color, intensity)
+        }
+    }
+
+    /// This light with its position and direction taken from local to world space by `world`,
+    /// for lights attached to scene graph nodes.
+    pub fn transformed(&self, world: &Matrix4<f32>) -> Light {
+        let direction = world.transform_vector(self.direction);
+        Light {
+            position: world.transform_point(Point3::from_vec(self.position)).to_vec(),
+            direction: if direction.magnitude2() > 1e-12 { direction.normalize() } else { self.direction },
+            ..*self
+        }
+    }
+
+    /// The std430 layout of `Light` in `lit.frag`, with the first layer of its shadow map.
+    fn pack(&self, shadowLayer: Option<u32>) -> [f32; LIGHT_FLOATS] {
+        let p = self.position;
+        let d = self.direction.normalize();
+        let c = self.color.map(srgbToLinear);
+        // Cosines, so the shader compares them against a dot product. `smoothstep` needs the
+        // inner one strictly above the outer one.
+        let outer = self.outerAngle.to_radians().cos();
+        let inner = self.innerAngle.to_radians().cos().max(outer + 1e-4);
+        [
+            p.x, p.y, p.z, self.kind as u32 as f32,
+            d.x, d.y, d.z, self.range,
+            c.x, c.y, c.z, self.intensity,
+            inner, outer, shadowLayer.map_or(-1.0, |layer| layer as f32), 0.0,
+        ]
+    }
+}
+
+/// The contents of the `Lights` storage block: the enabled light count, then the lights.
+pub fn packLights(lights: &[Light]) -> Vec<f32> {
+    let enabled: Vec<(&Light, Option<u32>)> =
+        lights.iter().zip(shadowLayers(lights)).filter(|(light, _)| light.enabled).collect();
+    let mut data = vec![0.0; HEADER_FLOATS];
+    data[0] = f32::from_bits(enabled.len() as u32);
+    for (light, layer) in enabled {
+        data.extend_from_slice(&light.pack(layer));
+    }
+    data
+}
+
+/// The storage buffer holding the light list.
+pub struct LightBuffer {
+    buffer: GLuint,
+    allocated: usize,
+}
+
+#[allow(dead_code)]
+impl LightBuffer {
+    pub fn new() -> LightBuffer {
+        let mut buffer = 0;
+        unsafe {
+            gl::CreateBuffers(1, &mut buffer);
+        }
+        LightBuffer { buffer, allocated: 0 }
+    }
+
+    /// Uploads the enabled `lights` and binds them to `LIGHTS_BINDING`.
+    pub fn upload(&mut self, lights: &[Light]) {
+        let data = packLights(lights);
+        let bytes = size_of_val(data.as_slice());
+        unsafe {
+            if bytes > self.allocated {
+                self.allocated = bytes.max(self.allocated * 2);
+                gl::NamedBufferData(self.buffer, self.allocated as GLsizeiptr, std::ptr::null(), gl::DYNAMIC_DRAW);
+            }
+            gl::NamedBufferSubData(self.buffer, 0, bytes as GLsizeiptr, data.as_ptr() as *const c_void);
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, LIGHTS_BINDING, self.buffer);
+        }
+    }
+
+    pub fn destroy(&self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.buffer);
+        }
+    }
+}
+
+/// Wireframe of a light in its own color: an arrow for directional lights, the range sphere
+/// for point lights and the outer cone for spot lights.
+pub fn pushLightGizmo(lines: &mut impl DebugShapes, light: &Light) {
+    let identity = Matrix4::from_scale(1.0);
+    let color = light.color;
+    let position = light.position;
+    let direction = light.direction.normalize();
+    match light.kind {
+        LightKind::Directional => {
+            lines.pushSphere(&Matrix4::from_translation(position), 0.1, 12, color);
+            lines.pushArrow(&identity, position, position + direction, 0.15, color);
+        }
+        LightKind::Point => {
+            let transform = Matrix4::from_translation(position);
+            lines.pushSphere(&transform, 0.1, 12, color);
+            lines.pushSphere(&transform, light.range, 24, color * 0.4);
+        }
+        LightKind::Spot => {
+            // pushCone points its apex along +Y from the base, turn +Y back onto the light
+            let rotation = Quaternion::from_arc(vec3(0.0, 1.0, 0.0), -direction, None);
+            let base = position + direction * light.range;
+            let radius = light.range * light.outerAngle.to_radians().tan();
+            let transform = Matrix4::from_translation(base) * Matrix4::from(rotation);
+            lines.pushCone(&transform, radius, light.range, 24, color * 0.4);
+            lines.pushArrow(&identity, position, position + direction * 0.5, 0.15, color);
+        }
+    }
+}
+
+/// Drag widget for the three components of `value`.
+pub fn editVector(ui: &Ui, label: &str, value: &mut Vector3, speed: f32) -> bool {
+    let mut array: [f32; 3] = (*value).into();
+    let changed = imgui::Drag::new(label).speed(speed).build_array(ui, &mut array);
+    *value = array.into();
+    changed
+}
+
+/// ImGui editor for the light list, with buttons to add and remove lights. Returns the index
+/// of a light removed this frame, the lights after it having moved down by one.
+pub fn editLights(ui: &Ui, lights: &mut Vec<Light>) -> Option<usize> {
+    let mut removed = None;
+    for (i, light) in lights.iter_mut().enumerate() {
+        let _id = ui.push_id_usize(i);
+        let title = format!("{} light {}###light", light.kind.label(), i);
+        if !ui.collapsing_header(title, imgui::TreeNodeFlags::empty()) {
+            continue;
+        }
+        ui.checkbox("Enabled", &mut light.enabled);
+        let mut kindItem = light.kind as usize;
+        if ui.combo_simple_string("Kind", &mut kindItem, &LightKind::ALL.map(LightKind::label)) {
+            light.kind = LightKind::ALL[kindItem];
+        }
+        let mut color: [f32; 3] = light.color.into();
+        if ui.color_edit3("Color", &mut color) {
+            light.color = color.into();
+        }
+        ui.slider("Intensity", 0.0, 20.0, &mut light.intensity);
+        editVector(ui, "Position", &mut light.position, 0.05);
+        if light.kind != LightKind::Point && editVector(ui, "Direction", &mut light.direction, 0.01) {
+            if light.direction.magnitude2() < 1e-6 {
+                light.direction = vec3(0.0, -1.0, 0.0);
+            }
+            light.direction = light.direction.normalize();
+        }
+        if light.kind != LightKind::Directional {
+            ui.slider("Range", 0.1, 50.0, &mut light.range);
+        }
+        if light.kind == LightKind::Spot {
+            ui.slider("Outer angle", 1.0, 89.0, &mut light.outerAngle);
+            ui.slider("Inner angle", 0.0, light.outerAngle, &mut light.innerAngle);
+        }
+        if light.kind != LightKind::Point {
+            ui.checkbox("Casts shadows", &mut light.castsShadows);
+        }
+        if ui.button("Remove") {
+            removed = Some(i);
+        }
+    }
+    if let Some(i) = removed {
+        lights.remove(i);
+    }
+    for kind in LightKind::ALL {
+        if ui.button(format!("Add {}", kind.label().to_lowercase())) {
+            let white = vec3(1.0, 1.0, 1.0);
+            lights.push(match kind {
+                LightKind::Directional => Light::directional(vec3(-0.4, -1.0, -0.6), white, 1.0),
+                LightKind::Point => Light::point(vec3(0.0, 1.5, 0.0), white, 5.0, 5.0),
+                LightKind::Spot => Light::spot(vec3(0.0, 3.0, 0.0), vec3(0.0, -1.0, 0.0), white, 10.0, 8.0, 20.0, 30.0),
+            });
+        }
+        ui.same_line();
+    }
+    ui.new_line();
+    removed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_renderer::LineBuffer;
+    use crate::shadows::MAX_CASCADES;
+
+    const WHITE: Vector3 = Vector3::new(1.0, 1.0, 1.0);
+
+    #[test]
+    fn packingSkipsDisabledLights() {
+        let mut off = Light::point(vec3(1.0, 2.0, 3.0), WHITE, 1.0, 5.0);
+        off.enabled = false;
+        let spot = Light::spot(vec3(1.0, 2.0, 3.0), vec3(0.0, -2.0, 0.0), vec3(1.0, 0.5, 0.0), 4.0, 8.0, 20.0, 30.0);
+        let data = packLights(&[off, spot]);
+        assert_eq!(data.len(), HEADER_FLOATS + LIGHT_FLOATS);
+        assert_eq!(data[0].to_bits(), 1);
+        let light = &data[HEADER_FLOATS..];
+        assert_eq!(&light[..4], &[1.0, 2.0, 3.0, 2.0]);
+        // Direction is normalized, range follows
+        assert_eq!(&light[4..8], &[0.0, -1.0, 0.0, 8.0]);
+        // Color decoded from sRGB
+        assert_eq!(&light[8..12], &[1.0, srgbToLinear(0.5), 0.0, 4.0]);
+        assert!((light[12] - 20f32.to_radians().cos()).abs() < 1e-6);
+        assert!((light[13] - 30f32.to_radians().cos()).abs() < 1e-6);
+        // The first spot shadow layer
+        assert_eq!(light[14], MAX_CASCADES as f32);
+    }
+
+    #[test]
+    fn equalConeAnglesKeepASoftEdge() {
+        let spot = Light::spot(vec3(0.0, 0.0, 0.0), vec3(0.0, -1.0, 0.0), WHITE, 1.0, 8.0, 30.0, 30.0);
+        let light = spot.pack(None);
+        assert!(light[12] > light[13]);
+        let wider = Light::spot(vec3(0.0, 0.0, 0.0), vec3(0.0, -1.0, 0.0), WHITE, 1.0, 8.0, 40.0, 30.0);
+        assert!(wider.pack(None)[12] > wider.pack(None)[13]);
+    }
+
+    #[test]
+    fn emptyListStillHasAHeader() {
+        let data = packLights(&[]);
+        assert_eq!(data.len(), HEADER_FLOATS);
+        assert_eq!(data[0].to_bits(), 0);
+    }
+
+    #[test]
+    fn spotGizmoOpensAlongTheDirection() {
+        let light = Light::spot(vec3(0.0, 3.0, 0.0), vec3(0.0, -1.0, 0.0), WHITE, 1.0, 2.0, 20.0, 45.0);
+        let mut lines = LineBuffer::new();
+        pushLightGizmo(&mut lines, &light);
+        // The cone's base circle sits `range` below the light, with radius range * tan(45°)
+        let lowest = lines.stripVertices().chunks_exact(6).map(|v| v[1]).fold(f32::MAX, f32::min);
+        assert!((lowest - 1.0).abs() < 1e-4, "{}", lowest);
+        let widest = lines.stripVertices().chunks_exact(6).map(|v| v[0].abs()).fold(0.0, f32::max);
+        assert!((widest - 2.0).abs() < 1e-4, "{}", widest);
+    }
+}
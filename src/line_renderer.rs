@@ -1,20 +1,158 @@
 #![allow(non_snake_case)]
 
 use crate::shader::Shader;
-use gl::types::{GLsizei, GLsizeiptr, GLuint};
+use gl::types::{GLenum, GLint, GLsizei, GLsizeiptr, GLuint};
 use std::os::raw::c_void;
 
 type Vector3 = cgmath::Vector3<f32>;
 type Matrix4 = cgmath::Matrix4<f32>;
 
-pub struct LineRenderer {
-    vec: Vec<f32>,
-    shader: Shader,
+pub mod bench;
+pub mod budget;
+pub mod curves;
+pub mod export;
+pub mod labels;
+pub mod overlay;
+pub mod retained;
+pub mod shapes;
+pub mod stream;
+
+use budget::{Admit, LineBudget, LineStats};
+use export::FrameCapture;
+use labels::Labels;
+use retained::{LineHandle, RetainedLines};
+use stream::{MappedLineBuffer, StreamLines};
+
+/// Anything that accepts colored line segments, so debug helpers can target both the GPU
+/// renderer and plain CPU-side buffers.
+///
+/// Strips store each vertex once: `beginStrip`, any number of `vertex` calls, then `endStrip`
+/// (open) or `endLoop` (closed back to the first vertex).
+pub trait LineSink {
+    fn pushLine(&mut self, pos1: Vector3, color1: Vector3, pos2: Vector3, color2: Vector3);
+
+    fn beginStrip(&mut self);
+    fn vertex(&mut self, pos: Vector3, color: Vector3);
+    fn endStrip(&mut self);
+    fn endLoop(&mut self);
+}
+
+/// Growable backing memory for a `LineBuffer`: a `Vec` on the CPU, or mapped GPU memory.
+pub trait Storage<T: Copy> {
+    fn asSlice(&self) -> &[T];
+    fn extend(&mut self, values: &[T]);
+    fn truncate(&mut self, len: usize);
+
+    fn len(&self) -> usize {
+        self.asSlice().len()
+    }
+
+    fn isEmpty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn clear(&mut self) {
+        self.truncate(0);
+    }
+}
+
+impl<T: Copy> Storage<T> for Vec<T> {
+    fn asSlice(&self) -> &[T] {
+        self
+    }
+
+    fn extend(&mut self, values: &[T]) {
+        self.extend_from_slice(values);
+    }
+
+    fn truncate(&mut self, len: usize) {
+        Vec::truncate(self, len);
+    }
+}
+
+/// Line storage, laid out exactly as it is uploaded to the GPU. CPU-side by default.
+#[derive(Default)]
+pub struct LineBuffer<V: Storage<f32> = Vec<f32>, I: Storage<u32> = Vec<u32>> {
+    vec: V,
+    stripVertices: V,
+    stripIndices: I,
+    stripStart: Option<u32>,
+}
+
+/// GPU copy of a `LineBuffer`: plain lines in one VAO, strips in an indexed one.
+struct GpuLines {
     vao: GLuint,
     vbo: GLuint,
-    floatsPushed: usize,
-    lastFloatsPushed: usize,
+    vboBytes: usize,
+    stripVao: GLuint,
+    stripVbo: GLuint,
+    stripEbo: GLuint,
+    stripVboBytes: usize,
+    stripEboBytes: usize,
+    lineVertices: usize,
+    stripIndices: usize,
+}
+
+/// How a batch of lines interacts with the depth buffer.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DepthMode {
+    /// Hidden behind geometry like any other surface.
+    Tested = 0,
+    /// Visible parts drawn normally, hidden parts dimmed and dashed.
+    XRay = 1,
+    /// No depth test, drawn on top of everything.
+    Overlay = 2,
+}
+
+impl DepthMode {
+    /// Also the draw order, so overlay lines end up on top.
+    pub const ALL: [DepthMode; DEPTH_MODES] = [DepthMode::Tested, DepthMode::XRay, DepthMode::Overlay];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            DepthMode::Tested => "Depth tested",
+            DepthMode::XRay => "X-ray",
+            DepthMode::Overlay => "Always on top",
+        }
+    }
+}
+
+pub const DEPTH_MODES: usize = 3;
+
+pub struct LineRenderer {
+    shader: Shader,
+    immediate: [StreamLines; DEPTH_MODES],
+    retained: RetainedLines,
+    retainedGpu: [GpuLines; DEPTH_MODES],
+    /// Screen-space lines in pixels, drawn over everything else.
+    overlay: StreamLines,
+    viewport: (f32, f32),
+    labels: Labels,
+    captureNext: bool,
+    capture: Option<FrameCapture>,
+    depthMode: DepthMode,
+    /// Draws every batch with this mode instead of the one it was pushed with.
+    pub depthOverride: Option<DepthMode>,
     pub enabled: bool,
+    /// Optional cap on immediate lines per frame and what to do past it.
+    pub budget: LineBudget,
+    stats: LineStats,
+    /// Used by mid-frame flushes, set with `setViewProjection` or the last `drawFlush`.
+    viewProjection: Matrix4,
+    /// Open strip bookkeeping, so strip segments count against the budget and survive flushes.
+    strip: StripState,
+    overlayStrip: StripState,
+    /// `LineSink` calls currently go to the overlay, see `OverlayLines`.
+    onOverlay: bool,
+}
+
+#[derive(Default)]
+struct StripState {
+    vertices: usize,
+    first: Option<(Vector3, Vector3)>,
+    last: Option<(Vector3, Vector3)>,
+    /// The strip was split by a flush, so its first vertex is no longer in the buffer.
+    resumed: bool,
 }
 
 /*
@@ -26,138 +164,785 @@ pub struct LineRenderer {
  *
  * Floats: 6
  * Bytes: 48
+ *
+ * Strips use the same vertex layout once per vertex, plus one u32 index per vertex and one
+ * STRIP_RESTART index per strip (28 bytes per segment on long strips).
  */
 const FLOATS: usize = 6;
 const FLOAT_SIZE: usize = size_of::<f32>();
+/// Matches `GL_PRIMITIVE_RESTART_FIXED_INDEX` for `GL_UNSIGNED_INT` indices.
+pub const STRIP_RESTART: u32 = u32::MAX;
 const SHADER_VERT: &str = "resources/shaders/line_renderer.vert";
 const SHADER_FRAG: &str = "resources/shaders/line_renderer.frag";
 
-impl LineRenderer {
-    pub fn new(capacity: usize) -> LineRenderer {
-        let mut renderer = LineRenderer {
+#[allow(dead_code)]
+impl LineBuffer {
+    pub fn new() -> LineBuffer {
+        LineBuffer::default()
+    }
+
+    pub fn withCapacity(capacity: usize) -> LineBuffer {
+        LineBuffer {
             vec: Vec::with_capacity(capacity),
-            shader: Shader::new(SHADER_VERT, SHADER_FRAG),
+            ..LineBuffer::default()
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<V: Storage<f32>, I: Storage<u32>> LineBuffer<V, I> {
+    pub fn fromStorage(vec: V, stripVertices: V, stripIndices: I) -> LineBuffer<V, I> {
+        LineBuffer {
+            vec,
+            stripVertices,
+            stripIndices,
+            stripStart: None,
+        }
+    }
+
+    pub fn floats(&self) -> &[f32] {
+        self.vec.asSlice()
+    }
+
+    pub fn stripVertices(&self) -> &[f32] {
+        self.stripVertices.asSlice()
+    }
+
+    pub fn stripIndices(&self) -> &[u32] {
+        self.stripIndices.asSlice()
+    }
+
+    pub fn lineCount(&self) -> usize {
+        self.vec.len() / (FLOATS * 2)
+    }
+
+    /// Returns `(pos1, color1, pos2, color2)` of the line at `index`.
+    pub fn line(&self, index: usize) -> (Vector3, Vector3, Vector3, Vector3) {
+        let f = &self.floats()[index * FLOATS * 2..(index + 1) * FLOATS * 2];
+        (
+            Vector3::new(f[0], f[1], f[2]),
+            Vector3::new(f[3], f[4], f[5]),
+            Vector3::new(f[6], f[7], f[8]),
+            Vector3::new(f[9], f[10], f[11]),
+        )
+    }
+
+    fn stripVertex(&self, index: u32) -> (Vector3, Vector3) {
+        let i = index as usize * FLOATS;
+        let f = &self.stripVertices()[i..i + FLOATS];
+        (Vector3::new(f[0], f[1], f[2]), Vector3::new(f[3], f[4], f[5]))
+    }
+
+    /// Every segment drawn, from plain lines followed by strips, as `(pos1, color1, pos2, color2)`.
+    pub fn segments(&self) -> impl Iterator<Item = (Vector3, Vector3, Vector3, Vector3)> + '_ {
+        let strips = self
+            .stripIndices()
+            .windows(2)
+            .filter(|pair| pair[0] != STRIP_RESTART && pair[1] != STRIP_RESTART)
+            .map(|pair| {
+                let (p1, c1) = self.stripVertex(pair[0]);
+                let (p2, c2) = self.stripVertex(pair[1]);
+                (p1, c1, p2, c2)
+            });
+        (0..self.lineCount()).map(|i| self.line(i)).chain(strips)
+    }
+
+    pub fn segmentCount(&self) -> usize {
+        self.segments().count()
+    }
+
+    pub fn isEmpty(&self) -> bool {
+        self.vec.isEmpty() && self.stripIndices.isEmpty()
+    }
+
+    pub fn clear(&mut self) {
+        self.vec.clear();
+        self.stripVertices.clear();
+        self.stripIndices.clear();
+        self.stripStart = None;
+    }
+
+    /// Copies all of `other` onto the end of this buffer, rebasing its strip indices.
+    pub fn append<V2: Storage<f32>, I2: Storage<u32>>(&mut self, other: &LineBuffer<V2, I2>) {
+        self.endStrip();
+        self.vec.extend(other.floats());
+        let offset = (self.stripVertices.len() / FLOATS) as u32;
+        self.stripVertices.extend(other.stripVertices());
+        let indices: Vec<u32> = other
+            .stripIndices()
+            .iter()
+            .map(|&i| if i == STRIP_RESTART { i } else { i + offset })
+            .collect();
+        self.stripIndices.extend(&indices);
+        if other.stripStart.is_some() {
+            self.stripIndices.extend(&[STRIP_RESTART]);
+        }
+    }
+
+    fn finishStrip(&mut self, closed: bool) {
+        let Some(start) = self.stripStart.take() else {
+            return;
+        };
+        let count = (self.stripVertices.len() / FLOATS) as u32 - start;
+        if count < 2 {
+            // A lone vertex draws nothing, drop it
+            self.stripVertices.truncate(start as usize * FLOATS);
+            self.stripIndices.truncate(self.stripIndices.len() - count as usize);
+            return;
+        }
+        if closed && count > 2 {
+            self.stripIndices.extend(&[start]);
+        }
+        self.stripIndices.extend(&[STRIP_RESTART]);
+    }
+}
+
+impl<V: Storage<f32>, I: Storage<u32>> LineSink for LineBuffer<V, I> {
+    fn pushLine(&mut self, pos1: Vector3, color1: Vector3, pos2: Vector3, color2: Vector3) {
+        self.vec.extend(&[
+            pos1.x, pos1.y, pos1.z, color1.x, color1.y, color1.z,
+            pos2.x, pos2.y, pos2.z, color2.x, color2.y, color2.z,
+        ]);
+    }
+
+    fn beginStrip(&mut self) {
+        self.finishStrip(false);
+        self.stripStart = Some((self.stripVertices.len() / FLOATS) as u32);
+    }
+
+    fn vertex(&mut self, pos: Vector3, color: Vector3) {
+        if self.stripStart.is_none() {
+            self.beginStrip();
+        }
+        let index = (self.stripVertices.len() / FLOATS) as u32;
+        self.stripVertices.extend(&[pos.x, pos.y, pos.z, color.x, color.y, color.z]);
+        self.stripIndices.extend(&[index]);
+    }
+
+    fn endStrip(&mut self) {
+        self.finishStrip(false);
+    }
+
+    fn endLoop(&mut self) {
+        self.finishStrip(true);
+    }
+}
+
+/// Attaches the line vertex layout (position + color) in `vbo` to `vao`.
+unsafe fn setupVertexArray(shader: &Shader, vao: GLuint, vbo: GLuint) {
+    unsafe {
+        gl::VertexArrayVertexBuffer(vao, 0, vbo, 0, (FLOATS * FLOAT_SIZE) as GLsizei);
+
+        let locPos = shader.getAttribLocation("i_position") as GLuint;
+        let locCol = shader.getAttribLocation("i_color") as GLuint;
+
+        let mut offset: GLuint = 0;
+        gl::VertexArrayAttribFormat(vao, locPos, 3, gl::FLOAT, gl::FALSE, offset);
+        gl::VertexArrayAttribBinding(vao, locPos, 0);
+        offset += 3 * FLOAT_SIZE as GLuint;
+
+        gl::VertexArrayAttribFormat(vao, locCol, 3, gl::FLOAT, gl::FALSE, offset);
+        gl::VertexArrayAttribBinding(vao, locCol, 0);
+        // offset += 3 * FLOAT_SIZE as GLuint;
+
+        gl::EnableVertexArrayAttrib(vao, locPos);
+        gl::EnableVertexArrayAttrib(vao, locCol);
+    }
+}
+
+/// Uploads `data`, reallocating only when it no longer fits in the `allocated` bytes.
+/// Reallocations at least double the size, so a slowly growing buffer isn't reallocated
+/// every frame.
+unsafe fn uploadGrowing<T>(buffer: GLuint, allocated: &mut usize, data: &[T]) {
+    let bytes = size_of_val(data);
+    unsafe {
+        if bytes > *allocated {
+            *allocated = bytes.max(*allocated * 2);
+            gl::NamedBufferData(buffer, *allocated as GLsizeiptr, std::ptr::null(), gl::DYNAMIC_DRAW);
+        }
+        gl::NamedBufferSubData(buffer, 0, bytes as GLsizeiptr, data.as_ptr() as *const c_void);
+    }
+}
+
+impl GpuLines {
+    fn new(shader: &Shader) -> GpuLines {
+        let mut gpu = GpuLines {
             vao: 0,
             vbo: 0,
-            floatsPushed: 0,
-            lastFloatsPushed: capacity,
-            enabled: true,
+            vboBytes: 0,
+            stripVao: 0,
+            stripVbo: 0,
+            stripEbo: 0,
+            stripVboBytes: 0,
+            stripEboBytes: 0,
+            lineVertices: 0,
+            stripIndices: 0,
         };
         unsafe {
-            gl::CreateVertexArrays(1, &mut renderer.vao);
-            gl::CreateBuffers(1, &mut renderer.vbo);
-            gl::BindVertexArray(renderer.vao);
-
-            gl::NamedBufferData(
-                renderer.vbo,
-                (capacity * FLOAT_SIZE) as GLsizeiptr,
-                renderer.vec.as_ptr() as *const c_void,
-                gl::DYNAMIC_DRAW,
-            );
-            gl::VertexArrayVertexBuffer(
-                renderer.vao,
-                0,
-                renderer.vbo,
-                0,
-                (FLOATS * FLOAT_SIZE) as GLsizei,
-            );
-
-            let locPos = renderer.shader.getAttribLocation("i_position") as GLuint;
-            let locCol = renderer.shader.getAttribLocation("i_color") as GLuint;
-
-            let mut offset: GLuint = 0;
-            gl::VertexArrayAttribFormat(
-                renderer.vao,
-                locPos,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                offset,
-            );
-            gl::VertexArrayAttribBinding(renderer.vao, locPos, 0);
-            offset += 3 * FLOAT_SIZE as GLuint;
-
-            gl::VertexArrayAttribFormat(
-                renderer.vao,
-                locCol,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                offset,
-            );
-            gl::VertexArrayAttribBinding(renderer.vao, locCol, 0);
-            // offset += 3 * FLOAT_SIZE as GLuint;
-
-            gl::EnableVertexArrayAttrib(renderer.vao, locPos);
-            gl::EnableVertexArrayAttrib(renderer.vao, locCol);
-            renderer.vec.clear();
-        }
-        renderer
-    }
-
-    pub fn pushLine(&mut self, pos1: Vector3, color1: Vector3, pos2: Vector3, color2: Vector3) {
-        if !self.enabled {
-            return;
+            gl::CreateVertexArrays(1, &mut gpu.vao);
+            gl::CreateBuffers(1, &mut gpu.vbo);
+            setupVertexArray(shader, gpu.vao, gpu.vbo);
+
+            gl::CreateVertexArrays(1, &mut gpu.stripVao);
+            gl::CreateBuffers(1, &mut gpu.stripVbo);
+            gl::CreateBuffers(1, &mut gpu.stripEbo);
+            setupVertexArray(shader, gpu.stripVao, gpu.stripVbo);
+            gl::VertexArrayElementBuffer(gpu.stripVao, gpu.stripEbo);
+        }
+        gpu
+    }
+
+    fn upload(&mut self, lines: &LineBuffer) {
+        unsafe {
+            if !lines.vec.is_empty() {
+                uploadGrowing(self.vbo, &mut self.vboBytes, lines.floats());
+            }
+            if !lines.stripIndices.is_empty() {
+                uploadGrowing(self.stripVbo, &mut self.stripVboBytes, lines.stripVertices());
+                uploadGrowing(self.stripEbo, &mut self.stripEboBytes, lines.stripIndices());
+            }
         }
-        self.vec.push(pos1.x);
-        self.vec.push(pos1.y);
-        self.vec.push(pos1.z);
-        self.vec.push(color1.x);
-        self.vec.push(color1.y);
-        self.vec.push(color1.z);
+        self.lineVertices = lines.vec.len() / FLOATS;
+        self.stripIndices = lines.stripIndices.len();
+    }
 
-        self.vec.push(pos2.x);
-        self.vec.push(pos2.y);
-        self.vec.push(pos2.z);
-        self.vec.push(color2.x);
-        self.vec.push(color2.y);
-        self.vec.push(color2.z);
+    fn draw(&self) {
+        unsafe {
+            if self.lineVertices >= 2 {
+                gl::BindVertexArray(self.vao);
+                gl::DrawArrays(gl::LINES, 0, self.lineVertices as GLsizei);
+            }
+            if self.stripIndices > 0 {
+                gl::BindVertexArray(self.stripVao);
+                gl::Enable(gl::PRIMITIVE_RESTART_FIXED_INDEX);
+                gl::DrawElements(
+                    gl::LINE_STRIP,
+                    self.stripIndices as GLsizei,
+                    gl::UNSIGNED_INT,
+                    std::ptr::null(),
+                );
+                gl::Disable(gl::PRIMITIVE_RESTART_FIXED_INDEX);
+            }
+        }
+    }
 
-        self.floatsPushed += FLOATS * 2;
+    fn isEmpty(&self) -> bool {
+        self.lineVertices < 2 && self.stripIndices == 0
+    }
+
+    fn allocatedBytes(&self) -> usize {
+        self.vboBytes + self.stripVboBytes + self.stripEboBytes
+    }
+
+    fn destroy(&self) {
+        unsafe {
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.stripEbo);
+            gl::DeleteBuffers(1, &self.stripVbo);
+            gl::DeleteVertexArrays(1, &self.stripVao);
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl LineRenderer {
+    /// `capacity` is the number of lines each depth mode holds per frame before its buffers
+    /// grow. Growth doubles, so it only happens a handful of times.
+    pub fn new(capacity: usize) -> LineRenderer {
+        let shader = Shader::new(SHADER_VERT, SHADER_FRAG);
+        LineRenderer {
+            immediate: std::array::from_fn(|_| StreamLines::new(&shader, capacity)),
+            retainedGpu: std::array::from_fn(|_| GpuLines::new(&shader)),
+            retained: RetainedLines::new(),
+            overlay: StreamLines::new(&shader, capacity),
+            viewport: (1.0, 1.0),
+            labels: Labels::new(),
+            captureNext: false,
+            capture: None,
+            shader,
+            depthMode: DepthMode::Tested,
+            depthOverride: None,
+            enabled: true,
+            budget: LineBudget::new(),
+            stats: LineStats::default(),
+            viewProjection: Matrix4::from_scale(1.0),
+            strip: StripState::default(),
+            overlayStrip: StripState::default(),
+            onOverlay: false,
+        }
+    }
+
+    /// Sets the depth mode for lines pushed or retained from now on.
+    pub fn setDepthMode(&mut self, mode: DepthMode) {
+        self.endStrip();
+        self.depthMode = mode;
+    }
+
+    /// Matrix for lines drawn before `drawFlush`, when `OverflowPolicy::Flush` kicks in.
+    /// Set it before pushing lines if the camera moved since the last frame.
+    pub fn setViewProjection(&mut self, pvMatrix: &Matrix4) {
+        self.viewProjection = *pvMatrix;
+    }
+
+    /// Framebuffer size in pixels, for the overlay projection. Call on `FramebufferSize`.
+    pub fn setViewport(&mut self, width: f32, height: f32) {
+        self.viewport = (width.max(1.0), height.max(1.0));
+    }
+
+    pub fn viewport(&self) -> (f32, f32) {
+        self.viewport
+    }
+
+    /// Screen-space lines for this frame, in pixels with the origin at the top left. Use
+    /// with `overlay::Overlay2D`; drawn by `drawOverlay`, without depth testing. Like the 3D
+    /// lines they are skipped while disabled and count against `budget`, but budget flushes
+    /// keep them for `drawOverlay`.
+    pub fn overlay(&mut self) -> OverlayLines<'_> {
+        OverlayLines { renderer: self }
+    }
+
+    /// Text anchored at `pos`, shown for this frame. Drawn by `drawLabels`.
+    pub fn pushLabel(&mut self, pos: Vector3, color: Vector3, text: impl Into<String>) {
+        if self.enabled {
+            self.labels.push(pos, color, text.into());
+        }
+    }
+
+    /// Draws this frame's labels with the ImGui background draw list, projected with the
+    /// matrix of the last `drawFlush` (or `setViewProjection`). Call once per ImGui frame.
+    pub fn drawLabels(&mut self, ui: &imgui::Ui) {
+        self.labels.draw(ui, &self.viewProjection, self.viewport);
+    }
+
+    /// World-space copy of every 3D line queued so far this frame, immediate and retained.
+    /// Lines already drawn by an `OverflowPolicy::Flush` flush are gone.
+    pub fn snapshot(&self) -> LineBuffer {
+        let mut lines = LineBuffer::new();
+        for stream in self.immediate.iter() {
+            lines.append(&stream.lines);
+        }
+        self.retained.appendTo(&mut lines);
+        lines
+    }
+
+    /// Takes a `snapshot` at the next `drawFlush`, with its camera, for `takeCapture`.
+    pub fn captureFrame(&mut self) {
+        self.captureNext = true;
+    }
+
+    pub fn takeCapture(&mut self) -> Option<FrameCapture> {
+        self.capture.take()
+    }
+
+    /// Counters of the last `drawFlush`ed frame.
+    pub fn stats(&self) -> LineStats {
+        self.stats
+    }
+
+    /// Lines the immediate buffers hold per frame before growing, over all depth modes.
+    pub fn capacityLines(&self) -> usize {
+        self.immediate.iter().map(|stream| stream.capacityLines()).sum()
+    }
+
+    /// GPU memory held by immediate and retained lines.
+    pub fn bufferBytes(&self) -> usize {
+        let immediate: usize = self.immediate.iter().map(|stream| stream.allocatedBytes()).sum();
+        let retained: usize = self.retainedGpu.iter().map(|gpu| gpu.allocatedBytes()).sum();
+        immediate + retained + self.overlay.allocatedBytes()
+    }
+
+    pub fn depthMode(&self) -> DepthMode {
+        self.depthMode
+    }
+
+    /// Keeps the lines built by `build` on the GPU until removed with `removeRetained`.
+    pub fn retain(&mut self, build: impl FnOnce(&mut LineBuffer)) -> LineHandle {
+        let mut lines = LineBuffer::new();
+        build(&mut lines);
+        self.retained.add(lines, self.depthMode, None)
+    }
+
+    /// Like `retain`, but the lines are removed automatically after `seconds` of `advance`.
+    pub fn retainFor(&mut self, seconds: f32, build: impl FnOnce(&mut LineBuffer)) -> LineHandle {
+        let mut lines = LineBuffer::new();
+        build(&mut lines);
+        self.retained.add(lines, self.depthMode, Some(seconds))
+    }
+
+    pub fn removeRetained(&mut self, handle: LineHandle) -> bool {
+        self.retained.remove(handle)
+    }
+
+    pub fn clearRetained(&mut self) {
+        self.retained.clear();
+    }
+
+    pub fn retainedLines(&self) -> &RetainedLines {
+        &self.retained
+    }
+
+    /// Ages timed retained lines, call once per frame.
+    pub fn advance(&mut self, dt: f32) {
+        self.retained.advance(dt);
     }
 
     pub fn drawFlush(&mut self, pvMatrix: &Matrix4) {
-        if self.vec.len() < FLOATS * 2 || self.floatsPushed < FLOATS * 2 {
-            return;
+        self.viewProjection = *pvMatrix;
+        self.strip = StripState::default();
+        // Retained lines are only re-uploaded when the set changes
+        if let Some(merged) = self.retained.takeChanged() {
+            for (gpu, lines) in self.retainedGpu.iter_mut().zip(merged.iter()) {
+                gpu.upload(lines);
+            }
+        }
+        for stream in self.immediate.iter_mut() {
+            stream.lines.endStrip();
+        }
+        if self.captureNext {
+            self.captureNext = false;
+            self.capture = Some(FrameCapture {
+                lines: self.snapshot(),
+                viewProjection: *pvMatrix,
+                viewport: self.viewport,
+            });
+        }
+        let isEmpty = |i: usize| self.immediate[i].lines.isEmpty() && self.retainedGpu[i].isEmpty();
+        if self.enabled && !(0..DEPTH_MODES).all(isEmpty) {
+            self.drawBatches(pvMatrix, true);
         }
+        // Fences this frame's segments, pushes from here on go to the next one
+        for stream in self.immediate.iter_mut() {
+            stream.nextFrame();
+        }
+        self.stats = LineStats {
+            capacityLines: self.capacityLines(),
+            bufferBytes: self.bufferBytes(),
+            ..self.budget.endFrame()
+        };
+    }
+
+    /// Draws the immediate lines queued so far and starts over with empty buffers, keeping an
+    /// open strip going from its last vertex. Overlay lines wait for `drawOverlay`.
+    fn flushImmediate(&mut self) {
+        for stream in self.immediate.iter_mut() {
+            stream.lines.endStrip();
+        }
+        let pvMatrix = self.viewProjection;
+        self.drawBatches(&pvMatrix, false);
+        for stream in self.immediate.iter_mut() {
+            stream.nextFrame();
+        }
+        self.budget.flushed();
+        resumeStrip(&mut self.immediate[self.depthMode as usize].lines, &mut self.strip);
+    }
+
+    /// Counts `count` lines against the budget, flushing if needed. False means drop them.
+    fn admit(&mut self, count: usize) -> bool {
+        loop {
+            match self.budget.admit(count) {
+                Admit::Accept => return true,
+                Admit::Drop => return false,
+                Admit::FlushFirst => self.flushImmediate(),
+            }
+        }
+    }
+
+    fn drawBatches(&self, pvMatrix: &Matrix4, includeRetained: bool) {
+        let isEmpty = |i: usize| {
+            self.immediate[i].lines.isEmpty() && (!includeRetained || self.retainedGpu[i].isEmpty())
+        };
+        let draw = |i: usize| {
+            self.immediate[i].draw();
+            if includeRetained {
+                self.retainedGpu[i].draw();
+            }
+        };
 
         self.shader.bind();
         self.shader.setMatrix4f("u_pvm", pvMatrix);
 
         unsafe {
-            if self.floatsPushed > self.lastFloatsPushed {
-                gl::NamedBufferData(
-                    self.vbo,
-                    (self.vec.len() * FLOAT_SIZE) as GLsizeiptr,
-                    self.vec.as_ptr() as *const c_void,
-                    gl::DYNAMIC_DRAW,
-                );
+            let depthTest = gl::IsEnabled(gl::DEPTH_TEST);
+            let mut depthFunc = gl::LESS as GLint;
+            gl::GetIntegerv(gl::DEPTH_FUNC, &mut depthFunc);
+
+            for mode in DepthMode::ALL {
+                let i = mode as usize;
+                if isEmpty(i) {
+                    continue;
+                }
+                match self.depthOverride.unwrap_or(mode) {
+                    DepthMode::Tested => {
+                        gl::Enable(gl::DEPTH_TEST);
+                        gl::DepthFunc(gl::LEQUAL);
+                    }
+                    DepthMode::XRay => {
+                        // Hidden parts first, without writing depth so the visible pass still passes
+                        gl::Enable(gl::DEPTH_TEST);
+                        gl::DepthFunc(gl::GREATER);
+                        gl::DepthMask(gl::FALSE);
+                        self.shader.setUniform1i("u_occluded", 1);
+                        draw(i);
+
+                        gl::DepthFunc(gl::LEQUAL);
+                        gl::DepthMask(gl::TRUE);
+                        self.shader.setUniform1i("u_occluded", 0);
+                    }
+                    DepthMode::Overlay => {
+                        gl::Disable(gl::DEPTH_TEST);
+                    }
+                }
+                draw(i);
+            }
+
+            if depthTest == gl::TRUE {
+                gl::Enable(gl::DEPTH_TEST);
             } else {
-                gl::NamedBufferSubData(
-                    self.vbo,
-                    0,
-                    (self.vec.len() * FLOAT_SIZE) as GLsizeiptr,
-                    self.vec.as_ptr() as *const c_void,
-                );
+                gl::Disable(gl::DEPTH_TEST);
             }
+            gl::DepthFunc(depthFunc as GLenum);
+        }
+    }
 
-            gl::BindVertexArray(self.vao);
-            let drawCount = self.vec.len() / FLOATS;
-            // println!("drawCount: {}", drawCount);
-            gl::DrawArrays(gl::LINES, 0, drawCount as GLsizei);
+    /// Draws and clears this frame's `overlay` lines. Call after post-processing, with the
+    /// window bound, so the overlay isn't tone mapped or blurred with the scene.
+    pub fn drawOverlay(&mut self) {
+        self.overlayStrip = StripState::default();
+        self.overlay.lines.endStrip();
+        if self.enabled && !self.overlay.lines.isEmpty() {
+            self.shader.bind();
+            self.shader.setMatrix4f("u_pvm", &overlay::pixelProjection(self.viewport.0, self.viewport.1));
+            self.shader.setUniform1i("u_display", 1);
+            unsafe {
+                let depthTest = gl::IsEnabled(gl::DEPTH_TEST);
+                gl::Disable(gl::DEPTH_TEST);
+                self.overlay.draw();
+                if depthTest == gl::TRUE {
+                    gl::Enable(gl::DEPTH_TEST);
+                }
+            }
+            self.shader.setUniform1i("u_display", 0);
         }
+        self.overlay.nextFrame();
+    }
 
-        self.vec.clear();
-        self.lastFloatsPushed = self.floatsPushed;
-        self.floatsPushed = 0;
+    /// Lines pushed this frame with `mode`, until the next `drawFlush`.
+    pub fn buffer(&self, mode: DepthMode) -> &MappedLineBuffer {
+        &self.immediate[mode as usize].lines
     }
 
-    pub fn destroy(&self) {
+    fn current(&mut self) -> &mut MappedLineBuffer {
+        if self.onOverlay {
+            &mut self.overlay.lines
+        } else {
+            &mut self.immediate[self.depthMode as usize].lines
+        }
+    }
+
+    fn stripState(&mut self) -> &mut StripState {
+        if self.onOverlay { &mut self.overlayStrip } else { &mut self.strip }
+    }
+
+    pub fn destroy(&mut self) {
         println!("Destroying line renderer");
         self.shader.delete();
-        unsafe {
-            gl::DeleteBuffers(1, &self.vbo);
-            gl::DeleteVertexArrays(1, &self.vao);
+        for stream in self.immediate.iter_mut() {
+            stream.destroy();
+        }
+        self.overlay.destroy();
+        for gpu in self.retainedGpu.iter() {
+            gpu.destroy();
+        }
+    }
+}
+
+/// Restarts a strip split by a flush from its last vertex.
+fn resumeStrip(lines: &mut MappedLineBuffer, strip: &mut StripState) {
+    if let Some((pos, color)) = strip.last {
+        lines.beginStrip();
+        lines.vertex(pos, color);
+        strip.resumed = true;
+    }
+}
+
+impl LineSink for LineRenderer {
+    fn pushLine(&mut self, pos1: Vector3, color1: Vector3, pos2: Vector3, color2: Vector3) {
+        if !self.enabled || !self.admit(1) {
+            return;
+        }
+        self.current().pushLine(pos1, color1, pos2, color2);
+    }
+
+    fn beginStrip(&mut self) {
+        if self.enabled {
+            self.current().beginStrip();
+            *self.stripState() = StripState::default();
+        }
+    }
+
+    fn vertex(&mut self, pos: Vector3, color: Vector3) {
+        if !self.enabled {
+            return;
+        }
+        // Only the second vertex onwards adds a segment
+        if self.stripState().vertices > 0 && !self.admit(1) {
+            return;
+        }
+        self.current().vertex(pos, color);
+        let strip = self.stripState();
+        strip.vertices += 1;
+        strip.first.get_or_insert((pos, color));
+        strip.last = Some((pos, color));
+    }
+
+    fn endStrip(&mut self) {
+        self.current().endStrip();
+        *self.stripState() = StripState::default();
+    }
+
+    fn endLoop(&mut self) {
+        if self.stripState().vertices <= 2 || !self.admit(1) {
+            self.endStrip();
+            return;
+        }
+        // `admit` may have flushed, check afterwards whether the first vertex is still here
+        let strip = self.stripState();
+        match (strip.resumed, strip.first) {
+            (true, Some((pos, color))) => {
+                self.current().vertex(pos, color);
+                self.current().endStrip();
+            }
+            _ => self.current().endLoop(),
+        }
+        *self.stripState() = StripState::default();
+    }
+}
+
+/// `LineSink` for the screen-space overlay, returned by `LineRenderer::overlay`.
+pub struct OverlayLines<'a> {
+    renderer: &'a mut LineRenderer,
+}
+
+impl OverlayLines<'_> {
+    fn push(&mut self, push: impl FnOnce(&mut LineRenderer)) {
+        self.renderer.onOverlay = true;
+        push(self.renderer);
+        self.renderer.onOverlay = false;
+    }
+}
+
+impl LineSink for OverlayLines<'_> {
+    fn pushLine(&mut self, pos1: Vector3, color1: Vector3, pos2: Vector3, color2: Vector3) {
+        self.push(|renderer| renderer.pushLine(pos1, color1, pos2, color2));
+    }
+
+    fn beginStrip(&mut self) {
+        self.push(|renderer| renderer.beginStrip());
+    }
+
+    fn vertex(&mut self, pos: Vector3, color: Vector3) {
+        self.push(|renderer| renderer.vertex(pos, color));
+    }
+
+    fn endStrip(&mut self) {
+        self.push(|renderer| renderer.endStrip());
+    }
+
+    fn endLoop(&mut self) {
+        self.push(|renderer| renderer.endLoop());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec3;
+
+    const WHITE: Vector3 = Vector3::new(1.0, 1.0, 1.0);
+
+    #[test]
+    fn stripStoresEachVertexOnce() {
+        let mut buffer = LineBuffer::new();
+        buffer.beginStrip();
+        for i in 0..5 {
+            buffer.vertex(vec3(i as f32, 0.0, 0.0), WHITE);
+        }
+        buffer.endStrip();
+        assert_eq!(buffer.stripVertices().len(), 5 * FLOATS);
+        assert_eq!(buffer.stripIndices(), &[0, 1, 2, 3, 4, STRIP_RESTART]);
+        assert_eq!(buffer.segmentCount(), 4);
+        let (p1, _, p2, _) = buffer.segments().last().unwrap();
+        assert_eq!((p1, p2), (vec3(3.0, 0.0, 0.0), vec3(4.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn loopClosesBackToFirstVertex() {
+        let mut buffer = LineBuffer::new();
+        buffer.beginStrip();
+        buffer.vertex(vec3(0.0, 0.0, 0.0), WHITE);
+        buffer.vertex(vec3(1.0, 0.0, 0.0), WHITE);
+        buffer.vertex(vec3(1.0, 1.0, 0.0), WHITE);
+        buffer.endLoop();
+        buffer.beginStrip();
+        buffer.vertex(vec3(5.0, 0.0, 0.0), WHITE);
+        buffer.vertex(vec3(6.0, 0.0, 0.0), WHITE);
+        buffer.endLoop();
+        assert_eq!(buffer.stripIndices(), &[0, 1, 2, 0, STRIP_RESTART, 3, 4, STRIP_RESTART]);
+        assert_eq!(buffer.segmentCount(), 4);
+    }
+
+    #[test]
+    fn degenerateStripsAreDropped() {
+        let mut buffer = LineBuffer::new();
+        buffer.pushLine(vec3(0.0, 0.0, 0.0), WHITE, vec3(1.0, 0.0, 0.0), WHITE);
+        buffer.beginStrip();
+        buffer.vertex(vec3(0.0, 0.0, 0.0), WHITE);
+        buffer.endStrip();
+        buffer.beginStrip();
+        buffer.endLoop();
+        assert!(buffer.stripVertices().is_empty());
+        assert!(buffer.stripIndices().is_empty());
+        assert_eq!(buffer.segmentCount(), 1);
+        assert!(!buffer.isEmpty());
+    }
+
+    #[test]
+    fn appendRebasesStripIndices() {
+        let mut a = LineBuffer::new();
+        a.pushLine(vec3(0.0, 0.0, 0.0), WHITE, vec3(1.0, 0.0, 0.0), WHITE);
+        a.beginStrip();
+        a.vertex(vec3(0.0, 1.0, 0.0), WHITE);
+        a.vertex(vec3(1.0, 1.0, 0.0), WHITE);
+        a.endStrip();
+
+        let mut b = LineBuffer::new();
+        b.beginStrip();
+        b.vertex(vec3(0.0, 2.0, 0.0), WHITE);
+        b.vertex(vec3(1.0, 2.0, 0.0), WHITE);
+        b.vertex(vec3(1.0, 3.0, 0.0), WHITE);
+        b.endLoop();
+
+        a.append(&b);
+        assert_eq!(a.stripIndices(), &[0, 1, STRIP_RESTART, 2, 3, 4, 2, STRIP_RESTART]);
+        assert_eq!(a.segmentCount(), 1 + 1 + 3);
+    }
+
+    #[test]
+    fn stripUsesLessThanSeparateLines() {
+        let (mut lines, mut strip) = (LineBuffer::new(), LineBuffer::new());
+        strip.beginStrip();
+        for i in 0..=1000 {
+            let p = vec3(i as f32, 0.0, 0.0);
+            strip.vertex(p, WHITE);
+            if i > 0 {
+                lines.pushLine(vec3(i as f32 - 1.0, 0.0, 0.0), WHITE, p, WHITE);
+            }
         }
+        strip.endStrip();
+        assert_eq!(lines.segmentCount(), strip.segmentCount());
+        let lineBytes = size_of_val(lines.floats());
+        let stripBytes = size_of_val(strip.stripVertices()) + size_of_val(strip.stripIndices());
+        assert!((stripBytes as f32) < lineBytes as f32 * 0.6);
     }
 }
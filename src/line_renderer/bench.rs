@@ -0,0 +1,74 @@
+#![allow(non_snake_case)]
+
+use super::stream::StreamLines;
+use super::{FLOATS, GpuLines, LineBuffer, LineSink, SHADER_FRAG, SHADER_VERT};
+use crate::shader::Shader;
+use cgmath::{SquareMatrix, vec3};
+use std::time::{Duration, Instant};
+
+type Matrix4 = cgmath::Matrix4<f32>;
+
+fn fill(sink: &mut impl LineSink, lineCount: usize, frame: usize) {
+    let color = vec3(1.0, 1.0, 1.0);
+    for i in 0..lineCount {
+        let a = (i + frame) as f32 * 0.001;
+        sink.pushLine(vec3(a.cos(), a.sin(), 0.0), color, vec3(a.cos(), a.sin(), 0.5), color);
+    }
+}
+
+fn timeFrames(frames: usize, mut frame: impl FnMut(usize)) -> Duration {
+    unsafe {
+        gl::Finish();
+    }
+    let start = Instant::now();
+    for i in 0..frames {
+        frame(i);
+    }
+    unsafe {
+        gl::Finish();
+    }
+    start.elapsed()
+}
+
+fn report(name: &str, lineCount: usize, frames: usize, elapsed: Duration) {
+    let perFrame = elapsed.as_secs_f64() * 1000.0 / frames as f64;
+    let linesPerSecond = (lineCount * frames) as f64 / elapsed.as_secs_f64();
+    println!(
+        "  {:<24} {:>8.3} ms/frame {:>8.2} M lines/s",
+        name,
+        perFrame,
+        linesPerSecond / 1_000_000.0
+    );
+}
+
+/// Pushes and draws `lineCount` lines per frame for `frames` frames, once through a CPU
+/// buffer re-uploaded with `NamedBufferData`/`NamedBufferSubData` and once through the
+/// persistently mapped ring `LineRenderer` uses. Needs a current GL context.
+pub fn runUploadBenchmark(lineCount: usize, frames: usize) {
+    println!("Line upload benchmark: {} lines x {} frames", lineCount, frames);
+    let shader = Shader::new(SHADER_VERT, SHADER_FRAG);
+    shader.bind();
+    shader.setMatrix4f("u_pvm", &Matrix4::identity());
+
+    let mut cpu = LineBuffer::withCapacity(lineCount * FLOATS * 2);
+    let mut gpu = GpuLines::new(&shader);
+    let elapsed = timeFrames(frames, |frame| {
+        fill(&mut cpu, lineCount, frame);
+        gpu.upload(&cpu);
+        gpu.draw();
+        cpu.clear();
+    });
+    report("BufferData/SubData", lineCount, frames, elapsed);
+    gpu.destroy();
+
+    let mut stream = StreamLines::new(&shader, lineCount);
+    let elapsed = timeFrames(frames, |frame| {
+        fill(&mut stream.lines, lineCount, frame);
+        stream.draw();
+        stream.nextFrame();
+    });
+    report("Persistent ring", lineCount, frames, elapsed);
+    stream.destroy();
+
+    shader.delete();
+}
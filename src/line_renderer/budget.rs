@@ -0,0 +1,183 @@
+#![allow(non_snake_case)]
+
+/// What to do with lines pushed past `LineBudget::maxLines`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum OverflowPolicy {
+    /// Ignore them for the rest of the frame.
+    Drop,
+    /// Draw everything queued so far, then keep going with an empty buffer.
+    Flush,
+}
+
+/// Outcome of `LineBudget::admit`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Admit {
+    Accept,
+    Drop,
+    /// Flush first, then admit again.
+    FlushFirst,
+}
+
+/// Counters for one frame of immediate lines, plus the GPU memory behind them.
+#[derive(Copy, Clone, Default, Debug)]
+pub struct LineStats {
+    pub lines: usize,
+    pub peakLines: usize,
+    pub droppedLines: usize,
+    pub flushes: usize,
+    pub capacityLines: usize,
+    pub bufferBytes: usize,
+}
+
+/// Counts immediate lines against an optional hard cap.
+pub struct LineBudget {
+    /// A cap of 0 drops everything, whatever the policy, since flushing couldn't make room.
+    pub maxLines: Option<usize>,
+    pub policy: OverflowPolicy,
+    queued: usize,
+    frame: usize,
+    peak: usize,
+    dropped: usize,
+    flushes: usize,
+}
+
+#[allow(dead_code)]
+impl LineBudget {
+    pub fn new() -> LineBudget {
+        LineBudget {
+            maxLines: None,
+            policy: OverflowPolicy::Drop,
+            queued: 0,
+            frame: 0,
+            peak: 0,
+            dropped: 0,
+            flushes: 0,
+        }
+    }
+
+    /// Lines queued since the last flush.
+    pub fn queued(&self) -> usize {
+        self.queued
+    }
+
+    pub fn admit(&mut self, count: usize) -> Admit {
+        let fits = self.maxLines.is_none_or(|max| self.queued + count <= max);
+        // When flushing, an empty buffer always takes the push, otherwise it would never progress
+        if fits || (self.policy == OverflowPolicy::Flush && self.queued == 0 && self.maxLines != Some(0)) {
+            self.queued += count;
+            self.frame += count;
+            return Admit::Accept;
+        }
+        match self.policy {
+            OverflowPolicy::Flush if self.maxLines != Some(0) => Admit::FlushFirst,
+            _ => {
+                self.dropped += count;
+                Admit::Drop
+            }
+        }
+    }
+
+    pub fn flushed(&mut self) {
+        self.queued = 0;
+        self.flushes += 1;
+    }
+
+    /// Closes the frame, returning its counters and resetting them. Buffer sizes are left
+    /// for the renderer to fill in.
+    pub fn endFrame(&mut self) -> LineStats {
+        self.peak = self.peak.max(self.frame);
+        let stats = LineStats {
+            lines: self.frame,
+            peakLines: self.peak,
+            droppedLines: self.dropped,
+            flushes: self.flushes,
+            ..LineStats::default()
+        };
+        self.queued = 0;
+        self.frame = 0;
+        self.dropped = 0;
+        self.flushes = 0;
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uncappedAcceptsEverything() {
+        let mut budget = LineBudget::new();
+        for _ in 0..1000 {
+            assert_eq!(budget.admit(1), Admit::Accept);
+        }
+        let stats = budget.endFrame();
+        assert_eq!(stats.lines, 1000);
+        assert_eq!(stats.droppedLines, 0);
+    }
+
+    #[test]
+    fn dropPolicyCountsDroppedLines() {
+        let mut budget = LineBudget::new();
+        budget.maxLines = Some(10);
+        for _ in 0..15 {
+            budget.admit(1);
+        }
+        assert_eq!(budget.admit(1), Admit::Drop);
+        let stats = budget.endFrame();
+        assert_eq!(stats.lines, 10);
+        assert_eq!(stats.droppedLines, 6);
+    }
+
+    #[test]
+    fn zeroCapDropsEverything() {
+        let mut budget = LineBudget::new();
+        budget.maxLines = Some(0);
+        assert_eq!(budget.admit(1), Admit::Drop);
+        assert_eq!(budget.endFrame().lines, 0);
+
+        budget.policy = OverflowPolicy::Flush;
+        assert_eq!(budget.admit(1), Admit::Drop);
+        assert_eq!(budget.admit(1), Admit::Drop);
+        let stats = budget.endFrame();
+        assert_eq!(stats.flushes, 0);
+        assert_eq!(stats.droppedLines, 2);
+    }
+
+    #[test]
+    fn flushPolicyAsksToFlush() {
+        let mut budget = LineBudget::new();
+        budget.maxLines = Some(4);
+        budget.policy = OverflowPolicy::Flush;
+        for _ in 0..4 {
+            assert_eq!(budget.admit(1), Admit::Accept);
+        }
+        assert_eq!(budget.admit(1), Admit::FlushFirst);
+        budget.flushed();
+        assert_eq!(budget.admit(1), Admit::Accept);
+        assert_eq!(budget.queued(), 1);
+        let stats = budget.endFrame();
+        assert_eq!(stats.lines, 5);
+        assert_eq!(stats.flushes, 1);
+    }
+
+    #[test]
+    fn oversizedPushStillFitsEmptyBuffer() {
+        let mut budget = LineBudget::new();
+        budget.maxLines = Some(2);
+        budget.policy = OverflowPolicy::Flush;
+        assert_eq!(budget.admit(5), Admit::Accept);
+        assert_eq!(budget.admit(1), Admit::FlushFirst);
+    }
+
+    #[test]
+    fn peakSurvivesFrames() {
+        let mut budget = LineBudget::new();
+        budget.admit(30);
+        assert_eq!(budget.endFrame().peakLines, 30);
+        budget.admit(10);
+        let stats = budget.endFrame();
+        assert_eq!(stats.lines, 10);
+        assert_eq!(stats.peakLines, 30);
+    }
+}
@@ -0,0 +1,345 @@
+#![allow(non_snake_case)]
+
+use super::LineSink;
+use cgmath::{InnerSpace, Vector2, vec2};
+
+type Vector3 = cgmath::Vector3<f32>;
+type Matrix4 = cgmath::Matrix4<f32>;
+
+/// Deepest bisection of a single span, caps the sample count at `2^MAX_DEPTH` per span.
+const MAX_DEPTH: u32 = 12;
+/// Deepest bisection of a span partly behind the camera, which isn't visible anyway.
+const MAX_HIDDEN_DEPTH: u32 = 4;
+/// Uniform spans before adaptive refinement, so S-shaped spans aren't mistaken for straight.
+const MIN_SPANS: u32 = 4;
+
+/// How finely curves are sampled.
+#[derive(Copy, Clone, Debug)]
+pub enum Subdivision {
+    /// Fixed number of equal parameter steps.
+    Segments(u32),
+    /// Splits spans until their projected midpoint is within `pixels` of the straight segment.
+    ScreenSpace {
+        viewProjection: Matrix4,
+        viewport: (f32, f32),
+        pixels: f32,
+    },
+}
+
+/// Constant color along a curve.
+#[allow(dead_code)]
+pub fn solid(color: Vector3) -> impl Fn(f32) -> Vector3 {
+    move |_| color
+}
+
+/// Linear blend from `start` to `end` over the curve.
+pub fn gradient(start: Vector3, end: Vector3) -> impl Fn(f32) -> Vector3 {
+    move |t| start + (end - start) * t
+}
+
+fn toScreen(viewProjection: &Matrix4, viewport: (f32, f32), p: Vector3) -> Option<Vector2<f32>> {
+    let clip = viewProjection * p.extend(1.0);
+    if clip.w <= 1e-5 {
+        return None;
+    }
+    let ndc = vec2(clip.x, clip.y) / clip.w;
+    Some(vec2((ndc.x * 0.5 + 0.5) * viewport.0, (ndc.y * 0.5 + 0.5) * viewport.1))
+}
+
+fn refine(
+    eval: &impl Fn(f32) -> Vector3,
+    viewProjection: &Matrix4,
+    viewport: (f32, f32),
+    pixels: f32,
+    (t0, t1): (f32, f32),
+    depth: u32,
+    out: &mut Vec<f32>,
+) {
+    let tm = (t0 + t1) * 0.5;
+    let screen = [t0, tm, t1].map(|t| toScreen(viewProjection, viewport, eval(t)));
+    let flat = match screen {
+        [Some(a), Some(m), Some(b)] => {
+            // Distance from the projected midpoint to the projected chord
+            let chord = b - a;
+            let len = chord.magnitude();
+            let error = if len > 1e-6 {
+                (chord.x * (m - a).y - chord.y * (m - a).x).abs() / len
+            } else {
+                (m - a).magnitude()
+            };
+            error <= pixels
+        }
+        // Behind the camera, only refine a little
+        _ => depth >= MAX_HIDDEN_DEPTH,
+    };
+    if flat || depth >= MAX_DEPTH {
+        out.push(t1);
+        return;
+    }
+    refine(eval, viewProjection, viewport, pixels, (t0, tm), depth + 1, out);
+    refine(eval, viewProjection, viewport, pixels, (tm, t1), depth + 1, out);
+}
+
+/// Parameters to sample `eval` at between `t0` and `t1`, both ends included.
+pub fn sampleParameters(eval: &impl Fn(f32) -> Vector3, t0: f32, t1: f32, subdivision: &Subdivision) -> Vec<f32> {
+    let mut out = vec![t0];
+    match *subdivision {
+        Subdivision::Segments(segments) => {
+            let segments = segments.max(1);
+            out.extend((1..=segments).map(|i| t0 + (t1 - t0) * i as f32 / segments as f32));
+        }
+        Subdivision::ScreenSpace {
+            viewProjection,
+            viewport,
+            pixels,
+        } => {
+            let pixels = pixels.max(0.01);
+            for i in 0..MIN_SPANS {
+                let a = t0 + (t1 - t0) * i as f32 / MIN_SPANS as f32;
+                let b = t0 + (t1 - t0) * (i + 1) as f32 / MIN_SPANS as f32;
+                refine(eval, &viewProjection, viewport, pixels, (a, b), 0, &mut out);
+            }
+        }
+    }
+    out
+}
+
+pub fn quadraticBezier(p0: Vector3, p1: Vector3, p2: Vector3, t: f32) -> Vector3 {
+    let u = 1.0 - t;
+    p0 * (u * u) + p1 * (2.0 * u * t) + p2 * (t * t)
+}
+
+pub fn cubicBezier(p0: Vector3, p1: Vector3, p2: Vector3, p3: Vector3, t: f32) -> Vector3 {
+    let u = 1.0 - t;
+    p0 * (u * u * u) + p1 * (3.0 * u * u * t) + p2 * (3.0 * u * t * t) + p3 * (t * t * t)
+}
+
+/// Uniform Catmull-Rom between `p1` and `p2`.
+pub fn catmullRom(p0: Vector3, p1: Vector3, p2: Vector3, p3: Vector3, t: f32) -> Vector3 {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    (p1 * 2.0
+        + (p2 - p0) * t
+        + (p0 * 2.0 - p1 * 5.0 + p2 * 4.0 - p3) * t2
+        + (p1 * 3.0 - p0 - p2 * 3.0 + p3) * t3)
+        * 0.5
+}
+
+/// Smooth curves, emitted as line strips. `color` is sampled from 0 at the start to 1 at the end.
+#[allow(dead_code)]
+pub trait DebugCurves: LineSink {
+    /// `curve` maps a parameter in `t0..=t1` to a position and color.
+    fn pushParametric(
+        &mut self,
+        curve: impl Fn(f32) -> (Vector3, Vector3),
+        t0: f32,
+        t1: f32,
+        subdivision: &Subdivision,
+    ) {
+        let params = sampleParameters(&|t| curve(t).0, t0, t1, subdivision);
+        self.beginStrip();
+        for t in params {
+            let (pos, color) = curve(t);
+            self.vertex(pos, color);
+        }
+        self.endStrip();
+    }
+
+    fn pushQuadraticBezier(
+        &mut self,
+        [p0, p1, p2]: [Vector3; 3],
+        color: impl Fn(f32) -> Vector3,
+        subdivision: &Subdivision,
+    ) {
+        self.pushParametric(|t| (quadraticBezier(p0, p1, p2, t), color(t)), 0.0, 1.0, subdivision);
+    }
+
+    fn pushCubicBezier(
+        &mut self,
+        [p0, p1, p2, p3]: [Vector3; 4],
+        color: impl Fn(f32) -> Vector3,
+        subdivision: &Subdivision,
+    ) {
+        self.pushParametric(|t| (cubicBezier(p0, p1, p2, p3, t), color(t)), 0.0, 1.0, subdivision);
+    }
+
+    /// Spline through every point. Each span is subdivided on its own so control points are
+    /// always hit exactly.
+    fn pushCatmullRom(
+        &mut self,
+        points: &[Vector3],
+        closed: bool,
+        color: impl Fn(f32) -> Vector3,
+        subdivision: &Subdivision,
+    ) {
+        let n = points.len();
+        if n < 2 {
+            return;
+        }
+        let spans = if closed { n } else { n - 1 };
+        let at = |i: isize| {
+            if closed {
+                points[i.rem_euclid(n as isize) as usize]
+            } else {
+                points[i.clamp(0, n as isize - 1) as usize]
+            }
+        };
+        self.beginStrip();
+        for span in 0..spans {
+            let i = span as isize;
+            let (p0, p1, p2, p3) = (at(i - 1), at(i), at(i + 1), at(i + 2));
+            let eval = |t: f32| catmullRom(p0, p1, p2, p3, t);
+            let params = sampleParameters(&eval, 0.0, 1.0, subdivision);
+            // The first sample repeats the previous span's last one
+            let skip = if span == 0 { 0 } else { 1 };
+            for t in params.into_iter().skip(skip) {
+                self.vertex(eval(t), color((span as f32 + t) / spans as f32));
+            }
+        }
+        self.endStrip();
+    }
+}
+
+impl<T: LineSink + ?Sized> DebugCurves for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_renderer::LineBuffer;
+    use cgmath::{Deg, Matrix4, Point3, perspective, vec3};
+
+    const WHITE: Vector3 = Vector3::new(1.0, 1.0, 1.0);
+
+    fn screenSpace(pixels: f32) -> Subdivision {
+        let projection: Matrix4<f32> = perspective(Deg(60.0), 1.0, 0.1, 100.0);
+        let view = Matrix4::look_at_rh(Point3::new(0.0, 0.0, 5.0), Point3::new(0.0, 0.0, 0.0), Vector3::unit_y());
+        Subdivision::ScreenSpace {
+            viewProjection: projection * view,
+            viewport: (800.0, 800.0),
+            pixels,
+        }
+    }
+
+    fn vertices(buffer: &LineBuffer) -> Vec<(Vector3, Vector3)> {
+        buffer
+            .stripVertices()
+            .chunks(6)
+            .map(|f| (vec3(f[0], f[1], f[2]), vec3(f[3], f[4], f[5])))
+            .collect()
+    }
+
+    fn assertNear(a: Vector3, b: Vector3) {
+        assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn fixedSegmentsAreEvenlySpaced() {
+        let params = sampleParameters(&|t| vec3(t, 0.0, 0.0), 0.0, 2.0, &Subdivision::Segments(4));
+        assert_eq!(params, vec![0.0, 0.5, 1.0, 1.5, 2.0]);
+    }
+
+    #[test]
+    fn straightLinesStayCoarse() {
+        let params = sampleParameters(&|t| vec3(t, 0.0, 0.0), -1.0, 1.0, &screenSpace(0.5));
+        assert_eq!(params.len(), MIN_SPANS as usize + 1);
+    }
+
+    #[test]
+    fn adaptiveErrorIsWithinTolerance() {
+        let circle = |t: f32| vec3(t.cos(), t.sin(), 0.0);
+        let coarse = sampleParameters(&circle, 0.0, 6.0, &screenSpace(4.0));
+        let fine = sampleParameters(&circle, 0.0, 6.0, &screenSpace(0.25));
+        assert!(fine.len() > coarse.len() * 2);
+
+        let Subdivision::ScreenSpace {
+            viewProjection,
+            viewport,
+            ..
+        } = screenSpace(0.25)
+        else {
+            unreachable!()
+        };
+        for pair in fine.windows(2) {
+            let a = toScreen(&viewProjection, viewport, circle(pair[0])).unwrap();
+            let b = toScreen(&viewProjection, viewport, circle(pair[1])).unwrap();
+            let m = toScreen(&viewProjection, viewport, circle((pair[0] + pair[1]) * 0.5)).unwrap();
+            let chord = b - a;
+            let error = (chord.x * (m - a).y - chord.y * (m - a).x).abs() / chord.magnitude();
+            assert!(error <= 0.25 + 1e-3);
+        }
+    }
+
+    #[test]
+    fn behindCameraStopsRefining() {
+        let behind = |t: f32| vec3(t.cos(), t.sin(), 10.0);
+        let params = sampleParameters(&behind, 0.0, 6.0, &screenSpace(0.1));
+        assert!(params.len() <= (MIN_SPANS * (1 << MAX_HIDDEN_DEPTH)) as usize + 1);
+    }
+
+    #[test]
+    fn bezierEndsOnControlPoints() {
+        let mut buffer = LineBuffer::new();
+        let (p0, p3) = (vec3(0.0, 0.0, 0.0), vec3(3.0, 0.0, 0.0));
+        let red = vec3(1.0, 0.0, 0.0);
+        buffer.pushCubicBezier(
+            [p0, vec3(1.0, 2.0, 0.0), vec3(2.0, -2.0, 0.0), p3],
+            gradient(WHITE, red),
+            &Subdivision::Segments(16),
+        );
+        let verts = vertices(&buffer);
+        assert_eq!(verts.len(), 17);
+        assertNear(verts[0].0, p0);
+        assertNear(verts[16].0, p3);
+        assertNear(verts[0].1, WHITE);
+        assertNear(verts[16].1, red);
+        assertNear(verts[8].1, vec3(1.0, 0.5, 0.5));
+
+        let mut buffer = LineBuffer::new();
+        let mid = vec3(1.0, 2.0, 0.0);
+        buffer.pushQuadraticBezier([p0, mid, p3], solid(WHITE), &Subdivision::Segments(2));
+        let verts = vertices(&buffer);
+        // Halfway is the average of the chord midpoint and the control point
+        assertNear(verts[1].0, vec3(1.25, 1.0, 0.0));
+    }
+
+    #[test]
+    fn catmullRomPassesThroughPoints() {
+        let points = [
+            vec3(0.0, 0.0, 0.0),
+            vec3(1.0, 1.0, 0.0),
+            vec3(2.0, 0.0, 0.0),
+            vec3(3.0, 1.0, 0.0),
+        ];
+        let mut buffer = LineBuffer::new();
+        buffer.pushCatmullRom(&points, false, solid(WHITE), &Subdivision::Segments(8));
+        let verts = vertices(&buffer);
+        assert_eq!(verts.len(), 3 * 8 + 1);
+        for (i, p) in points.iter().enumerate() {
+            assertNear(verts[i * 8].0, *p);
+        }
+
+        let mut buffer = LineBuffer::new();
+        buffer.pushCatmullRom(&points, true, solid(WHITE), &Subdivision::Segments(8));
+        let verts = vertices(&buffer);
+        assert_eq!(verts.len(), 4 * 8 + 1);
+        assertNear(verts[32].0, points[0]);
+    }
+
+    #[test]
+    fn parametricUsesCurveColors() {
+        let mut buffer = LineBuffer::new();
+        buffer.pushParametric(
+            |t| (vec3(t, 0.0, 0.0), vec3(t, t, t)),
+            0.0,
+            1.0,
+            &Subdivision::Segments(4),
+        );
+        assert_eq!(buffer.segmentCount(), 4);
+        let verts = vertices(&buffer);
+        // The strip starts at t0, so the first segment isn't lost
+        assertNear(verts[0].0, vec3(0.0, 0.0, 0.0));
+        for (pos, color) in verts {
+            assertNear(color, vec3(pos.x, pos.x, pos.x));
+        }
+    }
+}
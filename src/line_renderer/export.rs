@@ -0,0 +1,258 @@
+#![allow(non_snake_case)]
+
+use super::{FLOATS, LineBuffer, STRIP_RESTART, Storage};
+use cgmath::{Vector4, vec2};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+type Vector2 = cgmath::Vector2<f32>;
+type Vector3 = cgmath::Vector3<f32>;
+type Matrix4 = cgmath::Matrix4<f32>;
+
+/// SVG background, light lines (the usual debug colors) vanish on white.
+const SVG_BACKGROUND: &str = "#202428";
+
+/// Lines of one frame as seen by the camera, from `LineRenderer::captureFrame`.
+pub struct FrameCapture {
+    pub lines: LineBuffer,
+    pub viewProjection: Matrix4,
+    pub viewport: (f32, f32),
+}
+
+impl FrameCapture {
+    /// Writes `<basePath>.svg`, `.obj` and `.ply`, returning the written paths.
+    pub fn save(&self, basePath: &str) -> io::Result<Vec<String>> {
+        let paths = ["svg", "obj", "ply"].map(|extension| format!("{}.{}", basePath, extension));
+        let mut svg = BufWriter::new(File::create(&paths[0])?);
+        writeSvg(&self.lines, &self.viewProjection, self.viewport, &mut svg)?;
+        svg.flush()?;
+        let mut obj = BufWriter::new(File::create(&paths[1])?);
+        writeObj(&self.lines, &mut obj)?;
+        obj.flush()?;
+        let mut ply = BufWriter::new(File::create(&paths[2])?);
+        writePly(&self.lines, &mut ply)?;
+        ply.flush()?;
+        Ok(paths.to_vec())
+    }
+}
+
+/// Clips the clip-space segment `a`..`b` to the view volume, returning the visible range
+/// of its parameter (Liang-Barsky against the six `-w <= x, y, z <= w` planes).
+fn clipSegment(a: Vector4<f32>, b: Vector4<f32>) -> Option<(f32, f32)> {
+    let planes = |v: Vector4<f32>| [v.w + v.x, v.w - v.x, v.w + v.y, v.w - v.y, v.w + v.z, v.w - v.z];
+    let (distA, distB) = (planes(a), planes(b));
+    let (mut t0, mut t1) = (0.0f32, 1.0f32);
+    for (da, db) in distA.into_iter().zip(distB) {
+        if da < 0.0 && db < 0.0 {
+            return None;
+        }
+        if da < 0.0 {
+            t0 = t0.max(da / (da - db));
+        } else if db < 0.0 {
+            t1 = t1.min(da / (da - db));
+        }
+    }
+    (t0 <= t1).then_some((t0, t1))
+}
+
+fn toPixels(clip: Vector4<f32>, viewport: (f32, f32)) -> Vector2 {
+    let w = clip.w.max(f32::EPSILON);
+    vec2((clip.x / w + 1.0) * 0.5 * viewport.0, (1.0 - clip.y / w) * 0.5 * viewport.1)
+}
+
+fn toByte(c: f32) -> u8 {
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Every segment projected through `viewProjection` onto a `viewport`-sized SVG. Segments
+/// are clipped to the view, each drawn in the average of its two vertex colors.
+pub fn writeSvg<V: Storage<f32>, I: Storage<u32>>(
+    lines: &LineBuffer<V, I>,
+    viewProjection: &Matrix4,
+    viewport: (f32, f32),
+    out: &mut impl Write,
+) -> io::Result<()> {
+    let (width, height) = viewport;
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = width,
+        h = height
+    )?;
+    writeln!(out, r#"<rect width="100%" height="100%" fill="{}"/>"#, SVG_BACKGROUND)?;
+    writeln!(out, r#"<g stroke-width="1" stroke-linecap="round">"#)?;
+    for (p1, c1, p2, c2) in lines.segments() {
+        let (a, b) = (viewProjection * p1.extend(1.0), viewProjection * p2.extend(1.0));
+        let Some((t0, t1)) = clipSegment(a, b) else {
+            continue;
+        };
+        let (s1, s2) = (toPixels(a + (b - a) * t0, viewport), toPixels(a + (b - a) * t1, viewport));
+        let color = (c1 + c2) * 0.5;
+        writeln!(
+            out,
+            r#"<line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" stroke="rgb({},{},{})"/>"#,
+            s1.x,
+            s1.y,
+            s2.x,
+            s2.y,
+            toByte(color.x),
+            toByte(color.y),
+            toByte(color.z)
+        )?;
+    }
+    writeln!(out, "</g>")?;
+    writeln!(out, "</svg>")
+}
+
+fn vertices(floats: &[f32]) -> impl Iterator<Item = (Vector3, Vector3)> + '_ {
+    floats
+        .chunks_exact(FLOATS)
+        .map(|v| (Vector3::new(v[0], v[1], v[2]), Vector3::new(v[3], v[4], v[5])))
+}
+
+/// World-space OBJ: vertices carry their color as `v x y z r g b`, plain lines become
+/// two-point `l` elements and strips one `l` polyline each.
+pub fn writeObj<V: Storage<f32>, I: Storage<u32>>(lines: &LineBuffer<V, I>, out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "# {} segments", lines.segmentCount())?;
+    for (p, c) in vertices(lines.floats()).chain(vertices(lines.stripVertices())) {
+        writeln!(out, "v {} {} {} {} {} {}", p.x, p.y, p.z, c.x, c.y, c.z)?;
+    }
+    // OBJ indices are 1-based
+    for i in 0..lines.lineCount() {
+        writeln!(out, "l {} {}", 2 * i + 1, 2 * i + 2)?;
+    }
+    let stripBase = 2 * lines.lineCount() + 1;
+    for strip in lines.stripIndices().split(|&i| i == STRIP_RESTART) {
+        if strip.len() < 2 {
+            continue;
+        }
+        let indices: Vec<String> = strip.iter().map(|&i| (i as usize + stripBase).to_string()).collect();
+        writeln!(out, "l {}", indices.join(" "))?;
+    }
+    Ok(())
+}
+
+/// World-space ASCII PLY with per-vertex 8-bit color and one edge per segment.
+pub fn writePly<V: Storage<f32>, I: Storage<u32>>(lines: &LineBuffer<V, I>, out: &mut impl Write) -> io::Result<()> {
+    let lineVertices = 2 * lines.lineCount();
+    let vertexCount = lineVertices + lines.stripVertices().len() / FLOATS;
+    let stripEdges: Vec<(usize, usize)> = lines
+        .stripIndices()
+        .windows(2)
+        .filter(|pair| pair[0] != STRIP_RESTART && pair[1] != STRIP_RESTART)
+        .map(|pair| (pair[0] as usize + lineVertices, pair[1] as usize + lineVertices))
+        .collect();
+
+    writeln!(out, "ply")?;
+    writeln!(out, "format ascii 1.0")?;
+    writeln!(out, "element vertex {}", vertexCount)?;
+    writeln!(out, "property float x\nproperty float y\nproperty float z")?;
+    writeln!(out, "property uchar red\nproperty uchar green\nproperty uchar blue")?;
+    writeln!(out, "element edge {}", lines.lineCount() + stripEdges.len())?;
+    writeln!(out, "property int vertex1\nproperty int vertex2")?;
+    writeln!(out, "end_header")?;
+    for (p, c) in vertices(lines.floats()).chain(vertices(lines.stripVertices())) {
+        writeln!(out, "{} {} {} {} {} {}", p.x, p.y, p.z, toByte(c.x), toByte(c.y), toByte(c.z))?;
+    }
+    for i in 0..lines.lineCount() {
+        writeln!(out, "{} {}", 2 * i, 2 * i + 1)?;
+    }
+    for (a, b) in stripEdges {
+        writeln!(out, "{} {}", a, b)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_renderer::LineSink;
+    use cgmath::{Deg, Point3, perspective, vec3};
+
+    const WHITE: Vector3 = Vector3::new(1.0, 1.0, 1.0);
+
+    fn camera() -> Matrix4 {
+        let projection = perspective(Deg(90.0), 1.0, 0.1, 100.0);
+        let view = Matrix4::look_at_rh(Point3::new(0.0, 0.0, 5.0), Point3::new(0.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0));
+        projection * view
+    }
+
+    fn sample() -> LineBuffer {
+        let mut lines = LineBuffer::new();
+        lines.pushLine(vec3(0.0, 0.0, 0.0), WHITE, vec3(1.0, 0.0, 0.0), vec3(1.0, 0.0, 0.0));
+        lines.beginStrip();
+        for i in 0..4 {
+            lines.vertex(vec3(0.0, i as f32, 0.0), WHITE);
+        }
+        lines.endLoop();
+        lines
+    }
+
+    fn svg(lines: &LineBuffer) -> String {
+        let mut out = Vec::new();
+        writeSvg(lines, &camera(), (200.0, 100.0), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn svgHasOneElementPerVisibleSegment() {
+        let text = svg(&sample());
+        assert_eq!(text.matches("<line ").count(), 5);
+        assert!(text.contains(r#"viewBox="0 0 200 100""#));
+        assert!(text.trim_end().ends_with("</svg>"));
+    }
+
+    #[test]
+    fn svgCullsSegmentsBehindTheCamera() {
+        let mut lines = LineBuffer::new();
+        lines.pushLine(vec3(0.0, 0.0, 6.0), WHITE, vec3(1.0, 0.0, 8.0), WHITE);
+        assert_eq!(svg(&lines).matches("<line ").count(), 0);
+    }
+
+    #[test]
+    fn svgClipsSegmentsCrossingTheNearPlane() {
+        let mut lines = LineBuffer::new();
+        lines.pushLine(vec3(0.0, 0.0, 0.0), WHITE, vec3(0.0, 0.0, 10.0), WHITE);
+        let text = svg(&lines);
+        assert_eq!(text.matches("<line ").count(), 1);
+        assert!(!text.contains("NaN") && !text.contains("inf"));
+    }
+
+    #[test]
+    fn clipKeepsInsideSegmentsWhole() {
+        let (a, b) = (Vector4::new(-0.5, 0.0, 0.0, 1.0), Vector4::new(0.5, 0.5, 0.0, 1.0));
+        assert_eq!(clipSegment(a, b), Some((0.0, 1.0)));
+        let (t0, t1) = clipSegment(Vector4::new(-3.0, 0.0, 0.0, 1.0), Vector4::new(1.0, 0.0, 0.0, 1.0)).unwrap();
+        assert!((t0 - 0.5).abs() < 1e-6 && t1 == 1.0);
+    }
+
+    #[test]
+    fn objWritesColoredVerticesAndPolylines() {
+        let mut out = Vec::new();
+        writeObj(&sample(), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let vertexLines: Vec<&str> = text.lines().filter(|l| l.starts_with("v ")).collect();
+        assert_eq!(vertexLines.len(), 2 + 4);
+        assert_eq!(vertexLines[1], "v 1 0 0 1 0 0");
+        let elements: Vec<&str> = text.lines().filter(|l| l.starts_with("l ")).collect();
+        // The loop repeats its first vertex to close
+        assert_eq!(elements, ["l 1 2", "l 3 4 5 6 3"]);
+    }
+
+    #[test]
+    fn plyCountsMatchTheBody() {
+        let mut out = Vec::new();
+        writePly(&sample(), &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.contains("element vertex 6\n"));
+        assert!(text.contains("element edge 5\n"));
+        let body: Vec<&str> = text.split("end_header\n").nth(1).unwrap().lines().collect();
+        assert_eq!(body.len(), 6 + 5);
+        assert_eq!(body[1], "1 0 0 255 0 0");
+        for edge in &body[6..] {
+            for index in edge.split(' ') {
+                assert!(index.parse::<usize>().unwrap() < 6);
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+#![allow(non_snake_case)]
+
+use cgmath::vec2;
+
+type Vector2 = cgmath::Vector2<f32>;
+type Vector3 = cgmath::Vector3<f32>;
+type Matrix4 = cgmath::Matrix4<f32>;
+
+/// Closest clip-space `w` a label is shown at, anything nearer is behind the camera.
+const MIN_W: f32 = 1e-3;
+
+/// Text anchored to a world position.
+pub struct Label {
+    pub pos: Vector3,
+    pub color: Vector3,
+    pub text: String,
+}
+
+/// A label's screen position in pixels (origin top-left), and its clip `w` for depth sorting.
+pub struct ProjectedLabel<'a> {
+    pub screen: Vector2,
+    pub depth: f32,
+    pub label: &'a Label,
+}
+
+/// Projects `pos` to pixel coordinates, or `None` when it is behind the camera or outside
+/// the viewport.
+pub fn projectToScreen(viewProjection: &Matrix4, viewport: (f32, f32), pos: Vector3) -> Option<(Vector2, f32)> {
+    let clip = viewProjection * pos.extend(1.0);
+    if clip.w < MIN_W {
+        return None;
+    }
+    let ndc = clip.truncate() / clip.w;
+    if ndc.x.abs() > 1.0 || ndc.y.abs() > 1.0 || ndc.z > 1.0 {
+        return None;
+    }
+    let screen = vec2((ndc.x + 1.0) * 0.5 * viewport.0, (1.0 - ndc.y) * 0.5 * viewport.1);
+    Some((screen, clip.w))
+}
+
+/// Labels queued for one frame.
+#[derive(Default)]
+pub struct Labels {
+    labels: Vec<Label>,
+}
+
+#[allow(dead_code)]
+impl Labels {
+    pub fn new() -> Labels {
+        Labels::default()
+    }
+
+    pub fn push(&mut self, pos: Vector3, color: Vector3, text: String) {
+        self.labels.push(Label { pos, color, text });
+    }
+
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    pub fn isEmpty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.labels.clear();
+    }
+
+    /// Visible labels, farthest first so nearer ones are drawn on top.
+    pub fn project(&self, viewProjection: &Matrix4, viewport: (f32, f32)) -> Vec<ProjectedLabel<'_>> {
+        let mut projected: Vec<ProjectedLabel> = self
+            .labels
+            .iter()
+            .filter_map(|label| {
+                let (screen, depth) = projectToScreen(viewProjection, viewport, label.pos)?;
+                Some(ProjectedLabel { screen, depth, label })
+            })
+            .collect();
+        projected.sort_by(|a, b| b.depth.total_cmp(&a.depth));
+        projected
+    }
+
+    /// Draws the visible labels behind all ImGui windows, then clears the queue.
+    pub fn draw(&mut self, ui: &imgui::Ui, viewProjection: &Matrix4, viewport: (f32, f32)) {
+        {
+            let drawList = ui.get_background_draw_list();
+            for projected in self.project(viewProjection, viewport) {
+                let color = projected.label.color;
+                // Offset a little up and right so the text doesn't cover the anchor point
+                let pos = [projected.screen.x + 4.0, projected.screen.y - ui.text_line_height() - 2.0];
+                drawList.add_text([pos[0] + 1.0, pos[1] + 1.0], [0.0, 0.0, 0.0, 0.75], &projected.label.text);
+                drawList.add_text(pos, [color.x, color.y, color.z, 1.0], &projected.label.text);
+            }
+        }
+        self.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Deg, Point3, perspective, vec3};
+
+    fn camera() -> Matrix4 {
+        let projection = perspective(Deg(90.0), 1.0, 0.1, 100.0);
+        let view = Matrix4::look_at_rh(Point3::new(0.0, 0.0, 5.0), Point3::new(0.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0));
+        projection * view
+    }
+
+    #[test]
+    fn centerProjectsToViewportCenter() {
+        let (screen, depth) = projectToScreen(&camera(), (800.0, 600.0), vec3(0.0, 0.0, 0.0)).unwrap();
+        assert!((screen.x - 400.0).abs() < 1e-3 && (screen.y - 300.0).abs() < 1e-3);
+        assert!((depth - 5.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn upIsTowardsTheTopOfTheScreen() {
+        let (screen, _) = projectToScreen(&camera(), (800.0, 600.0), vec3(0.0, 1.0, 0.0)).unwrap();
+        assert!(screen.y < 300.0);
+    }
+
+    #[test]
+    fn labelsBehindTheCameraAreCulled() {
+        assert!(projectToScreen(&camera(), (800.0, 600.0), vec3(0.0, 0.0, 10.0)).is_none());
+        assert!(projectToScreen(&camera(), (800.0, 600.0), vec3(0.0, 0.0, 5.0)).is_none());
+    }
+
+    #[test]
+    fn offscreenLabelsAreCulled() {
+        assert!(projectToScreen(&camera(), (800.0, 600.0), vec3(50.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn projectSortsFarthestFirst() {
+        let mut labels = Labels::new();
+        let white = vec3(1.0, 1.0, 1.0);
+        labels.push(vec3(0.0, 0.0, 2.0), white, "near".to_string());
+        labels.push(vec3(0.0, 0.0, -20.0), white, "far".to_string());
+        labels.push(vec3(0.0, 0.0, 9.0), white, "behind".to_string());
+        let projected = labels.project(&camera(), (800.0, 600.0));
+        let texts: Vec<&str> = projected.iter().map(|p| p.label.text.as_str()).collect();
+        assert_eq!(texts, ["far", "near"]);
+    }
+}
@@ -0,0 +1,181 @@
+#![allow(non_snake_case)]
+
+use super::LineSink;
+use cgmath::{vec2, vec3};
+use std::f32::consts::TAU;
+
+type Vector2 = cgmath::Vector2<f32>;
+type Vector3 = cgmath::Vector3<f32>;
+type Matrix4 = cgmath::Matrix4<f32>;
+
+/// Pixel coordinates (origin top-left, +Y down) to clip space, for a `width` x `height`
+/// framebuffer.
+pub fn pixelProjection(width: f32, height: f32) -> Matrix4 {
+    cgmath::ortho(0.0, width, height, 0.0, -1.0, 1.0)
+}
+
+/// NDC (origin centered, +Y up) to pixel coordinates, for mixing both in one overlay.
+pub fn ndcToPixels(ndc: Vector2, viewport: (f32, f32)) -> Vector2 {
+    vec2((ndc.x + 1.0) * 0.5 * viewport.0, (1.0 - ndc.y) * 0.5 * viewport.1)
+}
+
+/// Cursor position in window coordinates, as GLFW reports it, to pixel coordinates of a
+/// `viewport` sized framebuffer. The two differ on high-DPI displays.
+pub fn cursorToPixels(cursor: Vector2, windowSize: (f32, f32), viewport: (f32, f32)) -> Vector2 {
+    vec2(cursor.x * viewport.0 / windowSize.0.max(1.0), cursor.y * viewport.1 / windowSize.1.max(1.0))
+}
+
+fn point(p: Vector2) -> Vector3 {
+    vec3(p.x, p.y, 0.0)
+}
+
+/// 2D overlay shapes in pixel coordinates, see `LineRenderer::overlay`. Use `ndcToPixels`
+/// for positions in NDC.
+#[allow(dead_code)]
+pub trait Overlay2D: LineSink {
+    fn pushLine2D(&mut self, a: Vector2, b: Vector2, color: Vector3) {
+        self.pushLine(point(a), color, point(b), color);
+    }
+
+    fn pushPolyline2D(&mut self, points: &[Vector2], closed: bool, color: Vector3) {
+        self.beginStrip();
+        for p in points {
+            self.vertex(point(*p), color);
+        }
+        if closed {
+            self.endLoop();
+        } else {
+            self.endStrip();
+        }
+    }
+
+    fn pushRect(&mut self, min: Vector2, max: Vector2, color: Vector3) {
+        let corners = [min, vec2(max.x, min.y), max, vec2(min.x, max.y)];
+        self.pushPolyline2D(&corners, true, color);
+    }
+
+    fn pushCircle2D(&mut self, center: Vector2, radius: f32, segments: u32, color: Vector3) {
+        let segments = segments.max(3);
+        let points: Vec<Vector2> = (0..segments)
+            .map(|i| {
+                let a = TAU * i as f32 / segments as f32;
+                center + vec2(a.cos(), a.sin()) * radius
+            })
+            .collect();
+        self.pushPolyline2D(&points, true, color);
+    }
+
+    /// Four arms of length `size`, leaving `gap` pixels free around `center`.
+    fn pushCrosshair(&mut self, center: Vector2, size: f32, gap: f32, color: Vector3) {
+        for dir in [vec2(1.0, 0.0), vec2(-1.0, 0.0), vec2(0.0, 1.0), vec2(0.0, -1.0)] {
+            self.pushLine2D(center + dir * gap, center + dir * (gap + size), color);
+        }
+    }
+
+    /// Rectangle spanned by two drag corners in any order, with a tick at each corner.
+    fn pushSelectionBox(&mut self, from: Vector2, to: Vector2, color: Vector3) {
+        let min = vec2(from.x.min(to.x), from.y.min(to.y));
+        let max = vec2(from.x.max(to.x), from.y.max(to.y));
+        self.pushRect(min, max, color);
+        let tick = ((max.x - min.x).min(max.y - min.y) * 0.25).min(6.0);
+        for (corner, dir) in [
+            (min, vec2(-1.0, -1.0)),
+            (vec2(max.x, min.y), vec2(1.0, -1.0)),
+            (max, vec2(1.0, 1.0)),
+            (vec2(min.x, max.y), vec2(-1.0, 1.0)),
+        ] {
+            self.pushLine2D(corner, corner + dir * tick, color);
+        }
+    }
+
+    /// Plots `values` across the rectangle `min`..`max`, mapping `range` to its bottom and top
+    /// edges. Values outside the range are clamped to the frame.
+    fn pushGraph(&mut self, min: Vector2, max: Vector2, values: &[f32], range: (f32, f32), color: Vector3) {
+        self.pushRect(min, max, color * 0.5);
+        if values.len() < 2 {
+            return;
+        }
+        let span = (range.1 - range.0).max(f32::EPSILON);
+        let step = (max.x - min.x) / (values.len() - 1) as f32;
+        let points: Vec<Vector2> = values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let t = ((v - range.0) / span).clamp(0.0, 1.0);
+                vec2(min.x + step * i as f32, max.y + (min.y - max.y) * t)
+            })
+            .collect();
+        self.pushPolyline2D(&points, false, color);
+    }
+}
+
+impl<T: LineSink + ?Sized> Overlay2D for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_renderer::LineBuffer;
+    use cgmath::Vector4;
+
+    const WHITE: Vector3 = Vector3::new(1.0, 1.0, 1.0);
+
+    fn assertNear(a: Vector2, b: Vector2) {
+        assert!((a.x - b.x).abs() < 1e-4 && (a.y - b.y).abs() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn pixelProjectionMapsCornersToNdc() {
+        let projection = pixelProjection(800.0, 600.0);
+        let topLeft = projection * Vector4::new(0.0, 0.0, 0.0, 1.0);
+        let bottomRight = projection * Vector4::new(800.0, 600.0, 0.0, 1.0);
+        assertNear(vec2(topLeft.x, topLeft.y), vec2(-1.0, 1.0));
+        assertNear(vec2(bottomRight.x, bottomRight.y), vec2(1.0, -1.0));
+    }
+
+    #[test]
+    fn ndcRoundTripsThroughPixels() {
+        let projection = pixelProjection(640.0, 480.0);
+        let ndc = vec2(0.25, -0.5);
+        let pixels = ndcToPixels(ndc, (640.0, 480.0));
+        let back = projection * Vector4::new(pixels.x, pixels.y, 0.0, 1.0);
+        assertNear(vec2(back.x, back.y), ndc);
+    }
+
+    #[test]
+    fn cursorScalesToFramebufferPixels() {
+        assertNear(cursorToPixels(vec2(100.0, 50.0), (800.0, 600.0), (800.0, 600.0)), vec2(100.0, 50.0));
+        assertNear(cursorToPixels(vec2(100.0, 50.0), (800.0, 600.0), (1600.0, 1200.0)), vec2(200.0, 100.0));
+    }
+
+    #[test]
+    fn rectIsAClosedLoop() {
+        let mut lines = LineBuffer::new();
+        lines.pushRect(vec2(10.0, 10.0), vec2(20.0, 30.0), WHITE);
+        assert_eq!(lines.segmentCount(), 4);
+        assert!(lines.segments().all(|(a, _, b, _)| a.z == 0.0 && b.z == 0.0));
+    }
+
+    #[test]
+    fn selectionBoxAcceptsAnyCornerOrder() {
+        let mut forward = LineBuffer::new();
+        let mut backward = LineBuffer::new();
+        forward.pushSelectionBox(vec2(5.0, 5.0), vec2(50.0, 40.0), WHITE);
+        backward.pushSelectionBox(vec2(50.0, 40.0), vec2(5.0, 5.0), WHITE);
+        assert_eq!(forward.floats(), backward.floats());
+        assert_eq!(forward.stripVertices(), backward.stripVertices());
+    }
+
+    #[test]
+    fn graphStaysInsideItsFrame() {
+        let mut lines = LineBuffer::new();
+        let (min, max) = (vec2(0.0, 100.0), vec2(200.0, 150.0));
+        lines.pushGraph(min, max, &[-5.0, 0.0, 0.5, 1.0, 9.0], (0.0, 1.0), WHITE);
+        // Frame loop plus one segment between each pair of samples
+        assert_eq!(lines.segmentCount(), 4 + 4);
+        for (a, _, b, _) in lines.segments() {
+            for p in [a, b] {
+                assert!(p.x >= min.x && p.x <= max.x && p.y >= min.y && p.y <= max.y, "{:?}", p);
+            }
+        }
+    }
+}
@@ -0,0 +1,193 @@
+#![allow(non_snake_case)]
+
+use super::{DEPTH_MODES, DepthMode, LineBuffer, LineSink};
+use std::collections::BTreeMap;
+
+/// Identifies a group of retained lines, returned by `LineRenderer::retain`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct LineHandle(u32);
+
+struct Entry {
+    lines: LineBuffer,
+    mode: DepthMode,
+    remaining: Option<f32>,
+}
+
+/// Lines that outlive a frame, either until removed or for a number of seconds.
+///
+/// All groups are merged into one buffer per depth mode, which is only rebuilt when a group is added,
+/// removed or expires, so static geometry isn't re-uploaded every frame.
+pub struct RetainedLines {
+    entries: BTreeMap<u32, Entry>,
+    nextId: u32,
+    changed: bool,
+}
+
+#[allow(dead_code)]
+impl RetainedLines {
+    pub fn new() -> RetainedLines {
+        RetainedLines {
+            entries: BTreeMap::new(),
+            nextId: 0,
+            changed: false,
+        }
+    }
+
+    /// Adds a group, `duration` in seconds or `None` to keep it until removed.
+    pub fn add(&mut self, mut lines: LineBuffer, mode: DepthMode, duration: Option<f32>) -> LineHandle {
+        lines.endStrip();
+        let id = self.nextId;
+        self.nextId = self.nextId.wrapping_add(1);
+        self.entries.insert(
+            id,
+            Entry {
+                lines,
+                mode,
+                remaining: duration,
+            },
+        );
+        self.changed = true;
+        LineHandle(id)
+    }
+
+    pub fn remove(&mut self, handle: LineHandle) -> bool {
+        let removed = self.entries.remove(&handle.0).is_some();
+        self.changed |= removed;
+        removed
+    }
+
+    pub fn contains(&self, handle: LineHandle) -> bool {
+        self.entries.contains_key(&handle.0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn isEmpty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.changed |= !self.entries.is_empty();
+        self.entries.clear();
+    }
+
+    /// Ages timed groups by `dt` seconds and drops the ones that ran out.
+    pub fn advance(&mut self, dt: f32) {
+        let before = self.entries.len();
+        self.entries.retain(|_, entry| match entry.remaining.as_mut() {
+            Some(remaining) => {
+                *remaining -= dt;
+                *remaining > 0.0
+            }
+            None => true,
+        });
+        self.changed |= self.entries.len() != before;
+    }
+
+    /// Appends every group, regardless of depth mode, to `out`.
+    pub fn appendTo(&self, out: &mut LineBuffer) {
+        for entry in self.entries.values() {
+            out.append(&entry.lines);
+        }
+    }
+
+    /// Returns every group merged by depth mode if anything changed since the last call.
+    pub fn takeChanged(&mut self) -> Option<[LineBuffer; DEPTH_MODES]> {
+        if !self.changed {
+            return None;
+        }
+        self.changed = false;
+        let mut merged: [LineBuffer; DEPTH_MODES] = Default::default();
+        for entry in self.entries.values() {
+            merged[entry.mode as usize].append(&entry.lines);
+        }
+        Some(merged)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec3;
+
+    type Vector3 = cgmath::Vector3<f32>;
+
+    const WHITE: Vector3 = Vector3::new(1.0, 1.0, 1.0);
+
+    fn segmentCount(merged: Option<[LineBuffer; DEPTH_MODES]>) -> usize {
+        merged.unwrap().iter().map(|b| b.segmentCount()).sum()
+    }
+
+    fn lines(count: usize) -> LineBuffer {
+        let mut buffer = LineBuffer::new();
+        for i in 0..count {
+            buffer.pushLine(vec3(i as f32, 0.0, 0.0), WHITE, vec3(i as f32, 1.0, 0.0), WHITE);
+        }
+        buffer
+    }
+
+    #[test]
+    fn onlyRebuildsWhenChanged() {
+        let mut retained = RetainedLines::new();
+        assert!(retained.takeChanged().is_none());
+
+        let a = retained.add(lines(2), DepthMode::Tested, None);
+        retained.add(lines(3), DepthMode::Tested, None);
+        assert_eq!(segmentCount(retained.takeChanged()), 5);
+        assert!(retained.takeChanged().is_none());
+
+        retained.advance(10.0);
+        assert!(retained.takeChanged().is_none());
+
+        assert!(retained.remove(a));
+        assert!(!retained.remove(a));
+        assert_eq!(segmentCount(retained.takeChanged()), 3);
+    }
+
+    #[test]
+    fn timedLinesExpire() {
+        let mut retained = RetainedLines::new();
+        let forever = retained.add(lines(1), DepthMode::Tested, None);
+        let short = retained.add(lines(1), DepthMode::Tested, Some(0.5));
+        let long = retained.add(lines(1), DepthMode::Tested, Some(2.0));
+        retained.takeChanged();
+
+        retained.advance(0.25);
+        assert!(retained.takeChanged().is_none());
+        assert_eq!(retained.len(), 3);
+
+        retained.advance(0.5);
+        assert!(!retained.contains(short));
+        assert!(retained.contains(long));
+        assert_eq!(segmentCount(retained.takeChanged()), 2);
+
+        retained.advance(5.0);
+        assert!(retained.contains(forever));
+        assert!(!retained.contains(long));
+    }
+
+    #[test]
+    fn mergesByDepthMode() {
+        let mut retained = RetainedLines::new();
+        retained.add(lines(1), DepthMode::Tested, None);
+        retained.add(lines(2), DepthMode::Overlay, None);
+        retained.add(lines(3), DepthMode::Overlay, Some(1.0));
+        let merged = retained.takeChanged().unwrap();
+        assert_eq!(merged[DepthMode::Tested as usize].segmentCount(), 1);
+        assert_eq!(merged[DepthMode::XRay as usize].segmentCount(), 0);
+        assert_eq!(merged[DepthMode::Overlay as usize].segmentCount(), 5);
+    }
+
+    #[test]
+    fn clearDropsEverything() {
+        let mut retained = RetainedLines::new();
+        retained.clear();
+        assert!(retained.takeChanged().is_none());
+        retained.add(lines(4), DepthMode::Tested, None);
+        retained.clear();
+        assert!(retained.isEmpty());
+        assert_eq!(segmentCount(retained.takeChanged()), 0);
+    }
+}
@@ -0,0 +1,445 @@
+#![allow(non_snake_case)]
+
+use super::LineSink;
+use cgmath::{InnerSpace, Rad, SquareMatrix, vec3};
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+type Vector3 = cgmath::Vector3<f32>;
+type Matrix4 = cgmath::Matrix4<f32>;
+
+const RED: Vector3 = Vector3::new(1.0, 0.0, 0.0);
+const GREEN: Vector3 = Vector3::new(0.0, 1.0, 0.0);
+const BLUE: Vector3 = Vector3::new(0.0, 0.0, 1.0);
+
+/// Transforms a point, dividing by `w` so projective matrices (frusta) work as well.
+pub fn transformPoint(transform: &Matrix4, p: Vector3) -> Vector3 {
+    let v = transform * p.extend(1.0);
+    v.truncate() / v.w
+}
+
+#[allow(dead_code)]
+fn arcPoints(radius: f32, startAngle: f32, endAngle: f32, segments: u32) -> Vec<Vector3> {
+    let segments = segments.max(1);
+    let step = (endAngle - startAngle) / segments as f32;
+    (0..=segments)
+        .map(|i| {
+            let a = startAngle + step * i as f32;
+            vec3(radius * a.cos(), 0.0, radius * a.sin())
+        })
+        .collect()
+}
+
+/// Wireframe debug shapes, built from line segments.
+///
+/// Everything is generated in local space and moved by `transform`. Circles and arcs lie in
+/// the local XZ plane, and round shapes (cylinder, cone, capsule) are aligned with local +Y.
+#[allow(dead_code)]
+pub trait DebugShapes: LineSink {
+    fn pushSegment(&mut self, transform: &Matrix4, a: Vector3, b: Vector3, color: Vector3) {
+        self.pushLine(
+            transformPoint(transform, a),
+            color,
+            transformPoint(transform, b),
+            color,
+        );
+    }
+
+    /// Connected points, sent as a single strip so shared vertices are stored once.
+    fn pushPolyline(&mut self, transform: &Matrix4, points: &[Vector3], closed: bool, color: Vector3) {
+        self.beginStrip();
+        for p in points {
+            self.vertex(transformPoint(transform, *p), color);
+        }
+        if closed {
+            self.endLoop();
+        } else {
+            self.endStrip();
+        }
+    }
+
+    fn pushAabb(&mut self, transform: &Matrix4, min: Vector3, max: Vector3, color: Vector3) {
+        // Corner bits: 1 = max.x, 2 = max.y, 4 = max.z. Edges join corners one bit apart.
+        let corner = |i: usize| {
+            vec3(
+                if i & 1 != 0 { max.x } else { min.x },
+                if i & 2 != 0 { max.y } else { min.y },
+                if i & 4 != 0 { max.z } else { min.z },
+            )
+        };
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.pushSegment(transform, corner(i), corner(i | bit), color);
+                }
+            }
+        }
+    }
+
+    /// Box centered on the transform's origin, oriented by its rotation.
+    fn pushBox(&mut self, transform: &Matrix4, halfExtents: Vector3, color: Vector3) {
+        self.pushAabb(transform, -halfExtents, halfExtents, color);
+    }
+
+    fn pushArc(
+        &mut self,
+        transform: &Matrix4,
+        radius: f32,
+        startAngle: f32,
+        endAngle: f32,
+        segments: u32,
+        color: Vector3,
+    ) {
+        let points = arcPoints(radius, startAngle, endAngle, segments);
+        self.pushPolyline(transform, &points, false, color);
+    }
+
+    fn pushCircle(&mut self, transform: &Matrix4, radius: f32, segments: u32, color: Vector3) {
+        let mut points = arcPoints(radius, 0.0, TAU, segments.max(3));
+        points.pop();
+        self.pushPolyline(transform, &points, true, color);
+    }
+
+    /// Three great circles, one per local plane.
+    fn pushSphere(&mut self, transform: &Matrix4, radius: f32, segments: u32, color: Vector3) {
+        self.pushCircle(transform, radius, segments, color);
+        self.pushCircle(
+            &(transform * Matrix4::from_angle_x(Rad(FRAC_PI_2))),
+            radius,
+            segments,
+            color,
+        );
+        self.pushCircle(
+            &(transform * Matrix4::from_angle_z(Rad(FRAC_PI_2))),
+            radius,
+            segments,
+            color,
+        );
+    }
+
+    /// Cylinder centered on the origin, `height` tall along +Y.
+    fn pushCylinder(
+        &mut self,
+        transform: &Matrix4,
+        radius: f32,
+        height: f32,
+        segments: u32,
+        color: Vector3,
+    ) {
+        let h = height * 0.5;
+        self.pushCircle(
+            &(transform * Matrix4::from_translation(vec3(0.0, h, 0.0))),
+            radius,
+            segments,
+            color,
+        );
+        self.pushCircle(
+            &(transform * Matrix4::from_translation(vec3(0.0, -h, 0.0))),
+            radius,
+            segments,
+            color,
+        );
+        for p in arcPoints(radius, 0.0, TAU, 4).iter().take(4) {
+            self.pushSegment(transform, vec3(p.x, -h, p.z), vec3(p.x, h, p.z), color);
+        }
+    }
+
+    /// Cone with its base on the origin and its apex `height` along +Y.
+    fn pushCone(
+        &mut self,
+        transform: &Matrix4,
+        radius: f32,
+        height: f32,
+        segments: u32,
+        color: Vector3,
+    ) {
+        self.pushCircle(transform, radius, segments, color);
+        let apex = vec3(0.0, height, 0.0);
+        for p in arcPoints(radius, 0.0, TAU, 4).iter().take(4) {
+            self.pushSegment(transform, *p, apex, color);
+        }
+    }
+
+    /// Capsule whose hemisphere centers are `height` apart along Y.
+    fn pushCapsule(
+        &mut self,
+        transform: &Matrix4,
+        radius: f32,
+        height: f32,
+        segments: u32,
+        color: Vector3,
+    ) {
+        self.pushCylinder(transform, radius, height, segments, color);
+        let h = height * 0.5;
+        let half = (segments / 2).max(1);
+        // Rotating the XZ arc by -90° around X puts it in the XY plane, the extra Y turn in ZY.
+        let xy = Matrix4::from_angle_x(Rad(-FRAC_PI_2));
+        let zy = Matrix4::from_angle_y(Rad(FRAC_PI_2)) * xy;
+        for plane in [xy, zy] {
+            let top = transform * Matrix4::from_translation(vec3(0.0, h, 0.0)) * plane;
+            let bottom = transform * Matrix4::from_translation(vec3(0.0, -h, 0.0)) * plane;
+            self.pushArc(&top, radius, 0.0, PI, half, color);
+            self.pushArc(&bottom, radius, PI, TAU, half, color);
+        }
+    }
+
+    fn pushArrow(
+        &mut self,
+        transform: &Matrix4,
+        from: Vector3,
+        to: Vector3,
+        headSize: f32,
+        color: Vector3,
+    ) {
+        self.pushSegment(transform, from, to, color);
+        let dir = to - from;
+        if dir.magnitude2() <= f32::EPSILON {
+            return;
+        }
+        let dir = dir.normalize();
+        let up = if dir.y.abs() < 0.99 {
+            Vector3::unit_y()
+        } else {
+            Vector3::unit_x()
+        };
+        let side = dir.cross(up).normalize() * headSize * 0.5;
+        let up = side.cross(dir);
+        let base = to - dir * headSize;
+        for offset in [side, -side, up, -up] {
+            self.pushSegment(transform, to, base + offset, color);
+        }
+    }
+
+    /// Red/green/blue lines along the local X/Y/Z axes.
+    fn pushAxes(&mut self, transform: &Matrix4, size: f32) {
+        let o = vec3(0.0, 0.0, 0.0);
+        self.pushSegment(transform, o, vec3(size, 0.0, 0.0), RED);
+        self.pushSegment(transform, o, vec3(0.0, size, 0.0), GREEN);
+        self.pushSegment(transform, o, vec3(0.0, 0.0, size), BLUE);
+    }
+
+    /// Square grid on the local XZ plane, `halfCells` cells out from the origin in each direction.
+    fn pushGrid(&mut self, transform: &Matrix4, halfCells: u32, spacing: f32, color: Vector3) {
+        let n = halfCells as i32;
+        let extent = n as f32 * spacing;
+        for i in -n..=n {
+            let d = i as f32 * spacing;
+            self.pushSegment(
+                transform,
+                vec3(-extent, 0.0, d),
+                vec3(extent, 0.0, d),
+                color,
+            );
+            self.pushSegment(
+                transform,
+                vec3(d, 0.0, -extent),
+                vec3(d, 0.0, extent),
+                color,
+            );
+        }
+    }
+
+    /// Outline of the volume seen through `viewProjection` (e.g. `projection * view`).
+    fn pushFrustum(&mut self, viewProjection: &Matrix4, color: Vector3) {
+        if let Some(inverse) = viewProjection.invert() {
+            // The NDC cube mapped back through the inverse is the frustum.
+            self.pushAabb(&inverse, vec3(-1.0, -1.0, -1.0), vec3(1.0, 1.0, 1.0), color);
+        }
+    }
+
+    /// Translate arrows and rotate rings for each local axis.
+    fn pushGizmo(&mut self, transform: &Matrix4, size: f32) {
+        let o = vec3(0.0, 0.0, 0.0);
+        let head = size * 0.2;
+        let ring = size * 0.8;
+        self.pushArrow(transform, o, vec3(size, 0.0, 0.0), head, RED);
+        self.pushArrow(transform, o, vec3(0.0, size, 0.0), head, GREEN);
+        self.pushArrow(transform, o, vec3(0.0, 0.0, size), head, BLUE);
+        self.pushCircle(
+            &(transform * Matrix4::from_angle_z(Rad(FRAC_PI_2))),
+            ring,
+            32,
+            RED,
+        );
+        self.pushCircle(transform, ring, 32, GREEN);
+        self.pushCircle(
+            &(transform * Matrix4::from_angle_x(Rad(FRAC_PI_2))),
+            ring,
+            32,
+            BLUE,
+        );
+    }
+}
+
+impl<T: LineSink + ?Sized> DebugShapes for T {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::line_renderer::LineBuffer;
+    use cgmath::{Deg, perspective};
+
+    const WHITE: Vector3 = Vector3::new(1.0, 1.0, 1.0);
+
+    fn assertNear(a: Vector3, b: Vector3) {
+        assert!((a - b).magnitude() < 1e-4, "{:?} != {:?}", a, b);
+    }
+
+    fn points(buffer: &LineBuffer) -> Vec<Vector3> {
+        buffer.segments().flat_map(|(p1, _, p2, _)| [p1, p2]).collect()
+    }
+
+    fn segment(buffer: &LineBuffer, index: usize) -> (Vector3, Vector3, Vector3, Vector3) {
+        buffer.segments().nth(index).unwrap()
+    }
+
+    #[test]
+    fn aabbHasTwelveAxisAlignedEdges() {
+        let mut buffer = LineBuffer::new();
+        let min = vec3(-1.0, -2.0, -3.0);
+        let max = vec3(1.0, 2.0, 3.0);
+        buffer.pushAabb(&Matrix4::identity(), min, max, WHITE);
+        assert_eq!(buffer.segmentCount(), 12);
+        for i in 0..12 {
+            let (p1, c1, p2, c2) = segment(&buffer, i);
+            assert_eq!(c1, WHITE);
+            assert_eq!(c2, WHITE);
+            let d = p2 - p1;
+            let axes = [d.x, d.y, d.z].iter().filter(|v| v.abs() > 0.0).count();
+            assert_eq!(axes, 1);
+        }
+        for p in points(&buffer) {
+            assert!(p.x.abs() == 1.0 && p.y.abs() == 2.0 && p.z.abs() == 3.0);
+        }
+    }
+
+    #[test]
+    fn orientedBoxFollowsTransform() {
+        let mut buffer = LineBuffer::new();
+        let transform =
+            Matrix4::from_translation(vec3(5.0, 0.0, 0.0)) * Matrix4::from_angle_y(Deg(45.0));
+        buffer.pushBox(&transform, vec3(1.0, 1.0, 1.0), WHITE);
+        assert_eq!(buffer.segmentCount(), 12);
+        let corner = 2.0f32.sqrt();
+        for p in points(&buffer) {
+            let local = p - vec3(5.0, 0.0, 0.0);
+            assertNear(
+                vec3(local.x.abs() + local.z.abs(), local.y.abs(), 0.0),
+                vec3(corner, 1.0, 0.0),
+            );
+        }
+    }
+
+    #[test]
+    fn circleAndSpherePointsLieOnRadius() {
+        let mut buffer = LineBuffer::new();
+        buffer.pushCircle(&Matrix4::identity(), 2.0, 16, WHITE);
+        assert_eq!(buffer.segmentCount(), 16);
+        for p in points(&buffer) {
+            assert!((p.magnitude() - 2.0).abs() < 1e-4);
+            assert_eq!(p.y, 0.0);
+        }
+
+        let mut buffer = LineBuffer::new();
+        let center = vec3(1.0, 2.0, 3.0);
+        buffer.pushSphere(&Matrix4::from_translation(center), 0.5, 12, WHITE);
+        assert_eq!(buffer.segmentCount(), 36);
+        for p in points(&buffer) {
+            assert!(((p - center).magnitude() - 0.5).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn arcStartsAndEndsAtAngles() {
+        let mut buffer = LineBuffer::new();
+        buffer.pushArc(&Matrix4::identity(), 1.0, 0.0, FRAC_PI_2, 8, WHITE);
+        assert_eq!(buffer.segmentCount(), 8);
+        assertNear(segment(&buffer, 0).0, vec3(1.0, 0.0, 0.0));
+        assertNear(segment(&buffer, 7).2, vec3(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn roundShapesStayWithinBounds() {
+        let mut buffer = LineBuffer::new();
+        buffer.pushCylinder(&Matrix4::identity(), 1.0, 2.0, 16, WHITE);
+        assert_eq!(buffer.segmentCount(), 16 * 2 + 4);
+        for p in points(&buffer) {
+            assert!(p.y.abs() <= 1.0 + 1e-4);
+            assert!(vec3(p.x, 0.0, p.z).magnitude() <= 1.0 + 1e-4);
+        }
+
+        let mut buffer = LineBuffer::new();
+        buffer.pushCone(&Matrix4::identity(), 1.0, 3.0, 16, WHITE);
+        assert_eq!(buffer.segmentCount(), 16 + 4);
+        // Plain lines come before strips
+        for i in 0..4 {
+            assertNear(segment(&buffer, i).2, vec3(0.0, 3.0, 0.0));
+        }
+
+        let mut buffer = LineBuffer::new();
+        buffer.pushCapsule(&Matrix4::identity(), 0.5, 2.0, 16, WHITE);
+        for p in points(&buffer) {
+            // Distance to the inner segment is at most the radius
+            let closest = vec3(0.0, p.y.clamp(-1.0, 1.0), 0.0);
+            assert!((p - closest).magnitude() <= 0.5 + 1e-4);
+        }
+        let top = points(&buffer).iter().map(|p| p.y).fold(f32::MIN, f32::max);
+        assert!((top - 1.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn arrowEndsAtTipWithHead() {
+        let mut buffer = LineBuffer::new();
+        let to = vec3(0.0, 0.0, 2.0);
+        buffer.pushArrow(&Matrix4::identity(), vec3(0.0, 0.0, 0.0), to, 0.5, WHITE);
+        assert_eq!(buffer.segmentCount(), 5);
+        for i in 1..5 {
+            let (tip, _, base, _) = segment(&buffer, i);
+            assertNear(tip, to);
+            assert!((base.z - 1.5).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn axesAreColoredPerAxis() {
+        let mut buffer = LineBuffer::new();
+        buffer.pushAxes(&Matrix4::identity(), 2.0);
+        assert_eq!(buffer.segmentCount(), 3);
+        assert_eq!(segment(&buffer, 0).1, RED);
+        assertNear(segment(&buffer, 0).2, vec3(2.0, 0.0, 0.0));
+        assertNear(segment(&buffer, 1).2, vec3(0.0, 2.0, 0.0));
+        assertNear(segment(&buffer, 2).2, vec3(0.0, 0.0, 2.0));
+    }
+
+    #[test]
+    fn gridCoversExtent() {
+        let mut buffer = LineBuffer::new();
+        buffer.pushGrid(&Matrix4::identity(), 5, 0.5, WHITE);
+        assert_eq!(buffer.segmentCount(), 2 * 11);
+        for p in points(&buffer) {
+            assert_eq!(p.y, 0.0);
+            assert!(p.x.abs() <= 2.5 && p.z.abs() <= 2.5);
+        }
+    }
+
+    #[test]
+    fn frustumCornersMatchPlanes() {
+        let mut buffer = LineBuffer::new();
+        let projection: Matrix4 = perspective(Deg(90.0), 1.0, 1.0, 10.0);
+        buffer.pushFrustum(&projection, WHITE);
+        assert_eq!(buffer.segmentCount(), 12);
+        for p in points(&buffer) {
+            let depth = -p.z;
+            assert!((depth - 1.0).abs() < 1e-3 || (depth - 10.0).abs() < 1e-3);
+            // 90° fov with aspect 1 means the corners sit at |x| = |y| = depth
+            assert!((p.x.abs() - depth).abs() < 1e-3);
+            assert!((p.y.abs() - depth).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn gizmoHasArrowsAndRings() {
+        let mut buffer = LineBuffer::new();
+        buffer.pushGizmo(&Matrix4::identity(), 1.0);
+        assert_eq!(buffer.segmentCount(), 3 * 5 + 3 * 32);
+    }
+}
@@ -0,0 +1,246 @@
+#![allow(non_snake_case)]
+
+use super::{FLOAT_SIZE, FLOATS, LineBuffer, LineSink, Storage, setupVertexArray};
+use crate::ring_buffer::{PersistentRing, SEGMENTS};
+use crate::shader::Shader;
+use gl::types::{GLintptr, GLsizei, GLsizeiptr, GLuint};
+use std::cell::OnceCell;
+use std::marker::PhantomData;
+use std::os::raw::c_void;
+use std::ptr;
+
+/// `Storage` living in the current segment of a `PersistentRing`, so pushes write straight
+/// into GPU-visible memory. A frame that doesn't fit carries on in a CPU-side spill, and the
+/// ring grows at the next frame boundary.
+pub struct MappedStorage<T> {
+    ring: PersistentRing,
+    len: usize,
+    /// All of this segment's values, once they outgrew it.
+    spill: Option<Vec<T>>,
+    /// Most values held since the last `nextFrame`, the capacity to grow to.
+    peak: usize,
+    /// Values read back for `asSlice`, the mapping being write-only.
+    readBack: OnceCell<Vec<T>>,
+    _marker: PhantomData<T>,
+}
+
+pub type MappedLineBuffer = LineBuffer<MappedStorage<f32>, MappedStorage<u32>>;
+
+#[allow(dead_code)]
+impl<T: Copy> MappedStorage<T> {
+    pub fn new(capacity: usize) -> MappedStorage<T> {
+        MappedStorage {
+            ring: PersistentRing::new(capacity * size_of::<T>()),
+            len: 0,
+            spill: None,
+            peak: 0,
+            readBack: OnceCell::new(),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.ring.segmentBytes() / size_of::<T>()
+    }
+
+    /// Size of the whole ring, all segments included.
+    pub fn allocatedBytes(&self) -> usize {
+        self.ring.segmentBytes() * SEGMENTS
+    }
+
+    pub fn buffer(&self) -> GLuint {
+        self.ring.buffer()
+    }
+
+    /// Calls `draw` with the buffer and byte offset holding this segment's values. A spill is
+    /// copied into a temporary buffer for it.
+    pub fn withSource(&self, draw: impl FnOnce(GLuint, usize)) {
+        let Some(spill) = &self.spill else {
+            self.ring.flush(self.len * size_of::<T>());
+            draw(self.ring.buffer(), self.ring.segmentOffset());
+            return;
+        };
+        unsafe {
+            let mut buffer = 0;
+            gl::CreateBuffers(1, &mut buffer);
+            gl::NamedBufferStorage(
+                buffer,
+                size_of_val(spill.as_slice()) as GLsizeiptr,
+                spill.as_ptr() as *const c_void,
+                0,
+            );
+            draw(buffer, 0);
+            // Deletion is deferred by the driver until the draw is done with it
+            gl::DeleteBuffers(1, &buffer);
+        }
+    }
+
+    /// Submits this frame's segment and starts the next one empty. The ring is only replaced
+    /// here, after waiting for the next segment, so a frame never spans two rings.
+    pub fn nextFrame(&mut self) {
+        self.ring.advance();
+        if self.peak > self.capacity() {
+            let capacity = self.peak.max(self.capacity() * 2);
+            // Deletion is deferred by the driver until in-flight draws are done with it
+            self.ring.destroy();
+            self.ring = PersistentRing::new(capacity * size_of::<T>());
+        }
+        self.len = 0;
+        self.peak = 0;
+        self.spill = None;
+        self.readBack.take();
+    }
+
+    /// This segment's values, read through GL.
+    fn readMapped(&self) -> Vec<T> {
+        let mut values = Vec::with_capacity(self.len);
+        if self.len == 0 {
+            return values;
+        }
+        let bytes = self.len * size_of::<T>();
+        self.ring.flush(bytes);
+        unsafe {
+            gl::GetNamedBufferSubData(
+                self.ring.buffer(),
+                self.ring.segmentOffset() as GLintptr,
+                bytes as GLsizeiptr,
+                values.as_mut_ptr() as *mut c_void,
+            );
+            values.set_len(self.len);
+        }
+        values
+    }
+
+    pub fn destroy(&mut self) {
+        self.ring.destroy();
+    }
+}
+
+impl<T: Copy> Storage<T> for MappedStorage<T> {
+    fn asSlice(&self) -> &[T] {
+        match &self.spill {
+            Some(spill) => spill,
+            None => self.readBack.get_or_init(|| self.readMapped()),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn extend(&mut self, values: &[T]) {
+        self.readBack.take();
+        if self.spill.is_none() && self.len + values.len() > self.capacity() {
+            self.spill = Some(self.readMapped());
+        }
+        match &mut self.spill {
+            Some(spill) => spill.extend_from_slice(values),
+            None => unsafe {
+                let dst = (self.ring.segmentPtr() as *mut T).add(self.len);
+                ptr::copy_nonoverlapping(values.as_ptr(), dst, values.len());
+            },
+        }
+        self.len += values.len();
+        self.peak = self.peak.max(self.len);
+    }
+
+    fn truncate(&mut self, len: usize) {
+        self.readBack.take();
+        self.len = self.len.min(len);
+        match &mut self.spill {
+            Some(_) if self.len == 0 => self.spill = None,
+            Some(spill) => spill.truncate(self.len),
+            None => {}
+        }
+    }
+}
+
+/// Per-frame lines written into persistently mapped, triple-buffered memory.
+pub struct StreamLines {
+    pub lines: MappedLineBuffer,
+    vao: GLuint,
+    stripVao: GLuint,
+}
+
+#[allow(dead_code)]
+impl StreamLines {
+    /// Sized for `capacityLines` separate lines, or as many strip segments.
+    pub fn new(shader: &Shader, capacityLines: usize) -> StreamLines {
+        let mut stream = StreamLines {
+            lines: LineBuffer::fromStorage(
+                MappedStorage::new(capacityLines * FLOATS * 2),
+                MappedStorage::new(capacityLines * FLOATS),
+                MappedStorage::new(capacityLines * 2),
+            ),
+            vao: 0,
+            stripVao: 0,
+        };
+        unsafe {
+            gl::CreateVertexArrays(1, &mut stream.vao);
+            gl::CreateVertexArrays(1, &mut stream.stripVao);
+            setupVertexArray(shader, stream.vao, stream.lines.vec.buffer());
+            setupVertexArray(shader, stream.stripVao, stream.lines.stripVertices.buffer());
+        }
+        stream
+    }
+
+    /// Separate lines that fit before the buffers grow.
+    pub fn capacityLines(&self) -> usize {
+        self.lines.vec.capacity() / (FLOATS * 2)
+    }
+
+    pub fn allocatedBytes(&self) -> usize {
+        let lines = &self.lines;
+        lines.vec.allocatedBytes() + lines.stripVertices.allocatedBytes() + lines.stripIndices.allocatedBytes()
+    }
+
+    pub fn draw(&self) {
+        let stride = (FLOATS * FLOAT_SIZE) as GLsizei;
+        let lineVertices = self.lines.vec.len() / FLOATS;
+        let stripIndices = self.lines.stripIndices.len();
+        // Rebound every frame, the segment offset (and buffer, after growing or spilling) changes
+        if lineVertices >= 2 {
+            self.lines.vec.withSource(|buffer, offset| unsafe {
+                gl::VertexArrayVertexBuffer(self.vao, 0, buffer, offset as GLintptr, stride);
+                gl::BindVertexArray(self.vao);
+                gl::DrawArrays(gl::LINES, 0, lineVertices as GLsizei);
+            });
+        }
+        if stripIndices > 0 {
+            self.lines.stripVertices.withSource(|vertices, vertexOffset| {
+                self.lines.stripIndices.withSource(|indices, indexOffset| unsafe {
+                    gl::VertexArrayVertexBuffer(self.stripVao, 0, vertices, vertexOffset as GLintptr, stride);
+                    gl::VertexArrayElementBuffer(self.stripVao, indices);
+                    gl::BindVertexArray(self.stripVao);
+                    gl::Enable(gl::PRIMITIVE_RESTART_FIXED_INDEX);
+                    gl::DrawElements(
+                        gl::LINE_STRIP,
+                        stripIndices as GLsizei,
+                        gl::UNSIGNED_INT,
+                        indexOffset as *const c_void,
+                    );
+                    gl::Disable(gl::PRIMITIVE_RESTART_FIXED_INDEX);
+                })
+            });
+        }
+    }
+
+    /// Call once the frame's draws are issued, even when nothing was drawn.
+    pub fn nextFrame(&mut self) {
+        self.lines.endStrip();
+        self.lines.clear();
+        self.lines.vec.nextFrame();
+        self.lines.stripVertices.nextFrame();
+        self.lines.stripIndices.nextFrame();
+    }
+
+    pub fn destroy(&mut self) {
+        self.lines.vec.destroy();
+        self.lines.stripVertices.destroy();
+        self.lines.stripIndices.destroy();
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteVertexArrays(1, &self.stripVao);
+        }
+    }
+}
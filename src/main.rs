@@ -2,11 +2,40 @@
 
 mod shader;
 mod camera;
+mod debug_draw;
+mod framebuffer;
+mod grid;
+mod instancing;
+mod lights;
 mod line_renderer;
+mod mesh;
+mod pbr;
+mod post_process;
+mod ring_buffer;
+mod scene_graph;
+mod shadows;
+mod sky;
+mod texture;
 
 use crate::shader::Shader;
 use crate::camera::{Camera, Movement};
-use crate::line_renderer::LineRenderer;
+use crate::grid::{editGrid, Grid};
+use crate::instancing::{bindInstancedShader, editStressTest, stressInstances, InstanceBuffer, MeshPool, StressMode, StressSettings};
+use crate::lights::{editLights, pushLightGizmo, Light, LightBuffer};
+use crate::line_renderer::{DepthMode, LineRenderer};
+use crate::line_renderer::budget::OverflowPolicy;
+use crate::line_renderer::curves::{gradient, DebugCurves, Subdivision};
+use crate::line_renderer::overlay::{cursorToPixels, ndcToPixels, Overlay2D};
+use crate::line_renderer::shapes::DebugShapes;
+use crate::mesh::{generators, Mesh};
+use crate::mesh::gltf_scene::{loadGltf, PbrMaterial, TextureRef};
+use crate::mesh::obj::{loadObj, Material};
+use crate::pbr::{bindMaterial, Ibl, MapSlot, MAP_SLOTS};
+use crate::post_process::{editPostStack, PostStack};
+use crate::scene_graph::{editSceneGraph, Component, SceneGraph, Transform};
+use crate::shadows::{editShadows, ShadowMaps};
+use crate::sky::{editSky, sunDirection, Sky, SkyMode};
+use crate::texture::{srgbToLinear, Image, SamplerState, Texture};
 
 use imgui::Context as ImContext;
 use imgui_glfw_rs::ImguiGLFW;
@@ -16,15 +45,167 @@ use imgui_glfw_rs::imgui as ImGui;
 use gl;
 use gl::types::*;
 
-use std::os::raw::c_void;
-use std::ptr;
 use std::f32::consts::PI;
-use cgmath::{perspective, vec3, Array, Deg, Matrix4, Point3, SquareMatrix, Vector3};
+use std::collections::HashMap;
+use std::path::Path;
+use cgmath::{perspective, vec2, vec3, Array, Deg, EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Vector2, Vector3};
 
 // settings
 const SCR_WIDTH: u32 = 800;
 const SCR_HEIGHT: u32 = 600;
 
+/// Piece of a model with its own materials, textures (indices into `Model::textures`,
+/// in `MapSlot` order) and model matrix. The base color map doubles as the Phong diffuse map.
+struct Part {
+	mesh: Mesh,
+	material: Material,
+	pbr: PbrMaterial,
+	maps: [Option<usize>; MAP_SLOTS],
+	transform: Matrix4<f32>,
+}
+
+impl Part {
+	fn new(mesh: Mesh, material: Material, pbr: PbrMaterial, transform: Matrix4<f32>) -> Part {
+		Part { mesh, material, pbr, maps: [None; MAP_SLOTS], transform }
+	}
+}
+
+/// An entry of the mesh list.
+struct Model {
+	name: String,
+	parts: Vec<Part>,
+	textures: Vec<Texture>,
+	/// glTF cameras: name, position, front and vertical fov in degrees
+	cameras: Vec<(String, Point3<f32>, Vector3<f32>, f32)>,
+}
+
+impl Model {
+	fn new(name: &str) -> Model {
+		Model { name: name.to_string(), parts: Vec::new(), textures: Vec::new(), cameras: Vec::new() }
+	}
+	
+	fn destroy(&self) {
+		for part in self.parts.iter() {
+			part.mesh.destroy();
+		}
+		for texture in self.textures.iter() {
+			texture.destroy();
+		}
+	}
+}
+
+/// Every .obj, .gltf and .glb under `dir`, sorted.
+fn findModels(dir: &Path, found: &mut Vec<String>) {
+	let Ok(entries) = std::fs::read_dir(dir) else {
+		return;
+	};
+	for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+		if path.is_dir() {
+			findModels(&path, found);
+		} else if path.extension().is_some_and(|extension| ["obj", "gltf", "glb"].iter().any(|e| extension == *e)) {
+			found.push(path.to_string_lossy().to_string());
+		}
+	}
+	found.sort();
+}
+
+fn loadModel(path: &str) -> Result<Model, String> {
+	let mut loaded = Model::new(path);
+	let sampler = SamplerState::default().anisotropic(8.0);
+	if path.ends_with(".obj") {
+		let model = loadObj(path)?;
+		let groups: Vec<&str> = model.parts.iter().map(|part| part.group.as_str()).collect();
+		println!("Loaded {} ({} triangles, groups: {})", path, model.triangleCount(), groups.join(", "));
+		// map_Kd is relative to the MTL file, which sits next to the OBJ in practice
+		let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+		let mut textureIndices: HashMap<String, Option<usize>> = HashMap::new();
+		for part in model.parts.iter() {
+			let material = model.material(part).cloned().unwrap_or_else(|| Material::new("default"));
+			let texture = material.diffuseMap.as_ref().and_then(|map| {
+				*textureIndices.entry(map.clone()).or_insert_with(|| {
+					let mapPath = directory.join(map).to_string_lossy().to_string();
+					match Image::load(&mapPath) {
+						Ok(mut image) => {
+							image.flipVertically();
+							loaded.textures.push(Texture::new2D(&image, true, &sampler));
+							Some(loaded.textures.len() - 1)
+						}
+						Err(error) => {
+							println!("Failed to load texture: {}", error);
+							None
+						}
+					}
+				})
+			});
+			let pbr = PbrMaterial::fromPhong(&material);
+			let mut loadedPart = Part::new(Mesh::new(&part.data), material, pbr, Matrix4::identity());
+			loadedPart.maps[MapSlot::BaseColor as usize] = texture;
+			loaded.parts.push(loadedPart);
+		}
+		return Ok(loaded);
+	}
+	let scene = loadGltf(path)?;
+	println!(
+		"Loaded {} ({} triangles, {} nodes, {} materials, {} images)",
+		path,
+		scene.triangleCount(),
+		scene.nodes.len(),
+		scene.materials.len(),
+		scene.images.len()
+	);
+	// Textures shared between materials using the same image and sampler, base color and
+	// emissive maps are sRGB
+	let mut textureRefs: Vec<(TextureRef, bool)> = Vec::new();
+	let mut loadTexture = |reference: TextureRef, srgb: bool, textures: &mut Vec<Texture>| {
+		if let Some(index) = textureRefs.iter().position(|&r| r == (reference, srgb)) {
+			return index;
+		}
+		let image = &scene.images[reference.image];
+		let sampler = SamplerState {
+			minFilter: reference.minFilter,
+			magFilter: reference.magFilter,
+			wrapS: reference.wrapS,
+			wrapT: reference.wrapT,
+			..sampler
+		};
+		let image = Image::rgba8(image.width, image.height, image.pixels.clone());
+		textures.push(Texture::new2D(&image, srgb, &sampler));
+		textureRefs.push((reference, srgb));
+		textures.len() - 1
+	};
+	let maps: Vec<[Option<usize>; MAP_SLOTS]> = scene
+		.materials
+		.iter()
+		.map(|material| {
+			MapSlot::ALL.map(|slot| {
+				let reference = slot.textureRef(material)?;
+				Some(loadTexture(reference, slot.isSrgb(), &mut loaded.textures))
+			})
+		})
+		.collect();
+	let defaultMaterial = PbrMaterial::gltfDefault();
+	for (meshIndex, transform) in scene.drawList() {
+		for primitive in scene.meshes[meshIndex].primitives.iter() {
+			let pbr = primitive.material.map_or(&defaultMaterial, |i| &scene.materials[i]);
+			let mut part = Part::new(Mesh::new(&primitive.data), pbr.toPhong(), pbr.clone(), transform);
+			if let Some(i) = primitive.material {
+				part.maps = maps[i];
+			}
+			loaded.parts.push(part);
+		}
+	}
+	loaded.cameras = scene
+		.cameraNodes()
+		.into_iter()
+		.filter_map(|node| {
+			let camera = &scene.cameras[scene.nodes[node].camera?];
+			let (pos, front, _) = scene.cameraPose(node);
+			Some((camera.name.clone(), pos, front, camera.yfov?.to_degrees()))
+		})
+		.collect();
+	Ok(loaded)
+}
+
 fn main() {
     println!("Hello, world!");
 
@@ -37,6 +218,15 @@ fn main() {
     glfw.window_hint(glfw::WindowHint::OpenGlForwardCompat(true));
 	println!("GLFW initialized, version: {}", glfw::get_version_string());
 
+	// --samples [count]: multisampled window, for what's drawn directly to it (ImGui). The
+	// scene's MSAA is set in the post-processing window.
+	let args: Vec<String> = std::env::args().collect();
+	if let Some(i) = args.iter().position(|arg| arg == "--samples") {
+		let samples = args.get(i + 1).and_then(|arg| arg.parse().ok()).unwrap_or(4);
+		glfw.window_hint(glfw::WindowHint::Samples(Some(samples)));
+	}
+	glfw.window_hint(glfw::WindowHint::SRgbCapable(true));
+
 	let (mut winWidth, mut winHeight) = (SCR_WIDTH, SCR_HEIGHT);
     let (mut window, events) = glfw
         .create_window(
@@ -56,6 +246,13 @@ fn main() {
     gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
 	println!("OpenGL function pointers");
 
+	// --bench-lines [count]: compare line upload paths and exit
+	if let Some(i) = args.iter().position(|arg| arg == "--bench-lines") {
+		let count = args.get(i + 1).and_then(|arg| arg.parse().ok()).unwrap_or(100_000);
+		line_renderer::bench::runUploadBenchmark(count, 240);
+		return;
+	}
+
 	let mut imgui = ImContext::create();
 	imgui.set_ini_filename(None);
 	imgui.set_log_filename(None);
@@ -84,80 +281,121 @@ fn main() {
 		"resources/shaders/fragment.frag",
 	);
 	
-	let (VAO, VBO, EBO, elementCount) = unsafe {
+	unsafe {
 		gl::Enable(gl::DEPTH_TEST);
 		gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
 
 		// gl::Enable(gl::CULL_FACE);
 		// gl::CullFace(gl::FRONT);
+	}
+	// The scene is drawn offscreen in HDR, then post-processed onto the window
+	let mut postStack = PostStack::new(winWidth, winHeight);
+
+	let litShader = Shader::new(
+		"resources/shaders/lit.vert",
+		"resources/shaders/lit.frag",
+	);
+	let pbrShader = Shader::new(
+		"resources/shaders/lit.vert",
+		"resources/shaders/pbr.frag",
+	);
+	// 0 unlit, 1 Blinn-Phong, 2 PBR
+	let mut shading = 2;
+	// Environments are baked when first picked, then cached by `Ibl`
+	let mut ibl = Ibl::new();
+	let mut environmentFiles: Vec<String> = std::fs::read_dir("resources/textures")
+		.map(|entries| {
+			entries
+				.filter_map(|entry| entry.ok())
+				.map(|entry| entry.path())
+				.filter(|path| path.extension().is_some_and(|extension| extension == "hdr"))
+				.map(|path| path.to_string_lossy().to_string())
+				.collect()
+		})
+		.unwrap_or_default();
+	environmentFiles.sort();
+	let mut environmentItem = environmentFiles.iter().position(|path| path.ends_with("sky.hdr")).map_or(0, |i| i + 1);
+	let mut environmentIntensity = 1.0f32;
+	let mut sky = Sky::new();
+	let mut grid = Grid::new();
+	let mut lights = vec![
+		Light::directional(vec3(-0.4, -1.0, -0.6), vec3(1.0, 0.95, 0.9), 1.0),
+		Light::point(vec3(1.5, 1.0, 1.5), vec3(1.0, 0.5, 0.2), 6.0, 6.0),
+		Light::spot(vec3(-1.5, 2.5, 0.0), vec3(0.5, -1.0, 0.0), vec3(0.3, 0.6, 1.0), 12.0, 8.0, 15.0, 25.0),
+	];
+	let mut lightBuffer = LightBuffer::new();
+	let mut ambientLight = [0.15f32; 3];
+	let mut lightGizmos = true;
+	let mut shadowMaps = ShadowMaps::new();
 
-        // vertex data and vao
-        // let vertices: [f32; 12] = [
-        // 	 0.5,  0.5,  0.0, // top right
-        // 	 0.5, -0.5,  0.0, // bottom right
-        // 	-0.5, -0.5, -0.5, // bottom left
-        // 	-0.5,  0.5,  0.0, // top left
-        // ];
-        // let indices = [
-        // 	0, 1, 3,
-        // 	1, 2, 3
-        // ];
-        let vertices: [f32; 24] = [
-            -0.5, 0.5, 0.5, // top left
-            0.0, 0.5, 0.0, // top
-            0.5, 0.5, 0.5, // top right
-            0.5, 0.0, 0.0, // right
-            0.5, -0.5, 0.5, // bottom right
-            0.0, -0.5, 0.0, // bottom
-            -0.5, -0.5, 0.5, // bottom left
-            -0.5, 0.0, 0.0, // left
-        ];
-        let indices = [
-            0, 1, 7, // top left
-            1, 2, 3, // top right
-            3, 4, 5, // bottom right
-            7, 5, 6, // bottom left
-            1, 3, 5, 1, 5, 7,
-        ];
-        let (mut VAO, mut VBO, mut EBO) = (0, 0, 0);
-        gl::GenVertexArrays(1, &mut VAO);
-        gl::GenBuffers(1, &mut VBO);
-        gl::GenBuffers(1, &mut EBO);
-        gl::BindVertexArray(VAO);
-
-        gl::BindBuffer(gl::ARRAY_BUFFER, VBO);
-        gl::BufferData(
-            gl::ARRAY_BUFFER,
-            (vertices.len() * size_of::<GLfloat>()) as GLsizeiptr,
-            vertices.as_ptr() as *const c_void,
-            gl::STATIC_DRAW,
-        );
-
-        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, EBO);
-        gl::BufferData(
-            gl::ELEMENT_ARRAY_BUFFER,
-            (indices.len() * size_of::<GLfloat>()) as GLsizeiptr,
-            indices.as_ptr() as *const c_void,
-            gl::STATIC_DRAW,
-        );
-
-        gl::VertexAttribPointer(
-            0,
-            3,
-            gl::FLOAT,
-            gl::FALSE,
-            3 * size_of::<GLfloat>() as GLsizei,
-            ptr::null(),
-        );
-        gl::EnableVertexAttribArray(0);
-
-        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-        gl::BindVertexArray(0);
-
-        (VAO, VBO, EBO, indices.len() as GLsizei)
-    };
+	let generated = [
+		("Star", generators::star()),
+		("Plane", generators::plane(1.0, 1.0, 4)),
+		("Cube", generators::cube(1.0)),
+		("UV sphere", generators::uvSphere(0.5, 32, 16)),
+		("Icosphere", generators::icosphere(0.5, 2)),
+		("Cylinder", generators::cylinder(0.5, 1.0, 32)),
+		("Cone", generators::cone(0.5, 1.0, 32)),
+		("Torus", generators::torus(0.5, 0.15, 48, 16)),
+		("Capsule", generators::capsule(0.25, 0.5, 32, 8)),
+	];
+	let mut meshes: Vec<Model> = generated
+		.iter()
+		.map(|(name, data)| {
+			let mut model = Model::new(name);
+			let material = Material::new("default");
+			let pbr = PbrMaterial::fromPhong(&material);
+			let part = Part::new(Mesh::new(data), material, pbr, Matrix4::identity());
+			model.parts.push(part);
+			model
+		})
+		.collect();
+	let mut meshIndex = 0;
+	// Scene graph: the picked model with a small moon attached, the lights and a camera. The
+	// "Mesh" combo picks the model node's mesh.
+	let mut sceneGraph = SceneGraph::new();
+	let sceneRoot = sceneGraph.add("Scene", None, Transform::default());
+	let modelNode = sceneGraph.addWith("Model", Some(sceneRoot), Transform::default(), Component::Mesh(meshIndex));
+	let moonMesh = generated.iter().position(|(name, _)| *name == "Icosphere").unwrap_or(0);
+	let moon = Transform {
+		translation: vec3(1.5, 0.5, 0.0),
+		scale: Vector3::from_value(0.25),
+		..Transform::default()
+	};
+	sceneGraph.addWith("Moon", Some(modelNode), moon, Component::Mesh(moonMesh));
+	let lightsNode = sceneGraph.add("Lights", Some(sceneRoot), Transform::default());
+	for (i, light) in lights.iter().enumerate() {
+		sceneGraph.addWith(&format!("{} light {}", light.kind.label(), i), Some(lightsNode), Transform::default(), Component::Light(i));
+	}
+	sceneGraph.addWith("Camera", Some(sceneRoot), Transform::fromTranslation(vec3(0.0, 1.0, 4.0)), Component::Camera { fov: 45.0 });
+	let mut selectedNode = None;
+	// Stress test scene: a cube drawn instanced, or a mix of shapes with multi-draw-indirect
+	let mut stress = StressSettings::default();
+	let instancedShader = Shader::new(
+		"resources/shaders/instanced.vert",
+		"resources/shaders/instanced.frag",
+	);
+	let stressCube = Mesh::new(&generators::cube(1.0));
+	let mut stressInstanceBuffer = InstanceBuffer::new();
+	let mut stressPool = MeshPool::new(&[
+		generators::cube(1.0),
+		generators::icosphere(0.5, 1),
+		generators::cone(0.5, 1.0, 16),
+		generators::torus(0.5, 0.15, 24, 8),
+		generators::cylinder(0.5, 1.0, 16),
+	]);
+	// Model files are loaded when picked in ImGui
+	let mut modelFiles = Vec::new();
+	findModels(Path::new("resources"), &mut modelFiles);
+	let mut modelFileItem = -1;
 	
 	let mut lineRenderer = LineRenderer::new(1024);
+	lineRenderer.setViewport(winWidth as f32, winHeight as f32);
+	let mut frameTimes = vec![0.0f32; 120];
+	let mut selectStart: Option<Vector2<f32>> = None;
+	lineRenderer.retain(|lines| {
+		lines.pushAabb(&Matrix4::identity(), vec3(-1.0, -1.0, -1.0), vec3(1.0, 1.0, 1.0), vec3(1.0, 1.0, 1.0));
+	});
 	
 	println!("Stating main loop");
     while !window.should_close() {
@@ -165,6 +403,7 @@ fn main() {
 		let frameTime = glfw.get_time() as f32;
 		dt = frameTime - lastFrameTime;
 		lastFrameTime = frameTime;
+		lineRenderer.advance(dt);
 		
 		// events
 		for (_, event) in glfw::flush_messages(&events) {
@@ -173,6 +412,8 @@ fn main() {
 				glfw::WindowEvent::FramebufferSize(width, height) => unsafe {
 					(winWidth, winHeight) = (width as u32, height as u32);
 					imgui.io_mut().display_size = [winWidth as f32, winHeight as f32];
+					lineRenderer.setViewport(winWidth as f32, winHeight as f32);
+					postStack.resize(winWidth, winHeight);
 					gl::Viewport(0, 0, width, height)
 				},
 				glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
@@ -234,61 +475,52 @@ fn main() {
 			camera.processMovement(Movement::Right, dt);
 		}
 
-		let white = vec3(1.0, 1.0, 1.0);
-		let red = vec3(1.0, 0.0, 0.0);
-		let green = vec3(0.0, 1.0, 0.0);
-		let blue = vec3(0.0, 0.0, 1.0);
-
-		let b1 = vec3(-1.0, -1.0, -1.0);
-		let b2 = vec3(1.0, -1.0, -1.0);
-		let b3 = vec3(1.0, -1.0, 1.0);
-		let b4 = vec3(-1.0, -1.0, 1.0);
-		let t1 = vec3(-1.0, 1.0, -1.0);
-		let t2 = vec3(1.0, 1.0, -1.0);
-		let t3 = vec3(1.0, 1.0, 1.0);
-		let t4 = vec3(-1.0, 1.0, 1.0);
-
-		lineRenderer.pushLine(b1, white, b2, red);
-		lineRenderer.pushLine(b2, red, b3, green);
-		lineRenderer.pushLine(b3, green, b4, blue);
-		lineRenderer.pushLine(b4, blue, b1, white);
-
-		lineRenderer.pushLine(t1, white, t2, red);
-		lineRenderer.pushLine(t2, red, t3, green);
-		lineRenderer.pushLine(t3, green, t4, blue);
-		lineRenderer.pushLine(t4, blue, t1, white);
-
-		lineRenderer.pushLine(b1, white, t1, white);
-		lineRenderer.pushLine(b2, red, t2, red);
-		lineRenderer.pushLine(b3, green, t3, green);
-		lineRenderer.pushLine(b4, blue, t4, blue);
-
-		{
-			let mut theta = 0.0f32;
-			let mut lastP = vec3(0.0, -5.0, 0.0);
-			let mut lastC = vec3(0.0, 0.0, 0.0);
-			while theta < PI * 20.0 {
-				let r = theta * 0.05; // r = b * theta (with b=1)
-				let x = r * theta.cos();
-				let y = r * 0.5 - 5.0;
-				let z = r * theta.sin();
-				let c = Vector3::from_value(theta / (PI * 20.0));
-
-				let p = vec3(x, y, z);
-				lineRenderer.pushLine(lastP, lastC, p, c);
-				lastP = p;
-				lastC = c;
-
-				theta += PI * 0.05;
-			}
-		}
-
         // render
+		postStack.begin();
         unsafe {
-            gl::ClearColor(0.2, 0.3, 0.3, 1.0);
+            let [red, green, blue] = sky.settings.clearColor();
+            gl::ClearColor(red, green, blue, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
 		}
-		
+
+		let (aspect, near) = (winWidth as f32 / winHeight as f32, 0.1);
+		let projection: Matrix4<f32> = perspective(Deg(camera.zoom), aspect, near, 100.0);
+		let view = camera.getViewMatrix();
+		// Lines may be flushed before drawFlush once the cap is hit, so clear and set the camera first
+		lineRenderer.setViewProjection(&(projection * view));
+
+		lineRenderer.setDepthMode(DepthMode::XRay);
+		lineRenderer.pushAxes(&Matrix4::identity(), 0.5);
+		lineRenderer.setDepthMode(DepthMode::Tested);
+		lineRenderer.pushLabel(vec3(0.5, 0.0, 0.0), vec3(1.0, 0.3, 0.3), "X");
+		lineRenderer.pushLabel(vec3(0.0, 0.5, 0.0), vec3(0.3, 1.0, 0.3), "Y");
+		lineRenderer.pushLabel(vec3(0.0, 0.0, 0.5), vec3(0.3, 0.3, 1.0), "Z");
+		let corner = vec3(1.0, 1.0, 1.0);
+		let distance = (corner - camera.pos.to_vec()).magnitude();
+		lineRenderer.pushLabel(corner, vec3(1.0, 1.0, 1.0), format!("({}, {}, {}) {:.1}m", corner.x, corner.y, corner.z, distance));
+
+		// Archimedean spiral, r = b * theta
+		lineRenderer.pushParametric(
+			|theta| {
+				let r = theta * 0.05;
+				let c = Vector3::from_value(theta / (PI * 20.0));
+				(vec3(r * theta.cos(), r * 0.5 - 5.0, r * theta.sin()), c)
+			},
+			0.0,
+			PI * 20.0,
+			&Subdivision::ScreenSpace {
+				viewProjection: projection * view,
+				viewport: (winWidth as f32, winHeight as f32),
+				pixels: 0.5,
+			},
+		);
+		debug_draw::sphere(vec3(1.0, 1.0, 1.0), 0.1, vec3(1.0, 1.0, 1.0));
+		lineRenderer.pushCubicBezier(
+			[vec3(-1.0, 1.0, -1.0), vec3(-1.0, 3.0, -1.0), vec3(1.0, 3.0, 1.0), vec3(1.0, 1.0, 1.0)],
+			gradient(vec3(1.0, 0.0, 1.0), vec3(0.0, 1.0, 1.0)),
+			&Subdivision::Segments(32),
+		);
+
 		shader.bind();
 		
 		let third = 1.0f32 / 3.0;
@@ -299,29 +531,168 @@ fn main() {
 		let blue = ((0.25 * frameTime - third) % 2.0 - 1.0).abs();
 		shader.setUniform3f("u_color", red, green, blue);
 		
-		let projection: Matrix4<f32> = perspective(Deg(camera.zoom), winWidth as f32 / winHeight as f32, 0.1, 100.0);
-		let view = camera.getViewMatrix();
-		let model: Matrix4<f32> =  Matrix4::identity();
-		let pvm = projection * view * model;
+		// Meshes are placed by the scene graph
+		let pvm = projection * view;
 		shader.setMatrix4f("u_pvm", &pvm);
 		
+		// Baking happens here, outside the shader bindings below
+		let environment = match environmentItem.checked_sub(1).map(|i| environmentFiles[i].clone()) {
+			Some(path) => match ibl.environment(&path) {
+				Ok(_) => Some(path),
+				Err(error) => {
+					println!("Failed to load environment: {}", error);
+					environmentItem = 0;
+					None
+				}
+			},
+			None => None,
+		};
+		// The inspector may have picked another mesh for the model node
+		if let Some(&Component::Mesh(index)) = sceneGraph.node(modelNode).components.first() {
+			meshIndex = index.min(meshes.len() - 1);
+		}
+		let sceneComponents = sceneGraph.components();
+		// Lights on nodes move with them, the others stay in world space
+		let mut worldLights = lights.clone();
+		for &(_, world, component) in sceneComponents.iter() {
+			if let Component::Light(i) = component && let Some(light) = lights.get(i) {
+				worldLights[i] = light.transformed(&world);
+			}
+		}
+		let drawn: Vec<(Matrix4<f32>, &Model)> = if stress.mode == StressMode::Off {
+			sceneComponents
+				.iter()
+				.filter_map(|&(_, world, component)| match component {
+					Component::Mesh(i) => meshes.get(i).map(|model| (world, model)),
+					_ => None,
+				})
+				.collect()
+		} else {
+			Vec::new()
+		};
+		// Picked as sRGB, the shaders light in linear
+		let ambient = Vector3::from(ambientLight.map(srgbToLinear));
+		if shading != 0 && stress.mode == StressMode::Off {
+			shadowMaps.render(&worldLights, &camera, aspect, near, |depthShader| {
+				for (world, model) in drawn.iter() {
+					for part in model.parts.iter() {
+						depthShader.setMatrix4f("u_model", &(world * part.transform));
+						part.mesh.draw();
+					}
+				}
+			});
+		}
+		if shading == 1 {
+			litShader.bind();
+			shadowMaps.bind(&litShader, &view);
+			litShader.setUniform3fv("u_viewPos", &camera.pos.to_vec());
+			litShader.setUniform3fv("u_ambientLight", &ambient);
+			lightBuffer.upload(&worldLights);
+		} else if shading == 2 {
+			pbrShader.bind();
+			pbrShader.setUniform3fv("u_viewPos", &camera.pos.to_vec());
+			pbrShader.setUniform3fv("u_ambientLight", &ambient);
+			shadowMaps.bind(&pbrShader, &view);
+			ibl.bind(&pbrShader, environment.as_ref().and_then(|path| ibl.cached(path)), environmentIntensity);
+			lightBuffer.upload(&worldLights);
+		}
+		for (world, model) in drawn.iter() {
+			for part in model.parts.iter() {
+				let transform = world * part.transform;
+				let partPvm = pvm * transform;
+				let diffuseMap = part.maps[MapSlot::BaseColor as usize];
+				if shading == 1 {
+					let material = &part.material;
+					litShader.setMatrix4f("u_pvm", &partPvm);
+					litShader.setMatrix4f("u_model", &transform);
+					litShader.setUniform3fv("u_ambient", &material.ambient);
+					litShader.setUniform3fv("u_diffuse", &material.diffuse);
+					litShader.setUniform3fv("u_specular", &material.specular);
+					litShader.setUniform1f("u_shininess", material.shininess);
+					litShader.setUniform1i("u_hasDiffuseMap", diffuseMap.is_some() as i32);
+					if let Some(texture) = diffuseMap {
+						litShader.setTexture("u_diffuseMap", 0, &model.textures[texture]);
+					}
+				} else if shading == 2 {
+					pbrShader.setMatrix4f("u_pvm", &partPvm);
+					pbrShader.setMatrix4f("u_model", &transform);
+					bindMaterial(&pbrShader, &part.pbr, part.maps.map(|map| map.map(|i| &model.textures[i])));
+				} else {
+					shader.setMatrix4f("u_pvm", &partPvm);
+				}
+				part.mesh.draw();
+			}
+		}
+		if stress.mode != StressMode::Off {
+			let time = if stress.spin { frameTime } else { 0.0 };
+			let instances = stressInstances(stress.count as usize, stress.spacing, time);
+			let sun = sunDirection(&worldLights).unwrap_or(Vector3::from_value(0.0));
+			bindInstancedShader(&instancedShader, &(projection * view), sun, ambient);
+			if stress.mode == StressMode::Instanced {
+				stressInstanceBuffer.upload(&instances);
+				stressCube.drawInstanced(&stressInstanceBuffer);
+			} else {
+				// Contiguous bands of each shape
+				let perMesh = instances.len().div_ceil(stressPool.meshCount());
+				let batches: Vec<&[_]> = instances.chunks(perMesh).collect();
+				stressPool.draw(&batches);
+			}
+		}
+		// Behind the meshes, only where they left the far plane depth
+		let environmentCube = environment.as_ref().and_then(|path| ibl.cached(path)).map(|environment| &environment.cube);
+		sky.draw(&view, &projection, &worldLights, environmentCube);
+		grid.draw(&view, &projection, camera.pos.to_vec());
 		unsafe {
-			gl::BindVertexArray(VAO);
-			gl::DrawElements(gl::TRIANGLES, elementCount, gl::UNSIGNED_INT, ptr::null());
-			
 			let error = gl::GetError();
 			if error != gl::NO_ERROR {
 				panic!("OpenGL error ({})", error);
 			}
 		}
 		
+		// 2D overlay: frame time graph, crosshair while captured, right-drag selection box
+		frameTimes.rotate_left(1);
+		*frameTimes.last_mut().unwrap() = dt * 1000.0;
+		let screenCenter = ndcToPixels(vec2(0.0, 0.0), lineRenderer.viewport());
+		// Both corners in framebuffer pixels, like the rest of the overlay
+		let (windowWidth, windowHeight) = window.get_size();
+		let cursor = cursorToPixels(vec2(lastMX, lastMY), (windowWidth as f32, windowHeight as f32), lineRenderer.viewport());
+		let mut overlay = lineRenderer.overlay();
+		overlay.pushGraph(vec2(10.0, winHeight as f32 - 70.0), vec2(250.0, winHeight as f32 - 10.0), &frameTimes, (0.0, 33.3), vec3(0.2, 1.0, 0.4));
+		if mouseMode {
+			overlay.pushCrosshair(screenCenter, 8.0, 3.0, vec3(1.0, 1.0, 1.0));
+		}
+		if !mouseMode && window.get_mouse_button(glfw::MouseButton::Button2) == Action::Press {
+			overlay.pushSelectionBox(*selectStart.get_or_insert(cursor), cursor, vec3(1.0, 0.8, 0.2));
+		} else {
+			selectStart = None;
+		}
+
+		if lightGizmos {
+			for light in worldLights.iter().filter(|light| light.enabled) {
+				pushLightGizmo(&mut lineRenderer, light);
+			}
+		}
+		if shadowMaps.settings.showCascades {
+			shadowMaps.pushCascadeFrusta(&mut lineRenderer);
+		}
+		debug_draw::drain(&mut lineRenderer);
 		lineRenderer.drawFlush(&pvm);
+		if let Some(capture) = lineRenderer.takeCapture() {
+			match capture.save("line_export") {
+				Ok(paths) => println!("Exported lines to {}", paths.join(", ")),
+				Err(error) => println!("Failed to export lines: {}", error),
+			}
+		}
+
+		postStack.finish(winWidth, winHeight);
+		lineRenderer.drawOverlay();
 
 		// imgui
         let ui = imguiGlfw.frame(&mut window, &mut imgui);
+		lineRenderer.drawLabels(ui);
         // ui.show_demo_window(&mut true);
         ui.window("ye")
-            .size([170.0, 160.0], ImGui::Condition::FirstUseEver)
+            .size([220.0, 300.0], ImGui::Condition::FirstUseEver)
             .build(|| {
                 ui.text("Hello, world!".to_string());
                 let s = ui.window_size();
@@ -335,6 +706,87 @@ fn main() {
 				if ui.button("Line Renderer Toggle") {
 					lineRenderer.enabled = !lineRenderer.enabled;
 				}
+				if ui.button("Raycast") {
+					let from = camera.pos.to_vec();
+					let to = from + camera.front * 10.0;
+					lineRenderer.retainFor(3.0, |lines| {
+						lines.pushArrow(&Matrix4::identity(), from, to, 0.25, vec3(1.0, 1.0, 0.0));
+					});
+				}
+				ui.text(format!("Retained groups: {}", lineRenderer.retainedLines().len()));
+				if ui.button("Export lines (SVG/OBJ/PLY)") {
+					lineRenderer.captureFrame();
+				}
+				let mut depthItem = lineRenderer.depthOverride.map_or(0, |mode| mode as usize + 1);
+				let depthItems = ["Per batch", DepthMode::Tested.label(), DepthMode::XRay.label(), DepthMode::Overlay.label()];
+				if ui.combo_simple_string("Line depth", &mut depthItem, &depthItems) {
+					lineRenderer.depthOverride = depthItem.checked_sub(1).map(|i| DepthMode::ALL[i]);
+				}
+				let stats = lineRenderer.stats();
+				ui.text(format!("Lines: {} (peak {})", stats.lines, stats.peakLines));
+				ui.text(format!("Dropped: {}, flushes: {}", stats.droppedLines, stats.flushes));
+				ui.text(format!("Capacity: {} lines, {:.1} KiB", stats.capacityLines, stats.bufferBytes as f32 / 1024.0));
+				let mut capped = lineRenderer.budget.maxLines.is_some();
+				if ui.checkbox("Cap lines", &mut capped) {
+					lineRenderer.budget.maxLines = capped.then_some(stats.capacityLines);
+				}
+				if let Some(maxLines) = lineRenderer.budget.maxLines.as_mut() {
+					let mut value = *maxLines as i32;
+					if ui.input_int("Max lines", &mut value).build() {
+						*maxLines = value.max(0) as usize;
+					}
+					let mut policyItem = lineRenderer.budget.policy as usize;
+					if ui.combo_simple_string("Overflow", &mut policyItem, &["Drop", "Flush"]) {
+						lineRenderer.budget.policy = [OverflowPolicy::Drop, OverflowPolicy::Flush][policyItem];
+					}
+				}
+				let meshNames: Vec<&str> = meshes.iter().map(|model| model.name.as_str()).collect();
+				ui.combo_simple_string("Mesh", &mut meshIndex, &meshNames);
+				ui.combo_simple_string("Shading", &mut shading, &["Unlit", "Blinn-Phong", "PBR"]);
+				// The cube map sky shows the environment too
+				if shading == 2 || sky.settings.mode == SkyMode::Cubemap {
+					let mut environmentNames = vec!["None"];
+					environmentNames.extend(environmentFiles.iter().map(|path| path.as_str()));
+					ui.combo_simple_string("Environment", &mut environmentItem, &environmentNames);
+				}
+				if shading == 2 {
+					ui.slider("Environment intensity", 0.0, 4.0, &mut environmentIntensity);
+				}
+				if ui.collapsing_header("Grid", ImGui::TreeNodeFlags::empty()) {
+					editGrid(ui, &mut grid.settings);
+				}
+				editStressTest(ui, &mut stress, stressPool.meshCount());
+				for (name, pos, front, fov) in meshes[meshIndex].cameras.iter() {
+					if ui.button(format!("Use camera '{}'", name)) {
+						camera.setPose(*pos, *front);
+						camera.zoom = fov.clamp(1.0, 45.0);
+					}
+				}
+				let fileNames: Vec<&str> = modelFiles.iter().map(|path| path.as_str()).collect();
+				if ui.list_box("Files", &mut modelFileItem, &fileNames, 6) {
+					let path = &modelFiles[modelFileItem as usize];
+					// Picking a file again reloads it
+					meshIndex = match meshes.iter().position(|model| &model.name == path) {
+						Some(index) => {
+							meshes[index].destroy();
+							meshes[index] = Model::new(path);
+							index
+						}
+						None => {
+							meshes.push(Model::new(path));
+							meshes.len() - 1
+						}
+					};
+					match loadModel(path) {
+						Ok(model) => meshes[meshIndex] = model,
+						Err(error) => println!("Failed to load model: {}", error),
+					}
+				}
+				if ui.button("Rescan files") {
+					modelFiles.clear();
+					findModels(Path::new("resources"), &mut modelFiles);
+					modelFileItem = -1;
+				}
 				if ui.button("Wireframe Toggle") {
 					unsafe {
 						let mut mode: GLint = gl::FILL as GLint;
@@ -347,6 +799,50 @@ fn main() {
 					}
 				}
             });
+		if let Some(Component::Mesh(index)) = sceneGraph.nodeMut(modelNode).components.first_mut() {
+			*index = meshIndex;
+		}
+		ui.window("Scene graph")
+			.position([SCR_WIDTH as f32 - 620.0, 10.0], ImGui::Condition::FirstUseEver)
+			.size([300.0, 400.0], ImGui::Condition::FirstUseEver)
+			.build(|| {
+				let meshNames: Vec<&str> = meshes.iter().map(|model| model.name.as_str()).collect();
+				let lightNames: Vec<String> = lights.iter().enumerate().map(|(i, light)| format!("{} light {}", light.kind.label(), i)).collect();
+				let viewThrough = editSceneGraph(ui, &mut sceneGraph, &mut selectedNode, &meshNames, &lightNames);
+				if let Some(node) = viewThrough
+					&& let Some(&Component::Camera { fov }) = sceneGraph.node(node).components.iter().find(|c| matches!(c, Component::Camera { .. }))
+				{
+					// Cameras look down their node's -Z
+					let world = sceneGraph.world(node);
+					camera.setPose(Point3::from_vec(world.w.truncate()), -world.z.truncate());
+					camera.zoom = fov;
+				}
+			});
+		ui.window("Lights")
+			.position([SCR_WIDTH as f32 - 310.0, 10.0], ImGui::Condition::FirstUseEver)
+			.size([300.0, 400.0], ImGui::Condition::FirstUseEver)
+			.build(|| {
+				ui.color_edit3("Ambient", &mut ambientLight);
+				ui.checkbox("Gizmos", &mut lightGizmos);
+				ui.separator();
+				if let Some(removed) = editLights(ui, &mut lights) {
+					sceneGraph.lightRemoved(removed);
+				}
+				ui.separator();
+				editShadows(ui, &mut shadowMaps.settings);
+			});
+		ui.window("Post-processing")
+			.position([10.0, SCR_HEIGHT as f32 - 310.0], ImGui::Condition::FirstUseEver)
+			.size([300.0, 300.0], ImGui::Condition::FirstUseEver)
+			.build(|| {
+				editPostStack(ui, &mut postStack);
+			});
+		ui.window("Sky")
+			.position([SCR_WIDTH as f32 - 310.0, 420.0], ImGui::Condition::FirstUseEver)
+			.size([300.0, 220.0], ImGui::Condition::FirstUseEver)
+			.build(|| {
+				editSky(ui, &mut sky.settings, environmentItem != 0);
+			});
         imguiGlfw.draw(&mut imgui, &mut window);
 
 		// Swap & Poll
@@ -357,10 +853,20 @@ fn main() {
 
 	println!("Cleaning up");
 	lineRenderer.destroy();
+	for model in meshes.iter() {
+		model.destroy();
+	}
+	lightBuffer.destroy();
+	shadowMaps.destroy();
+	postStack.destroy();
+	sky.destroy();
+	grid.destroy();
+	stressCube.destroy();
+	stressInstanceBuffer.destroy();
+	stressPool.destroy();
+	instancedShader.delete();
+	ibl.destroy();
+	pbrShader.delete();
+	litShader.delete();
 	shader.delete();
-	unsafe {
-        gl::DeleteBuffers(1, &EBO);
-        gl::DeleteBuffers(1, &VBO);
-        gl::DeleteVertexArrays(1, &VAO);
-    }
 }
@@ -0,0 +1,394 @@
+#![allow(non_snake_case)]
+
+pub mod generators;
+pub mod gltf_scene;
+pub mod obj;
+
+use crate::instancing::InstanceBuffer;
+use cgmath::{InnerSpace, Zero, vec3};
+use gl::types::{GLenum, GLint, GLsizei, GLsizeiptr, GLuint};
+use std::os::raw::c_void;
+
+type Vector3 = cgmath::Vector3<f32>;
+
+const FLOAT_SIZE: usize = size_of::<f32>();
+
+/// A per-vertex attribute. Each has a fixed shader location, so any shader declaring
+/// `layout (location = N)` inputs works with any mesh providing them.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+#[allow(dead_code)]
+pub enum Attribute {
+    Position,
+    Normal,
+    Uv,
+    Color,
+    /// xyz tangent, w = bitangent sign.
+    Tangent,
+}
+
+impl Attribute {
+    pub fn components(self) -> usize {
+        match self {
+            Attribute::Position | Attribute::Normal | Attribute::Color => 3,
+            Attribute::Uv => 2,
+            Attribute::Tangent => 4,
+        }
+    }
+
+    pub fn location(self) -> GLuint {
+        self as GLuint
+    }
+}
+
+/// Interleaved vertex format, attributes in the given order.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct VertexLayout {
+    attributes: Vec<Attribute>,
+}
+
+#[allow(dead_code)]
+impl VertexLayout {
+    pub fn new(attributes: &[Attribute]) -> VertexLayout {
+        for (i, attribute) in attributes.iter().enumerate() {
+            assert!(!attributes[..i].contains(attribute), "{:?} appears twice in vertex layout", attribute);
+        }
+        VertexLayout {
+            attributes: attributes.to_vec(),
+        }
+    }
+
+    pub fn attributes(&self) -> &[Attribute] {
+        &self.attributes
+    }
+
+    pub fn contains(&self, attribute: Attribute) -> bool {
+        self.attributes.contains(&attribute)
+    }
+
+    /// Floats per vertex.
+    pub fn floats(&self) -> usize {
+        self.attributes.iter().map(|a| a.components()).sum()
+    }
+
+    pub fn stride(&self) -> usize {
+        self.floats() * FLOAT_SIZE
+    }
+
+    /// Offset of `attribute` inside a vertex, in floats.
+    pub fn offset(&self, attribute: Attribute) -> Option<usize> {
+        let index = self.attributes.iter().position(|&a| a == attribute)?;
+        Some(self.attributes[..index].iter().map(|a| a.components()).sum())
+    }
+}
+
+/// Index buffer contents, narrowed to 16 bits when every index fits.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Indices {
+    U16(Vec<u16>),
+    U32(Vec<u32>),
+}
+
+#[allow(dead_code)]
+impl Indices {
+    pub fn fit(indices: &[u32]) -> Indices {
+        if indices.iter().all(|&i| i <= u16::MAX as u32) {
+            Indices::U16(indices.iter().map(|&i| i as u16).collect())
+        } else {
+            Indices::U32(indices.to_vec())
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Indices::U16(indices) => indices.len(),
+            Indices::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn isEmpty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn byteSize(&self) -> usize {
+        match self {
+            Indices::U16(indices) => size_of_val(indices.as_slice()),
+            Indices::U32(indices) => size_of_val(indices.as_slice()),
+        }
+    }
+
+    pub fn glType(&self) -> GLenum {
+        match self {
+            Indices::U16(_) => gl::UNSIGNED_SHORT,
+            Indices::U32(_) => gl::UNSIGNED_INT,
+        }
+    }
+
+    fn asPtr(&self) -> *const c_void {
+        match self {
+            Indices::U16(indices) => indices.as_ptr() as *const c_void,
+            Indices::U32(indices) => indices.as_ptr() as *const c_void,
+        }
+    }
+}
+
+/// CPU-side mesh: interleaved vertices in `layout`, and triangle indices (empty to draw
+/// the vertices in order).
+#[derive(Clone, Debug)]
+pub struct MeshData {
+    pub layout: VertexLayout,
+    pub vertices: Vec<f32>,
+    pub indices: Vec<u32>,
+}
+
+#[allow(dead_code)]
+impl MeshData {
+    pub fn new(layout: VertexLayout) -> MeshData {
+        MeshData {
+            layout,
+            vertices: Vec::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    pub fn vertexCount(&self) -> usize {
+        self.vertices.len() / self.layout.floats()
+    }
+
+    /// Appends one vertex, `values` holding every attribute in layout order. Returns its index.
+    pub fn pushVertex(&mut self, values: &[f32]) -> u32 {
+        assert_eq!(values.len(), self.layout.floats(), "Vertex doesn't match layout {:?}", self.layout);
+        let index = self.vertexCount() as u32;
+        self.vertices.extend_from_slice(values);
+        index
+    }
+
+    pub fn pushTriangle(&mut self, a: u32, b: u32, c: u32) {
+        self.indices.extend_from_slice(&[a, b, c]);
+    }
+
+    /// The floats of `attribute` for vertex `index`.
+    pub fn attribute(&self, index: usize, attribute: Attribute) -> Option<&[f32]> {
+        let offset = self.layout.offset(attribute)?;
+        let start = index * self.layout.floats() + offset;
+        Some(&self.vertices[start..start + attribute.components()])
+    }
+
+    /// Sets the normal of each vertex in `vertices` to the area-weighted average of the faces
+    /// using it, for sources without normals.
+    pub fn smoothNormals(&mut self, vertices: impl IntoIterator<Item = u32>) {
+        let Some(normalOffset) = self.layout.offset(Attribute::Normal) else {
+            return;
+        };
+        let position = |data: &MeshData, i: u32| {
+            let p = data.attribute(i as usize, Attribute::Position).unwrap();
+            vec3(p[0], p[1], p[2])
+        };
+        let mut sums = vec![Vector3::zero(); self.vertexCount()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [triangle[0], triangle[1], triangle[2]].map(|i| position(self, i));
+            // Unnormalized, so bigger faces weigh more
+            let face = (b - a).cross(c - a);
+            for &i in triangle {
+                sums[i as usize] += face;
+            }
+        }
+        let floats = self.layout.floats();
+        for i in vertices {
+            let sum = sums[i as usize];
+            let n = if sum.magnitude2() > 0.0 { sum.normalize() } else { vec3(0.0, 1.0, 0.0) };
+            let start = i as usize * floats + normalOffset;
+            self.vertices[start..start + 3].copy_from_slice(&[n.x, n.y, n.z]);
+        }
+    }
+
+    /// Panics if the vertex data is cut short or an index is out of range.
+    pub fn validate(&self) {
+        if let Err(error) = self.check() {
+            panic!("{}", error);
+        }
+    }
+
+    /// Like `validate`, for data read from files.
+    pub fn check(&self) -> Result<(), String> {
+        if !self.vertices.len().is_multiple_of(self.layout.floats()) {
+            return Err("Vertex data isn't a whole number of vertices".to_string());
+        }
+        let count = self.vertexCount() as u32;
+        match self.indices.iter().find(|&&i| i >= count) {
+            Some(index) => Err(format!("Index {} out of range for {} vertices", index, count)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Mesh uploaded to the GPU, drawn as triangles.
+pub struct Mesh {
+    vao: GLuint,
+    vbo: GLuint,
+    ebo: GLuint,
+    layout: VertexLayout,
+    vertexCount: usize,
+    indexCount: usize,
+    indexType: GLenum,
+}
+
+#[allow(dead_code)]
+impl Mesh {
+    pub fn new(data: &MeshData) -> Mesh {
+        data.validate();
+        assert!(!data.vertices.is_empty(), "Mesh has no vertices");
+        let indices = Indices::fit(&data.indices);
+        let mut mesh = Mesh {
+            vao: 0,
+            vbo: 0,
+            ebo: 0,
+            layout: data.layout.clone(),
+            vertexCount: data.vertexCount(),
+            indexCount: indices.len(),
+            indexType: indices.glType(),
+        };
+        unsafe {
+            gl::CreateVertexArrays(1, &mut mesh.vao);
+            gl::CreateBuffers(1, &mut mesh.vbo);
+            gl::NamedBufferStorage(
+                mesh.vbo,
+                size_of_val(data.vertices.as_slice()) as GLsizeiptr,
+                data.vertices.as_ptr() as *const c_void,
+                0,
+            );
+            gl::VertexArrayVertexBuffer(mesh.vao, 0, mesh.vbo, 0, data.layout.stride() as GLsizei);
+
+            for &attribute in data.layout.attributes() {
+                let location = attribute.location();
+                let offset = data.layout.offset(attribute).unwrap() * FLOAT_SIZE;
+                gl::VertexArrayAttribFormat(
+                    mesh.vao,
+                    location,
+                    attribute.components() as GLint,
+                    gl::FLOAT,
+                    gl::FALSE,
+                    offset as GLuint,
+                );
+                gl::VertexArrayAttribBinding(mesh.vao, location, 0);
+                gl::EnableVertexArrayAttrib(mesh.vao, location);
+            }
+
+            if !indices.isEmpty() {
+                gl::CreateBuffers(1, &mut mesh.ebo);
+                gl::NamedBufferStorage(mesh.ebo, indices.byteSize() as GLsizeiptr, indices.asPtr(), 0);
+                gl::VertexArrayElementBuffer(mesh.vao, mesh.ebo);
+            }
+        }
+        mesh
+    }
+
+    pub fn layout(&self) -> &VertexLayout {
+        &self.layout
+    }
+
+    pub fn vertexCount(&self) -> usize {
+        self.vertexCount
+    }
+
+    pub fn indexCount(&self) -> usize {
+        self.indexCount
+    }
+
+    pub fn draw(&self) {
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            if self.indexCount > 0 {
+                gl::DrawElements(gl::TRIANGLES, self.indexCount as GLsizei, self.indexType, std::ptr::null());
+            } else {
+                gl::DrawArrays(gl::TRIANGLES, 0, self.vertexCount as GLsizei);
+            }
+        }
+    }
+
+    /// Draws one copy per instance in `instances`, with their attributes on the locations
+    /// from `instancing::INSTANCE_LOCATION` on.
+    pub fn drawInstanced(&self, instances: &InstanceBuffer) {
+        instances.attach(self.vao);
+        let count = instances.count() as GLsizei;
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            if self.indexCount > 0 {
+                gl::DrawElementsInstanced(gl::TRIANGLES, self.indexCount as GLsizei, self.indexType, std::ptr::null(), count);
+            } else {
+                gl::DrawArraysInstanced(gl::TRIANGLES, 0, self.vertexCount as GLsizei, count);
+            }
+        }
+    }
+
+    pub fn destroy(&self) {
+        unsafe {
+            if self.ebo != 0 {
+                gl::DeleteBuffers(1, &self.ebo);
+            }
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layoutOffsetsFollowAttributeOrder() {
+        let layout = VertexLayout::new(&[Attribute::Position, Attribute::Normal, Attribute::Uv, Attribute::Tangent]);
+        assert_eq!(layout.floats(), 12);
+        assert_eq!(layout.stride(), 48);
+        assert_eq!(layout.offset(Attribute::Position), Some(0));
+        assert_eq!(layout.offset(Attribute::Uv), Some(6));
+        assert_eq!(layout.offset(Attribute::Tangent), Some(8));
+        assert_eq!(layout.offset(Attribute::Color), None);
+    }
+
+    #[test]
+    #[should_panic]
+    fn duplicateAttributesAreRejected() {
+        VertexLayout::new(&[Attribute::Position, Attribute::Position]);
+    }
+
+    #[test]
+    fn indicesNarrowWhenTheyFit() {
+        let small = Indices::fit(&[0, 1, 65535]);
+        assert_eq!(small.glType(), gl::UNSIGNED_SHORT);
+        assert_eq!(small.byteSize(), 6);
+        let large = Indices::fit(&[0, 1, 65536]);
+        assert_eq!(large.glType(), gl::UNSIGNED_INT);
+        assert_eq!(large.byteSize(), 12);
+    }
+
+    #[test]
+    fn pushVertexReturnsIndices() {
+        let mut data = MeshData::new(VertexLayout::new(&[Attribute::Position, Attribute::Color]));
+        let a = data.pushVertex(&[0.0, 0.0, 0.0, 1.0, 0.0, 0.0]);
+        let b = data.pushVertex(&[1.0, 0.0, 0.0, 0.0, 1.0, 0.0]);
+        let c = data.pushVertex(&[0.0, 1.0, 0.0, 0.0, 0.0, 1.0]);
+        data.pushTriangle(a, b, c);
+        data.validate();
+        assert_eq!(data.vertexCount(), 3);
+        assert_eq!(data.attribute(1, Attribute::Color), Some(&[0.0, 1.0, 0.0][..]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn outOfRangeIndicesAreRejected() {
+        let mut data = MeshData::new(VertexLayout::new(&[Attribute::Position]));
+        data.pushVertex(&[0.0, 0.0, 0.0]);
+        data.pushTriangle(0, 0, 1);
+        data.validate();
+    }
+
+    #[test]
+    fn checkReportsInsteadOfPanicking() {
+        let mut data = MeshData::new(VertexLayout::new(&[Attribute::Position]));
+        data.pushVertex(&[0.0, 0.0, 0.0]);
+        assert_eq!(data.check(), Ok(()));
+        data.pushTriangle(0, 0, 1);
+        assert_eq!(data.check(), Err("Index 1 out of range for 1 vertices".to_string()));
+    }
+}
@@ -0,0 +1,423 @@
+#![allow(non_snake_case)]
+
+use super::{Attribute, MeshData, VertexLayout};
+use cgmath::{InnerSpace, vec2, vec3};
+use std::collections::HashMap;
+use std::f32::consts::{FRAC_PI_2, PI, TAU};
+
+type Vector2 = cgmath::Vector2<f32>;
+type Vector3 = cgmath::Vector3<f32>;
+
+/// Layout of every generated mesh: position, normal, uv.
+pub fn standardLayout() -> VertexLayout {
+    VertexLayout::new(&[Attribute::Position, Attribute::Normal, Attribute::Uv])
+}
+
+fn pushVertex(data: &mut MeshData, pos: Vector3, normal: Vector3, uv: Vector2) -> u32 {
+    data.pushVertex(&[pos.x, pos.y, pos.z, normal.x, normal.y, normal.z, uv.x, uv.y])
+}
+
+/// Triangles between two rows of `columns + 1` vertices starting at `upper` and `lower`,
+/// counter-clockwise seen from the side the normals face. Rows run in the direction of
+/// increasing angle around +Y, `lower` below `upper`. Pole rows collapse to a point, so
+/// their degenerate half of each quad is skipped.
+fn stitchRows(data: &mut MeshData, upper: u32, lower: u32, columns: u32, upperPole: bool, lowerPole: bool) {
+    for s in 0..columns {
+        let (a, b, c, d) = (upper + s, upper + s + 1, lower + s + 1, lower + s);
+        if !upperPole {
+            data.pushTriangle(a, b, c);
+        }
+        if !lowerPole {
+            data.pushTriangle(a, c, d);
+        }
+    }
+}
+
+/// Flat disc at height `y` facing +Y (`up`) or -Y, as a fan around its center.
+fn pushCap(data: &mut MeshData, radius: f32, y: f32, segments: u32, up: bool) {
+    let normal = vec3(0.0, if up { 1.0 } else { -1.0 }, 0.0);
+    let center = pushVertex(data, vec3(0.0, y, 0.0), normal, vec2(0.5, 0.5));
+    for s in 0..=segments {
+        let theta = TAU * s as f32 / segments as f32;
+        let (sin, cos) = theta.sin_cos();
+        pushVertex(data, vec3(radius * cos, y, radius * sin), normal, vec2(0.5 + cos * 0.5, 0.5 + sin * 0.5));
+    }
+    for s in 0..segments {
+        let (current, next) = (center + 1 + s, center + 2 + s);
+        if up {
+            data.pushTriangle(center, next, current);
+        } else {
+            data.pushTriangle(center, current, next);
+        }
+    }
+}
+
+/// `width` x `depth` plane in XZ facing +Y, split into `subdivisions` quads per side.
+pub fn plane(width: f32, depth: f32, subdivisions: u32) -> MeshData {
+    let n = subdivisions.max(1);
+    let mut data = MeshData::new(standardLayout());
+    for j in 0..=n {
+        for i in 0..=n {
+            let uv = vec2(i as f32 / n as f32, j as f32 / n as f32);
+            let pos = vec3((uv.x - 0.5) * width, 0.0, (uv.y - 0.5) * depth);
+            pushVertex(&mut data, pos, vec3(0.0, 1.0, 0.0), uv);
+        }
+    }
+    let row = n + 1;
+    for j in 0..n {
+        for i in 0..n {
+            let (a, b) = (j * row + i, j * row + i + 1);
+            let (c, d) = (b + row, a + row);
+            data.pushTriangle(a, d, b);
+            data.pushTriangle(b, d, c);
+        }
+    }
+    data
+}
+
+/// Cube with edges of `size`, centered on the origin. Faces don't share vertices so each
+/// keeps its own normal and full 0..1 UVs.
+pub fn cube(size: f32) -> MeshData {
+    let h = size * 0.5;
+    // Normal, then u and v axes with u x v = normal so corners come out counter-clockwise
+    let faces = [
+        (vec3(1.0, 0.0, 0.0), vec3(0.0, 0.0, -1.0), vec3(0.0, 1.0, 0.0)),
+        (vec3(-1.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0), vec3(0.0, 1.0, 0.0)),
+        (vec3(0.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 0.0, -1.0)),
+        (vec3(0.0, -1.0, 0.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 0.0, 1.0)),
+        (vec3(0.0, 0.0, 1.0), vec3(1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0)),
+        (vec3(0.0, 0.0, -1.0), vec3(-1.0, 0.0, 0.0), vec3(0.0, 1.0, 0.0)),
+    ];
+    let mut data = MeshData::new(standardLayout());
+    for (normal, u, v) in faces {
+        let first = data.vertexCount() as u32;
+        for (su, sv) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+            let pos = (normal + u * su + v * sv) * h;
+            pushVertex(&mut data, pos, normal, vec2(su * 0.5 + 0.5, sv * 0.5 + 0.5));
+        }
+        data.pushTriangle(first, first + 1, first + 2);
+        data.pushTriangle(first, first + 2, first + 3);
+    }
+    data
+}
+
+/// Latitude/longitude sphere. `rings` runs pole to pole, UV v = 0 at the top.
+pub fn uvSphere(radius: f32, segments: u32, rings: u32) -> MeshData {
+    let (segments, rings) = (segments.max(3), rings.max(2));
+    let mut data = MeshData::new(standardLayout());
+    for r in 0..=rings {
+        let phi = PI * r as f32 / rings as f32;
+        for s in 0..=segments {
+            let theta = TAU * s as f32 / segments as f32;
+            let normal = vec3(phi.sin() * theta.cos(), phi.cos(), phi.sin() * theta.sin());
+            let uv = vec2(s as f32 / segments as f32, r as f32 / rings as f32);
+            pushVertex(&mut data, normal * radius, normal, uv);
+        }
+    }
+    let row = segments + 1;
+    for r in 0..rings {
+        stitchRows(&mut data, r * row, (r + 1) * row, segments, r == 0, r == rings - 1);
+    }
+    data
+}
+
+/// Sphere from a subdivided icosahedron, with evenly sized triangles. Every subdivision
+/// splits each triangle in four: `10 * 4^n + 2` vertices.
+pub fn icosphere(radius: f32, subdivisions: u32) -> MeshData {
+    let t = (1.0 + 5.0f32.sqrt()) * 0.5;
+    let mut directions: Vec<Vector3> = [
+        (-1.0, t, 0.0),
+        (1.0, t, 0.0),
+        (-1.0, -t, 0.0),
+        (1.0, -t, 0.0),
+        (0.0, -1.0, t),
+        (0.0, 1.0, t),
+        (0.0, -1.0, -t),
+        (0.0, 1.0, -t),
+        (t, 0.0, -1.0),
+        (t, 0.0, 1.0),
+        (-t, 0.0, -1.0),
+        (-t, 0.0, 1.0),
+    ]
+    .iter()
+    .map(|&(x, y, z)| vec3(x, y, z).normalize())
+    .collect();
+    #[rustfmt::skip]
+    let mut triangles: Vec<[u32; 3]> = vec![
+        [0, 11, 5], [0, 5, 1], [0, 1, 7], [0, 7, 10], [0, 10, 11],
+        [1, 5, 9], [5, 11, 4], [11, 10, 2], [10, 7, 6], [7, 1, 8],
+        [3, 9, 4], [3, 4, 2], [3, 2, 6], [3, 6, 8], [3, 8, 9],
+        [4, 9, 5], [2, 4, 11], [6, 2, 10], [8, 6, 7], [9, 8, 1],
+    ];
+
+    for _ in 0..subdivisions {
+        // Edges are shared by two triangles, cache midpoints so they share the vertex too
+        let mut midpoints: HashMap<(u32, u32), u32> = HashMap::new();
+        let mut midpoint = |a: u32, b: u32| -> u32 {
+            *midpoints.entry((a.min(b), a.max(b))).or_insert_with(|| {
+                directions.push((directions[a as usize] + directions[b as usize]).normalize());
+                directions.len() as u32 - 1
+            })
+        };
+        triangles = triangles
+            .iter()
+            .flat_map(|&[a, b, c]| {
+                let (ab, bc, ca) = (midpoint(a, b), midpoint(b, c), midpoint(c, a));
+                [[a, ab, ca], [b, bc, ab], [c, ca, bc], [ab, bc, ca]]
+            })
+            .collect();
+    }
+
+    let mut data = MeshData::new(standardLayout());
+    for dir in &directions {
+        let uv = vec2(0.5 + dir.z.atan2(dir.x) / TAU, dir.y.clamp(-1.0, 1.0).acos() / PI);
+        pushVertex(&mut data, dir * radius, *dir, uv);
+    }
+    for [a, b, c] in triangles {
+        data.pushTriangle(a, b, c);
+    }
+    data
+}
+
+/// Capped cylinder along Y, centered on the origin.
+pub fn cylinder(radius: f32, height: f32, segments: u32) -> MeshData {
+    let segments = segments.max(3);
+    let h = height * 0.5;
+    let mut data = MeshData::new(standardLayout());
+    for (row, y) in [h, -h].into_iter().enumerate() {
+        for s in 0..=segments {
+            let theta = TAU * s as f32 / segments as f32;
+            let normal = vec3(theta.cos(), 0.0, theta.sin());
+            let uv = vec2(s as f32 / segments as f32, row as f32);
+            pushVertex(&mut data, vec3(normal.x * radius, y, normal.z * radius), normal, uv);
+        }
+    }
+    stitchRows(&mut data, 0, segments + 1, segments, false, false);
+    pushCap(&mut data, radius, h, segments, true);
+    pushCap(&mut data, radius, -h, segments, false);
+    data
+}
+
+/// Cone along Y with its apex at `height / 2` and base at `-height / 2`.
+pub fn cone(radius: f32, height: f32, segments: u32) -> MeshData {
+    let segments = segments.max(3);
+    let h = height * 0.5;
+    // Slanted side normal, one apex vertex per segment so each face gets its own
+    let normalAt = |theta: f32| vec3(height * theta.cos(), radius, height * theta.sin()).normalize();
+    let mut data = MeshData::new(standardLayout());
+    for s in 0..segments {
+        let theta = TAU * (s as f32 + 0.5) / segments as f32;
+        pushVertex(&mut data, vec3(0.0, h, 0.0), normalAt(theta), vec2((s as f32 + 0.5) / segments as f32, 0.0));
+    }
+    for s in 0..=segments {
+        let theta = TAU * s as f32 / segments as f32;
+        let pos = vec3(radius * theta.cos(), -h, radius * theta.sin());
+        pushVertex(&mut data, pos, normalAt(theta), vec2(s as f32 / segments as f32, 1.0));
+    }
+    stitchRows(&mut data, 0, segments, segments, true, false);
+    pushCap(&mut data, radius, -h, segments, false);
+    data
+}
+
+/// Torus around Y. `majorRadius` to the center of the tube, `minorRadius` of the tube.
+pub fn torus(majorRadius: f32, minorRadius: f32, majorSegments: u32, minorSegments: u32) -> MeshData {
+    let (major, minor) = (majorSegments.max(3), minorSegments.max(3));
+    let mut data = MeshData::new(standardLayout());
+    // Rows go around the tube top to bottom (phi from PI/2 down through the outside)
+    for j in 0..=minor {
+        let phi = FRAC_PI_2 - TAU * j as f32 / minor as f32;
+        for i in 0..=major {
+            let theta = TAU * i as f32 / major as f32;
+            let center = vec3(theta.cos(), 0.0, theta.sin()) * majorRadius;
+            let normal = vec3(phi.cos() * theta.cos(), phi.sin(), phi.cos() * theta.sin());
+            let uv = vec2(i as f32 / major as f32, j as f32 / minor as f32);
+            pushVertex(&mut data, center + normal * minorRadius, normal, uv);
+        }
+    }
+    let row = major + 1;
+    for j in 0..minor {
+        stitchRows(&mut data, j * row, (j + 1) * row, major, false, false);
+    }
+    data
+}
+
+/// Cylinder of `height` along Y with hemispherical ends, `height + 2 * radius` tall overall.
+/// `rings` is per hemisphere.
+pub fn capsule(radius: f32, height: f32, segments: u32, rings: u32) -> MeshData {
+    let (segments, rings) = (segments.max(3), rings.max(1));
+    let h = height * 0.5;
+    let total = height + 2.0 * radius;
+    let mut data = MeshData::new(standardLayout());
+    // Top hemisphere rows down to its equator, then the bottom one from its equator, the
+    // gap between both equators is the cylinder
+    let rows: Vec<(f32, f32)> = (0..=rings)
+        .map(|r| (FRAC_PI_2 * r as f32 / rings as f32, h))
+        .chain((0..=rings).map(|r| (FRAC_PI_2 + FRAC_PI_2 * r as f32 / rings as f32, -h)))
+        .collect();
+    for &(phi, offset) in &rows {
+        for s in 0..=segments {
+            let theta = TAU * s as f32 / segments as f32;
+            let normal = vec3(phi.sin() * theta.cos(), phi.cos(), phi.sin() * theta.sin());
+            let pos = normal * radius + vec3(0.0, offset, 0.0);
+            let uv = vec2(s as f32 / segments as f32, 0.5 - pos.y / total);
+            pushVertex(&mut data, pos, normal, uv);
+        }
+    }
+    let row = segments + 1;
+    let last = rows.len() as u32 - 1;
+    for r in 0..last {
+        stitchRows(&mut data, r * row, (r + 1) * row, segments, r == 0, r == last - 1);
+    }
+    data
+}
+
+/// The bent star from the original demo, flat shaded: every triangle has its own vertices.
+pub fn star() -> MeshData {
+    let points = [
+        vec3(-0.5, 0.5, 0.5),   // top left
+        vec3(0.0, 0.5, 0.0),    // top
+        vec3(0.5, 0.5, 0.5),    // top right
+        vec3(0.5, 0.0, 0.0),    // right
+        vec3(0.5, -0.5, 0.5),   // bottom right
+        vec3(0.0, -0.5, 0.0),   // bottom
+        vec3(-0.5, -0.5, 0.5),  // bottom left
+        vec3(-0.5, 0.0, 0.0),   // left
+    ];
+    #[rustfmt::skip]
+    let triangles = [
+        [0, 7, 1], // top left
+        [1, 3, 2], // top right
+        [3, 5, 4], // bottom right
+        [7, 6, 5], // bottom left
+        [1, 7, 5], [1, 5, 3],
+    ];
+    let mut data = MeshData::new(standardLayout());
+    for triangle in triangles {
+        let [a, b, c] = triangle.map(|i| points[i]);
+        let normal = (b - a).cross(c - a).normalize();
+        let indices = [a, b, c].map(|p| pushVertex(&mut data, p, normal, vec2(p.x + 0.5, p.y + 0.5)));
+        data.pushTriangle(indices[0], indices[1], indices[2]);
+    }
+    data
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(data: &MeshData, i: usize) -> Vector3 {
+        let p = data.attribute(i, Attribute::Position).unwrap();
+        vec3(p[0], p[1], p[2])
+    }
+
+    fn normal(data: &MeshData, i: usize) -> Vector3 {
+        let n = data.attribute(i, Attribute::Normal).unwrap();
+        vec3(n[0], n[1], n[2])
+    }
+
+    /// Valid indices, unit normals, UVs in 0..1, and counter-clockwise triangles whose
+    /// normals point away from `center(position)`.
+    fn checkMesh(data: &MeshData, center: impl Fn(Vector3) -> Vector3) {
+        data.validate();
+        assert_eq!(data.indices.len() % 3, 0);
+        for i in 0..data.vertexCount() {
+            let n = normal(data, i);
+            assert!((n.magnitude() - 1.0).abs() < 1e-4, "normal {:?} of vertex {} isn't unit length", n, i);
+            let p = position(data, i);
+            assert!(n.dot(p - center(p)) > 0.0, "normal {:?} at {:?} points inward", n, p);
+            let uv = data.attribute(i, Attribute::Uv).unwrap();
+            assert!(uv.iter().all(|&c| (-1e-4..=1.0 + 1e-4).contains(&c)), "uv {:?} out of range", uv);
+        }
+        for triangle in data.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|k| triangle[k] as usize);
+            let face = (position(data, b) - position(data, a)).cross(position(data, c) - position(data, a));
+            assert!(face.magnitude() > 1e-7, "degenerate triangle {:?}", triangle);
+            let average = normal(data, a) + normal(data, b) + normal(data, c);
+            assert!(face.dot(average) > 0.0, "triangle {:?} is wound clockwise", triangle);
+        }
+    }
+
+    fn origin(_: Vector3) -> Vector3 {
+        vec3(0.0, 0.0, 0.0)
+    }
+
+    #[test]
+    fn planeFacesUp() {
+        let data = plane(2.0, 3.0, 4);
+        assert_eq!(data.vertexCount(), 25);
+        assert_eq!(data.indices.len(), 4 * 4 * 6);
+        checkMesh(&data, |p| p - vec3(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn cubeHasSeparateFaces() {
+        let data = cube(2.0);
+        assert_eq!(data.vertexCount(), 24);
+        assert_eq!(data.indices.len(), 36);
+        checkMesh(&data, origin);
+        assert!((0..24).all(|i| position(&data, i).map(f32::abs) == vec3(1.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn uvSphereCounts() {
+        let data = uvSphere(1.5, 16, 8);
+        assert_eq!(data.vertexCount(), 17 * 9);
+        assert_eq!(data.indices.len(), 16 * (8 - 1) * 6);
+        checkMesh(&data, origin);
+        assert!((0..data.vertexCount()).all(|i| (position(&data, i).magnitude() - 1.5).abs() < 1e-4));
+    }
+
+    #[test]
+    fn icosphereCounts() {
+        for (subdivisions, vertices) in [(0, 12), (1, 42), (2, 162)] {
+            let data = icosphere(1.0, subdivisions);
+            assert_eq!(data.vertexCount(), vertices);
+            assert_eq!(data.indices.len(), 20 * 4usize.pow(subdivisions) * 3);
+            checkMesh(&data, origin);
+        }
+    }
+
+    #[test]
+    fn cylinderCounts() {
+        let data = cylinder(0.5, 2.0, 12);
+        assert_eq!(data.vertexCount(), 2 * 13 + 2 * (1 + 13));
+        assert_eq!(data.indices.len(), 12 * 6 + 2 * 12 * 3);
+        checkMesh(&data, origin);
+    }
+
+    #[test]
+    fn coneCounts() {
+        let data = cone(0.5, 1.0, 10);
+        assert_eq!(data.vertexCount(), 10 + 11 + 1 + 11);
+        assert_eq!(data.indices.len(), 10 * 3 + 10 * 3);
+        checkMesh(&data, origin);
+    }
+
+    #[test]
+    fn torusCounts() {
+        let data = torus(1.0, 0.25, 24, 8);
+        assert_eq!(data.vertexCount(), 25 * 9);
+        assert_eq!(data.indices.len(), 24 * 8 * 6);
+        // Outward from the tube's center line, not the origin
+        checkMesh(&data, |p| vec3(p.x, 0.0, p.z).normalize());
+    }
+
+    #[test]
+    fn capsuleCounts() {
+        let data = capsule(0.5, 1.0, 12, 4);
+        assert_eq!(data.vertexCount(), 13 * 10);
+        assert_eq!(data.indices.len(), 12 * (9 - 1) * 6);
+        checkMesh(&data, |p| vec3(0.0, p.y.clamp(-0.5, 0.5), 0.0));
+        let top = (0..data.vertexCount()).map(|i| position(&data, i).y).fold(f32::MIN, f32::max);
+        assert!((top - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn starIsFlatShaded() {
+        let data = star();
+        assert_eq!(data.vertexCount(), 18);
+        assert_eq!(data.indices.len(), 18);
+        // Open surface, just check it faces the viewer (+Z) at the default camera
+        checkMesh(&data, |p| p - vec3(0.0, 0.0, 1.0));
+    }
+}
@@ -0,0 +1,618 @@
+#![allow(non_snake_case)]
+
+use super::generators::standardLayout;
+use super::obj::Material;
+use super::{Attribute, MeshData, VertexLayout};
+use cgmath::{EuclideanSpace, InnerSpace, SquareMatrix, Transform, vec3};
+use gltf::image::Format;
+use gltf::mesh::Mode;
+
+type Point3 = cgmath::Point3<f32>;
+type Vector3 = cgmath::Vector3<f32>;
+type Vector4 = cgmath::Vector4<f32>;
+type Matrix4 = cgmath::Matrix4<f32>;
+
+/// Decoded image, always converted to 8-bit RGBA.
+#[derive(Clone, Debug)]
+#[allow(dead_code)]
+pub struct GltfImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+/// A material's texture: an index into `GltfScene::images` and the sampler state as GL enums.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct TextureRef {
+    pub image: usize,
+    pub texCoord: u32,
+    pub magFilter: u32,
+    pub minFilter: u32,
+    pub wrapS: u32,
+    pub wrapT: u32,
+}
+
+/// glTF metallic-roughness material. Texture factors multiply the texture samples.
+#[derive(Clone, Debug)]
+#[allow(dead_code)]
+pub struct PbrMaterial {
+    pub name: String,
+    pub baseColor: Vector4,
+    pub metallic: f32,
+    pub roughness: f32,
+    pub emissive: Vector3,
+    pub baseColorTexture: Option<TextureRef>,
+    /// Roughness in green, metalness in blue.
+    pub metallicRoughnessTexture: Option<TextureRef>,
+    pub normalTexture: Option<TextureRef>,
+    pub occlusionTexture: Option<TextureRef>,
+    pub emissiveTexture: Option<TextureRef>,
+    pub doubleSided: bool,
+}
+
+#[allow(dead_code)]
+impl PbrMaterial {
+    /// Closest Blinn-Phong material, for the lit shader: metals tint their highlights and
+    /// rougher surfaces get wider ones. Textures are ignored.
+    pub fn toPhong(&self) -> Material {
+        let base = self.baseColor.truncate();
+        let dielectric = vec3(0.04, 0.04, 0.04);
+        // alpha = roughness^2, the usual Beckmann to Phong exponent conversion
+        let alpha = (self.roughness * self.roughness).max(1e-3);
+        let mut material = Material::new(&self.name);
+        material.ambient = self.emissive;
+        material.diffuse = base * (1.0 - self.metallic);
+        material.specular = dielectric + (base - dielectric) * self.metallic;
+        material.shininess = (2.0 / (alpha * alpha) - 2.0).clamp(1.0, 1024.0);
+        material.opacity = self.baseColor.w;
+        material
+    }
+
+    /// Inverse of `toPhong` for OBJ and generated meshes, so they can use the PBR shader.
+    /// The diffuse map becomes the base color texture, which the caller binds itself.
+    pub fn fromPhong(material: &Material) -> PbrMaterial {
+        // Specular brighter than the dielectric 4% is taken as metal tinting its highlights
+        let specular = material.specular.x.max(material.specular.y).max(material.specular.z);
+        let metallic = ((specular - 0.04) / 0.96).clamp(0.0, 1.0);
+        let base = material.diffuse + (material.specular - material.diffuse) * metallic;
+        let alpha = (2.0 / (material.shininess.max(1.0) + 2.0)).sqrt();
+        PbrMaterial {
+            name: material.name.clone(),
+            baseColor: base.extend(material.opacity),
+            metallic,
+            roughness: alpha.sqrt(),
+            emissive: material.ambient,
+            baseColorTexture: None,
+            metallicRoughnessTexture: None,
+            normalTexture: None,
+            occlusionTexture: None,
+            emissiveTexture: None,
+            doubleSided: true,
+        }
+    }
+
+    /// The glTF default material, for primitives without one.
+    pub fn gltfDefault() -> PbrMaterial {
+        PbrMaterial {
+            name: "default".to_string(),
+            baseColor: Vector4::new(1.0, 1.0, 1.0, 1.0),
+            metallic: 1.0,
+            roughness: 1.0,
+            emissive: Vector3::new(0.0, 0.0, 0.0),
+            baseColorTexture: None,
+            metallicRoughnessTexture: None,
+            normalTexture: None,
+            occlusionTexture: None,
+            emissiveTexture: None,
+            doubleSided: false,
+        }
+    }
+
+    /// UV set the textures sample: the base color texture's, else the first texture's.
+    pub fn texCoord(&self) -> u32 {
+        [
+            self.baseColorTexture,
+            self.metallicRoughnessTexture,
+            self.normalTexture,
+            self.occlusionTexture,
+            self.emissiveTexture,
+        ]
+        .into_iter()
+        .flatten()
+        .next()
+        .map_or(0, |texture| texture.texCoord)
+    }
+}
+
+pub struct Primitive {
+    pub data: MeshData,
+    /// Index into `GltfScene::materials`, `None` for the glTF default material.
+    pub material: Option<usize>,
+}
+
+#[allow(dead_code)]
+pub struct SceneMesh {
+    pub name: String,
+    pub primitives: Vec<Primitive>,
+}
+
+/// Perspective camera, looking down its node's -Z.
+#[derive(Clone, Debug)]
+#[allow(dead_code)]
+pub struct SceneCamera {
+    pub name: String,
+    /// Vertical field of view in radians, `None` for orthographic cameras.
+    pub yfov: Option<f32>,
+    pub znear: f32,
+    /// `None` for an infinite projection.
+    pub zfar: Option<f32>,
+    pub aspect: Option<f32>,
+}
+
+#[allow(dead_code)]
+pub struct SceneNode {
+    pub name: String,
+    /// Relative to the parent.
+    pub local: Matrix4,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub mesh: Option<usize>,
+    pub camera: Option<usize>,
+}
+
+/// A glTF file's default scene. Nodes, meshes, materials, images and cameras keep their
+/// glTF indices.
+pub struct GltfScene {
+    pub nodes: Vec<SceneNode>,
+    /// Top-level nodes of the default scene.
+    pub roots: Vec<usize>,
+    pub meshes: Vec<SceneMesh>,
+    pub materials: Vec<PbrMaterial>,
+    pub images: Vec<GltfImage>,
+    pub cameras: Vec<SceneCamera>,
+}
+
+#[allow(dead_code)]
+impl GltfScene {
+    pub fn worldTransform(&self, node: usize) -> Matrix4 {
+        let mut transform = self.nodes[node].local;
+        let mut current = self.nodes[node].parent;
+        while let Some(parent) = current {
+            transform = self.nodes[parent].local * transform;
+            current = self.nodes[parent].parent;
+        }
+        transform
+    }
+
+    /// Every mesh instance reachable from the roots, with its world transform.
+    pub fn drawList(&self) -> Vec<(usize, Matrix4)> {
+        let mut list = Vec::new();
+        let mut stack: Vec<(usize, Matrix4)> = self.roots.iter().rev().map(|&root| (root, Matrix4::identity())).collect();
+        while let Some((index, parentTransform)) = stack.pop() {
+            let node = &self.nodes[index];
+            let world = parentTransform * node.local;
+            if let Some(mesh) = node.mesh {
+                list.push((mesh, world));
+            }
+            stack.extend(node.children.iter().rev().map(|&child| (child, world)));
+        }
+        list
+    }
+
+    /// Nodes carrying a camera, in node order.
+    pub fn cameraNodes(&self) -> Vec<usize> {
+        (0..self.nodes.len()).filter(|&i| self.nodes[i].camera.is_some()).collect()
+    }
+
+    /// World position, view direction and up vector of a camera node.
+    pub fn cameraPose(&self, node: usize) -> (Point3, Vector3, Vector3) {
+        let world = self.worldTransform(node);
+        let pos = world.transform_point(Point3::origin());
+        let front = world.transform_vector(vec3(0.0, 0.0, -1.0)).normalize();
+        let up = world.transform_vector(vec3(0.0, 1.0, 0.0)).normalize();
+        (pos, front, up)
+    }
+
+    pub fn triangleCount(&self) -> usize {
+        self.meshes
+            .iter()
+            .flat_map(|mesh| mesh.primitives.iter())
+            .map(|primitive| primitive.data.indices.len() / 3)
+            .sum()
+    }
+}
+
+fn convertImage(data: gltf::image::Data) -> GltfImage {
+    let channels = match data.format {
+        Format::R8 | Format::R16 => 1,
+        Format::R8G8 | Format::R16G16 => 2,
+        Format::R8G8B8 | Format::R16G16B16 => 3,
+        Format::R8G8B8A8 | Format::R16G16B16A16 => 4,
+        // Float images aren't supported, they become white
+        Format::R32G32B32FLOAT | Format::R32G32B32A32FLOAT => {
+            return GltfImage {
+                width: 1,
+                height: 1,
+                pixels: vec![255; 4],
+            };
+        }
+    };
+    let wide = matches!(
+        data.format,
+        Format::R16 | Format::R16G16 | Format::R16G16B16 | Format::R16G16B16A16
+    );
+    // 16-bit channels are little endian, keep their high byte
+    let samples: Vec<u8> = if wide {
+        data.pixels.chunks_exact(2).map(|pair| pair[1]).collect()
+    } else {
+        data.pixels
+    };
+    let mut pixels = Vec::with_capacity((data.width * data.height * 4) as usize);
+    for texel in samples.chunks_exact(channels) {
+        let rgba = match channels {
+            1 => [texel[0], texel[0], texel[0], 255],
+            2 => [texel[0], texel[1], 0, 255],
+            3 => [texel[0], texel[1], texel[2], 255],
+            _ => [texel[0], texel[1], texel[2], texel[3]],
+        };
+        pixels.extend_from_slice(&rgba);
+    }
+    GltfImage {
+        width: data.width,
+        height: data.height,
+        pixels,
+    }
+}
+
+fn textureRef(texture: gltf::Texture, texCoord: u32) -> TextureRef {
+    let sampler = texture.sampler();
+    TextureRef {
+        image: texture.source().index(),
+        texCoord,
+        magFilter: sampler.mag_filter().map_or(gl::LINEAR, |f| f.as_gl_enum()),
+        minFilter: sampler.min_filter().map_or(gl::LINEAR_MIPMAP_LINEAR, |f| f.as_gl_enum()),
+        wrapS: sampler.wrap_s().as_gl_enum(),
+        wrapT: sampler.wrap_t().as_gl_enum(),
+    }
+}
+
+fn convertMaterial(material: gltf::Material) -> PbrMaterial {
+    let pbr = material.pbr_metallic_roughness();
+    let info = |info: Option<gltf::texture::Info>| info.map(|info| textureRef(info.texture(), info.tex_coord()));
+    let mut converted = PbrMaterial {
+        name: material.name().unwrap_or("unnamed").to_string(),
+        baseColor: pbr.base_color_factor().into(),
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        emissive: material.emissive_factor().into(),
+        baseColorTexture: info(pbr.base_color_texture()),
+        metallicRoughnessTexture: info(pbr.metallic_roughness_texture()),
+        normalTexture: material.normal_texture().map(|t| textureRef(t.texture(), t.tex_coord())),
+        occlusionTexture: material.occlusion_texture().map(|t| textureRef(t.texture(), t.tex_coord())),
+        emissiveTexture: info(material.emissive_texture()),
+        doubleSided: material.double_sided(),
+    };
+    // Vertices carry a single UV set, textures on another one would sample the wrong coordinates
+    let texCoord = converted.texCoord();
+    for texture in [
+        &mut converted.baseColorTexture,
+        &mut converted.metallicRoughnessTexture,
+        &mut converted.normalTexture,
+        &mut converted.occlusionTexture,
+        &mut converted.emissiveTexture,
+    ] {
+        if texture.is_some_and(|texture| texture.texCoord != texCoord) {
+            *texture = None;
+        }
+    }
+    converted
+}
+
+/// Triangle list indices for a primitive's mode, `None` for points and lines.
+fn triangulate(mode: Mode, indices: Vec<u32>) -> Option<Vec<u32>> {
+    match mode {
+        Mode::Triangles => Some(indices),
+        Mode::TriangleStrip => Some(
+            (2..indices.len())
+                .flat_map(|i| {
+                    // Every other triangle is flipped to keep the winding
+                    if i % 2 == 0 {
+                        [indices[i - 2], indices[i - 1], indices[i]]
+                    } else {
+                        [indices[i - 1], indices[i - 2], indices[i]]
+                    }
+                })
+                .collect(),
+        ),
+        Mode::TriangleFan => Some(
+            (2..indices.len())
+                .flat_map(|i| [indices[0], indices[i - 1], indices[i]])
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+/// `texCoord` is the UV set of the primitive's material, see `PbrMaterial::texCoord`.
+fn convertPrimitive(
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+    texCoord: u32,
+) -> Result<Option<Primitive>, String> {
+    let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+    let Some(positions) = reader.read_positions() else {
+        return Err("primitive without POSITION".to_string());
+    };
+    let positions: Vec<[f32; 3]> = positions.collect();
+    let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|n| n.collect());
+    let uvs: Option<Vec<[f32; 2]>> = reader.read_tex_coords(texCoord).map(|uv| uv.into_f32().collect());
+    let colors: Option<Vec<[f32; 3]>> = reader.read_colors(0).map(|c| c.into_rgb_f32().collect());
+    let tangents: Option<Vec<[f32; 4]>> = reader.read_tangents().map(|t| t.collect());
+    let texCoordName = format!("TEXCOORD_{}", texCoord);
+    let lengths = [
+        ("NORMAL", normals.as_ref().map(Vec::len)),
+        (texCoordName.as_str(), uvs.as_ref().map(Vec::len)),
+        ("COLOR_0", colors.as_ref().map(Vec::len)),
+        ("TANGENT", tangents.as_ref().map(Vec::len)),
+    ];
+    for (name, len) in lengths {
+        if let Some(len) = len
+            && len != positions.len()
+        {
+            return Err(format!("{} has {} elements for {} positions", name, len, positions.len()));
+        }
+    }
+
+    let mut attributes = standardLayout().attributes().to_vec();
+    if colors.is_some() {
+        attributes.push(Attribute::Color);
+    }
+    if tangents.is_some() {
+        attributes.push(Attribute::Tangent);
+    }
+    let mut data = MeshData::new(VertexLayout::new(&attributes));
+    let mut vertex = Vec::with_capacity(data.layout.floats());
+    for (i, p) in positions.iter().enumerate() {
+        vertex.clear();
+        vertex.extend_from_slice(p);
+        vertex.extend_from_slice(&normals.as_ref().map_or([0.0; 3], |n| n[i]));
+        vertex.extend_from_slice(&uvs.as_ref().map_or([0.0; 2], |uv| uv[i]));
+        if let Some(colors) = &colors {
+            vertex.extend_from_slice(&colors[i]);
+        }
+        if let Some(tangents) = &tangents {
+            vertex.extend_from_slice(&tangents[i]);
+        }
+        data.pushVertex(&vertex);
+    }
+
+    let indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    let Some(indices) = triangulate(primitive.mode(), indices) else {
+        return Ok(None);
+    };
+    data.indices = indices;
+    data.check()?;
+    if normals.is_none() {
+        data.smoothNormals(0..positions.len() as u32);
+    }
+    Ok(Some(Primitive {
+        data,
+        material: primitive.material().index(),
+    }))
+}
+
+/// Loads a `.gltf` (with embedded or external buffers and images) or `.glb` file. Only the
+/// default scene's nodes are roots, skins, morph targets and animations are ignored.
+pub fn loadGltf(path: &str) -> Result<GltfScene, String> {
+    let (document, buffers, images) = gltf::import(path).map_err(|e| format!("{}: {}", path, e))?;
+
+    let materials: Vec<PbrMaterial> = document.materials().map(convertMaterial).collect();
+    let mut meshes = Vec::new();
+    for mesh in document.meshes() {
+        let mut primitives = Vec::new();
+        for primitive in mesh.primitives() {
+            let texCoord = primitive.material().index().map_or(0, |i| materials[i].texCoord());
+            let converted = convertPrimitive(&primitive, &buffers, texCoord)
+                .map_err(|e| format!("{}: mesh {}: {}", path, mesh.index(), e))?;
+            primitives.extend(converted);
+        }
+        meshes.push(SceneMesh {
+            name: mesh.name().map_or_else(|| format!("mesh {}", mesh.index()), str::to_string),
+            primitives,
+        });
+    }
+
+    let mut nodes: Vec<SceneNode> = document
+        .nodes()
+        .map(|node| SceneNode {
+            name: node.name().map_or_else(|| format!("node {}", node.index()), str::to_string),
+            local: node.transform().matrix().into(),
+            parent: None,
+            children: node.children().map(|child| child.index()).collect(),
+            mesh: node.mesh().map(|mesh| mesh.index()),
+            camera: node.camera().map(|camera| camera.index()),
+        })
+        .collect();
+    for i in 0..nodes.len() {
+        for child in nodes[i].children.clone() {
+            nodes[child].parent = Some(i);
+        }
+    }
+    let roots = match document.default_scene().or_else(|| document.scenes().next()) {
+        Some(scene) => scene.nodes().map(|node| node.index()).collect(),
+        None => (0..nodes.len()).filter(|&i| nodes[i].parent.is_none()).collect(),
+    };
+
+    let cameras = document
+        .cameras()
+        .map(|camera| {
+            let name = camera.name().unwrap_or("camera").to_string();
+            match camera.projection() {
+                gltf::camera::Projection::Perspective(p) => SceneCamera {
+                    name,
+                    yfov: Some(p.yfov()),
+                    znear: p.znear(),
+                    zfar: p.zfar(),
+                    aspect: p.aspect_ratio(),
+                },
+                gltf::camera::Projection::Orthographic(o) => SceneCamera {
+                    name,
+                    yfov: None,
+                    znear: o.znear(),
+                    zfar: Some(o.zfar()),
+                    aspect: Some(o.xmag() / o.ymag()),
+                },
+            }
+        })
+        .collect();
+
+    Ok(GltfScene {
+        nodes,
+        roots,
+        meshes,
+        materials,
+        images: images.into_iter().map(convertImage).collect(),
+        cameras,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec4;
+
+    const FIXTURE: &str = "resources/models/fixture.gltf";
+
+    fn close(a: Vector3, b: Vector3) -> bool {
+        (a - b).magnitude() < 1e-5
+    }
+
+    fn node(scene: &GltfScene, name: &str) -> usize {
+        scene.nodes.iter().position(|node| node.name == name).unwrap()
+    }
+
+    #[test]
+    fn hierarchyComposesTransforms() {
+        let scene = loadGltf(FIXTURE).unwrap();
+        assert_eq!(scene.roots, [0, 3]);
+        let quad = node(&scene, "QuadNode");
+        assert_eq!(scene.nodes[quad].parent, Some(0));
+        assert_eq!(scene.nodes[0].children, [1, 2]);
+        // Root lifts by 1, the quad node doubles
+        let corner = scene.worldTransform(quad).transform_point(Point3::new(0.5, 0.5, 0.0));
+        assert!(close(corner.to_vec(), vec3(1.0, 2.0, 0.0)), "{:?}", corner);
+        // 90 degrees around Y turns +X into -Z
+        let triangle = scene.worldTransform(node(&scene, "TriangleNode"));
+        assert!(close(triangle.transform_point(Point3::new(1.0, 0.0, 0.0)).to_vec(), vec3(1.0, 1.0, -1.0)));
+    }
+
+    #[test]
+    fn drawListMatchesWorldTransforms() {
+        let scene = loadGltf(FIXTURE).unwrap();
+        let list = scene.drawList();
+        assert_eq!(list.iter().map(|&(mesh, _)| mesh).collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(list[1].1, scene.worldTransform(node(&scene, "TriangleNode")));
+    }
+
+    #[test]
+    fn meshesAreTriangulatedWithNormals() {
+        let scene = loadGltf(FIXTURE).unwrap();
+        assert_eq!(scene.triangleCount(), 3);
+        let quad = &scene.meshes[0].primitives[0];
+        assert_eq!(quad.material, Some(0));
+        assert_eq!(quad.data.vertexCount(), 4);
+        assert_eq!(quad.data.attribute(2, Attribute::Uv), Some(&[1.0, 0.0][..]));
+        // The triangle has no NORMAL, so they're computed
+        let triangle = &scene.meshes[1].primitives[0].data;
+        triangle.validate();
+        for i in 0..triangle.vertexCount() {
+            assert_eq!(triangle.attribute(i, Attribute::Normal), Some(&[0.0, 0.0, 1.0][..]));
+        }
+    }
+
+    #[test]
+    fn materialsKeepFactorsAndSamplers() {
+        let scene = loadGltf(FIXTURE).unwrap();
+        let red = &scene.materials[0];
+        assert_eq!(red.baseColor, vec4(1.0, 0.0, 0.0, 1.0));
+        assert_eq!((red.metallic, red.roughness), (0.25, 0.75));
+        assert!(!red.doubleSided);
+        let texture = red.baseColorTexture.unwrap();
+        assert_eq!(texture.image, 0);
+        assert_eq!((texture.magFilter, texture.minFilter), (gl::NEAREST, gl::NEAREST));
+        assert_eq!((texture.wrapS, texture.wrapT), (gl::CLAMP_TO_EDGE, gl::REPEAT));
+        // Its occlusion map is on TEXCOORD_1, which the mesh doesn't carry
+        assert!(red.occlusionTexture.is_none());
+        assert_eq!(red.texCoord(), 0);
+
+        let checker = &scene.materials[1];
+        assert!(checker.doubleSided);
+        assert_eq!(checker.emissive, vec3(0.1, 0.2, 0.3));
+        assert_eq!((checker.metallic, checker.roughness), (1.0, 1.0));
+        assert_eq!(checker.baseColorTexture.unwrap().image, 1);
+        assert_eq!(checker.baseColorTexture.unwrap().minFilter, gl::LINEAR_MIPMAP_LINEAR);
+    }
+
+    #[test]
+    fn phongApproximationFollowsMetalness() {
+        let scene = loadGltf(FIXTURE).unwrap();
+        let red = scene.materials[0].toPhong();
+        assert!(close(red.diffuse, vec3(0.75, 0.0, 0.0)));
+        assert!(close(red.specular, vec3(0.28, 0.03, 0.03)));
+        // Fully rough
+        let checker = scene.materials[1].toPhong();
+        assert_eq!(checker.shininess, 1.0);
+        assert!(close(checker.ambient, vec3(0.1, 0.2, 0.3)));
+    }
+
+    #[test]
+    fn phongRoundTripKeepsRoughness() {
+        let scene = loadGltf(FIXTURE).unwrap();
+        let red = PbrMaterial::fromPhong(&scene.materials[0].toPhong());
+        assert!((red.roughness - 0.75).abs() < 1e-3, "{}", red.roughness);
+        // Defaults are matte dielectrics
+        let default = PbrMaterial::fromPhong(&Material::new("default"));
+        assert_eq!(default.metallic, 0.0);
+        assert_eq!(default.baseColor, vec4(0.8, 0.8, 0.8, 1.0));
+
+        let gltfDefault = PbrMaterial::gltfDefault();
+        assert_eq!(gltfDefault.baseColor, vec4(1.0, 1.0, 1.0, 1.0));
+        assert_eq!((gltfDefault.metallic, gltfDefault.roughness), (1.0, 1.0));
+    }
+
+    #[test]
+    fn embeddedAndExternalImagesBecomeRgba() {
+        let scene = loadGltf(FIXTURE).unwrap();
+        let embedded = &scene.images[0];
+        assert_eq!((embedded.width, embedded.height), (2, 2));
+        assert_eq!(&embedded.pixels[..4], &[255, 0, 0, 255]);
+        assert_eq!(&embedded.pixels[12..], &[255, 255, 255, 128]);
+        // checker.png is RGB, alpha gets filled in
+        let external = &scene.images[1];
+        assert_eq!(external.pixels.len(), 2 * 2 * 4);
+        assert_eq!(&external.pixels[4..8], &[0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn camerasHavePoses() {
+        let scene = loadGltf(FIXTURE).unwrap();
+        assert_eq!(scene.cameraNodes(), [3]);
+        let camera = &scene.cameras[scene.nodes[3].camera.unwrap()];
+        assert_eq!(camera.yfov, Some(0.8));
+        assert_eq!((camera.znear, camera.zfar, camera.aspect), (0.1, Some(100.0), Some(1.5)));
+        let (pos, front, up) = scene.cameraPose(3);
+        assert!(close(pos.to_vec(), vec3(0.0, 0.0, 5.0)));
+        assert!(close(front, vec3(0.0, 0.0, -1.0)));
+        assert!(close(up, vec3(0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn stripsAndFansBecomeLists() {
+        assert_eq!(triangulate(Mode::TriangleStrip, vec![0, 1, 2, 3]), Some(vec![0, 1, 2, 2, 1, 3]));
+        assert_eq!(triangulate(Mode::TriangleFan, vec![0, 1, 2, 3]), Some(vec![0, 1, 2, 0, 2, 3]));
+        assert_eq!(triangulate(Mode::Lines, vec![0, 1]), None);
+    }
+}
@@ -0,0 +1,346 @@
+#![allow(non_snake_case)]
+
+use super::generators::standardLayout;
+use super::MeshData;
+use crate::texture::srgbToLinear;
+use cgmath::{InnerSpace, Zero, vec3};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+type Vector3 = cgmath::Vector3<f32>;
+
+/// The parts of an MTL material the renderer uses. Colors are linear, MTL files hold display
+/// values which are decoded from sRGB when parsed.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Material {
+    pub name: String,
+    pub ambient: Vector3,
+    pub diffuse: Vector3,
+    pub specular: Vector3,
+    pub shininess: f32,
+    pub opacity: f32,
+    /// `map_Kd`, relative to the MTL file.
+    pub diffuseMap: Option<String>,
+}
+
+impl Material {
+    pub fn new(name: &str) -> Material {
+        Material {
+            name: name.to_string(),
+            ambient: vec3(0.0, 0.0, 0.0),
+            diffuse: vec3(0.8, 0.8, 0.8),
+            specular: vec3(0.0, 0.0, 0.0),
+            shininess: 1.0,
+            opacity: 1.0,
+            diffuseMap: None,
+        }
+    }
+}
+
+/// Faces sharing a group and a material, ready for `Mesh::new`.
+pub struct ObjPart {
+    pub group: String,
+    pub material: Option<String>,
+    pub data: MeshData,
+}
+
+pub struct ObjModel {
+    pub parts: Vec<ObjPart>,
+    pub materials: HashMap<String, Material>,
+}
+
+#[allow(dead_code)]
+impl ObjModel {
+    pub fn material(&self, part: &ObjPart) -> Option<&Material> {
+        self.materials.get(part.material.as_ref()?)
+    }
+
+    pub fn triangleCount(&self) -> usize {
+        self.parts.iter().map(|part| part.data.indices.len() / 3).sum()
+    }
+}
+
+fn parseFloats<const N: usize>(args: &[&str], lineNumber: usize) -> Result<[f32; N], String> {
+    let mut values = [0.0; N];
+    if args.len() < N {
+        return Err(format!("line {}: expected {} numbers", lineNumber, N));
+    }
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = arg.parse().map_err(|_| format!("line {}: invalid number '{}'", lineNumber, arg))?;
+    }
+    Ok(values)
+}
+
+/// Resolves a 1-based or negative (relative to the end) OBJ index into `count` elements.
+fn resolveIndex(index: &str, count: usize, lineNumber: usize) -> Result<usize, String> {
+    let value: i64 = index.parse().map_err(|_| format!("line {}: invalid index '{}'", lineNumber, index))?;
+    let resolved = if value < 0 { count as i64 + value } else { value - 1 };
+    if value == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!("line {}: index {} out of range ({} defined)", lineNumber, value, count));
+    }
+    Ok(resolved as usize)
+}
+
+/// One face corner: position, uv and normal indices, 0-based.
+type Corner = (usize, Option<usize>, Option<usize>);
+
+struct PartBuilder {
+    part: ObjPart,
+    corners: HashMap<Corner, u32>,
+    /// Vertices without an OBJ normal, smoothed from their faces at the end.
+    missingNormals: Vec<u32>,
+}
+
+impl PartBuilder {
+    fn new(group: &str, material: Option<&str>) -> PartBuilder {
+        PartBuilder {
+            part: ObjPart {
+                group: group.to_string(),
+                material: material.map(str::to_string),
+                data: MeshData::new(standardLayout()),
+            },
+            corners: HashMap::new(),
+            missingNormals: Vec::new(),
+        }
+    }
+
+    fn vertex(&mut self, corner: Corner, positions: &[Vector3], uvs: &[[f32; 2]], normals: &[Vector3]) -> u32 {
+        if let Some(&index) = self.corners.get(&corner) {
+            return index;
+        }
+        let (p, uv, n) = corner;
+        let pos = positions[p];
+        let uv = uv.map_or([0.0, 0.0], |i| uvs[i]);
+        let normal = n.map(|i| normals[i]).filter(|normal| normal.magnitude2() > 0.0);
+        let n = normal.unwrap_or(Vector3::zero());
+        let index = self.part.data.pushVertex(&[pos.x, pos.y, pos.z, n.x, n.y, n.z, uv[0], uv[1]]);
+        if normal.is_none() {
+            self.missingNormals.push(index);
+        }
+        self.corners.insert(corner, index);
+        index
+    }
+
+    fn finish(mut self) -> ObjPart {
+        self.part.data.smoothNormals(self.missingNormals);
+        self.part
+    }
+}
+
+/// Parses OBJ source. Faces of any size are fan-triangulated, which assumes they're convex.
+/// Materials are only referenced by name, `loadObj` also reads the MTL files.
+pub fn parseObj(source: &str) -> Result<ObjModel, String> {
+    let (mut positions, mut uvs, mut normals) = (Vec::new(), Vec::new(), Vec::new());
+    let mut parts: Vec<ObjPart> = Vec::new();
+    let mut group = "default".to_string();
+    let mut material: Option<String> = None;
+    let mut builder: Option<PartBuilder> = None;
+
+    for (i, line) in source.lines().enumerate() {
+        let lineNumber = i + 1;
+        let line = line.split('#').next().unwrap().trim();
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        let args: Vec<&str> = words.collect();
+        match keyword {
+            "v" => positions.push(parseFloats::<3>(&args, lineNumber)?.into()),
+            "vt" => uvs.push(parseFloats::<2>(&args, lineNumber)?),
+            "vn" => {
+                // Zero normals stay zero and are smoothed like missing ones
+                let normal = Vector3::from(parseFloats::<3>(&args, lineNumber)?);
+                normals.push(if normal.magnitude2() > 0.0 { normal.normalize() } else { normal });
+            }
+            "g" | "o" | "usemtl" => {
+                let name = args.join(" ");
+                if keyword == "usemtl" {
+                    material = Some(name);
+                } else {
+                    group = if name.is_empty() { "default".to_string() } else { name };
+                }
+                // Start a new part lazily, at the next face
+                if let Some(finished) = builder.take() {
+                    parts.push(finished.finish());
+                }
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(format!("line {}: face needs at least 3 vertices", lineNumber));
+                }
+                let mut corners = Vec::with_capacity(args.len());
+                for arg in &args {
+                    let mut fields = arg.split('/');
+                    let p = resolveIndex(fields.next().unwrap(), positions.len(), lineNumber)?;
+                    let uv = match fields.next() {
+                        Some("") | None => None,
+                        Some(index) => Some(resolveIndex(index, uvs.len(), lineNumber)?),
+                    };
+                    let n = match fields.next() {
+                        Some("") | None => None,
+                        Some(index) => Some(resolveIndex(index, normals.len(), lineNumber)?),
+                    };
+                    corners.push((p, uv, n));
+                }
+                let part = builder.get_or_insert_with(|| PartBuilder::new(&group, material.as_deref()));
+                let indices: Vec<u32> = corners
+                    .into_iter()
+                    .map(|corner| part.vertex(corner, &positions, &uvs, &normals))
+                    .collect();
+                for k in 1..indices.len() - 1 {
+                    part.part.data.pushTriangle(indices[0], indices[k], indices[k + 1]);
+                }
+            }
+            // Smoothing groups, free-form geometry, lines and points aren't used
+            _ => {}
+        }
+    }
+    if let Some(finished) = builder.take() {
+        parts.push(finished.finish());
+    }
+    Ok(ObjModel {
+        parts,
+        materials: HashMap::new(),
+    })
+}
+
+/// Parses MTL source into materials keyed by name.
+pub fn parseMtl(source: &str) -> Result<HashMap<String, Material>, String> {
+    let mut materials = HashMap::new();
+    let mut current: Option<Material> = None;
+    for (i, line) in source.lines().enumerate() {
+        let lineNumber = i + 1;
+        let line = line.split('#').next().unwrap().trim();
+        let mut words = line.split_whitespace();
+        let Some(keyword) = words.next() else {
+            continue;
+        };
+        let args: Vec<&str> = words.collect();
+        if keyword == "newmtl" {
+            if let Some(material) = current.take() {
+                materials.insert(material.name.clone(), material);
+            }
+            current = Some(Material::new(&args.join(" ")));
+            continue;
+        }
+        let Some(material) = current.as_mut() else {
+            return Err(format!("line {}: '{}' before newmtl", lineNumber, keyword));
+        };
+        match keyword {
+            "Ka" => material.ambient = parseFloats::<3>(&args, lineNumber)?.map(srgbToLinear).into(),
+            "Kd" => material.diffuse = parseFloats::<3>(&args, lineNumber)?.map(srgbToLinear).into(),
+            "Ks" => material.specular = parseFloats::<3>(&args, lineNumber)?.map(srgbToLinear).into(),
+            "Ns" => material.shininess = parseFloats::<1>(&args, lineNumber)?[0],
+            "d" => material.opacity = parseFloats::<1>(&args, lineNumber)?[0],
+            "Tr" => material.opacity = 1.0 - parseFloats::<1>(&args, lineNumber)?[0],
+            // Options like -s come before the file name, which is last
+            "map_Kd" => material.diffuseMap = args.last().map(|s| s.to_string()),
+            _ => {}
+        }
+    }
+    if let Some(material) = current.take() {
+        materials.insert(material.name.clone(), material);
+    }
+    Ok(materials)
+}
+
+/// Loads an OBJ file and the MTL libraries it references, relative to its directory.
+pub fn loadObj(path: &str) -> Result<ObjModel, String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    let mut model = parseObj(&source).map_err(|e| format!("{}: {}", path, e))?;
+    let directory = Path::new(path).parent().unwrap_or(Path::new(""));
+    for line in source.lines() {
+        if let Some(libraries) = line.trim().strip_prefix("mtllib ") {
+            for library in libraries.split_whitespace() {
+                let mtlPath = directory.join(library);
+                let mtl = fs::read_to_string(&mtlPath).map_err(|e| format!("{}: {}", mtlPath.display(), e))?;
+                model
+                    .materials
+                    .extend(parseMtl(&mtl).map_err(|e| format!("{}: {}", mtlPath.display(), e))?);
+            }
+        }
+    }
+    Ok(model)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::Attribute;
+
+    fn normal(data: &MeshData, i: usize) -> Vector3 {
+        let n = data.attribute(i, Attribute::Normal).unwrap();
+        vec3(n[0], n[1], n[2])
+    }
+
+    #[test]
+    fn ngonsAreFanTriangulated() {
+        let model = loadObj("resources/models/ngon.obj").unwrap();
+        assert_eq!(model.parts.len(), 1);
+        let data = &model.parts[0].data;
+        data.validate();
+        // Hexagon (4 triangles) and quad (2) sharing two corners
+        assert_eq!(data.indices.len(), (4 + 2) * 3);
+        assert_eq!(data.vertexCount(), 8);
+        assert_eq!(data.attribute(1, Attribute::Uv), Some(&[1.0, 0.0][..]));
+    }
+
+    #[test]
+    fn missingNormalsAreSmoothed() {
+        let model = loadObj("resources/models/ngon.obj").unwrap();
+        let data = &model.parts[0].data;
+        for i in 0..data.vertexCount() {
+            let n = normal(data, i);
+            assert!((n - vec3(0.0, 0.0, 1.0)).magnitude() < 1e-5, "{:?}", n);
+        }
+    }
+
+    #[test]
+    fn zeroNormalsAreSmoothedToo() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 0\nvn 0 0 2\nf 1//1 2//2 3//1\n";
+        let model = parseObj(source).unwrap();
+        let data = &model.parts[0].data;
+        for i in 0..data.vertexCount() {
+            let n = normal(data, i);
+            assert!((n - vec3(0.0, 0.0, 1.0)).magnitude() < 1e-5, "{:?}", n);
+        }
+    }
+
+    #[test]
+    fn groupsAndMaterialsSplitParts() {
+        let model = loadObj("resources/models/cube.obj").unwrap();
+        let names: Vec<(&str, Option<&str>)> =
+            model.parts.iter().map(|p| (p.group.as_str(), p.material.as_deref())).collect();
+        assert_eq!(names, [("sides", Some("red")), ("sides", Some("blue")), ("caps", Some("blue"))]);
+        assert_eq!(model.triangleCount(), 12);
+        let red = model.material(&model.parts[0]).unwrap();
+        assert_eq!(red.diffuse, vec3(0.8, 0.1, 0.1).map(srgbToLinear));
+        assert_eq!(red.shininess, 32.0);
+        assert_eq!(model.materials["blue"].diffuseMap.as_deref(), Some("checker.png"));
+        assert!(Path::new("resources/models/checker.png").exists());
+        for part in &model.parts {
+            part.data.validate();
+            for i in 0..part.data.vertexCount() {
+                assert!((normal(&part.data, i).magnitude() - 1.0).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn negativeIndicesCountFromTheEnd() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\nv 1 1 0\nf 2 -1 3\n";
+        let model = parseObj(source).unwrap();
+        let data = &model.parts[0].data;
+        assert_eq!(data.indices, [0, 1, 2, 1, 3, 2]);
+        assert_eq!(data.attribute(3, Attribute::Position), Some(&[1.0, 1.0, 0.0][..]));
+    }
+
+    #[test]
+    fn badIndicesReportTheLine() {
+        let error = parseObj("v 0 0 0\nv 1 0 0\n\nf 1 2 3\n").err().unwrap();
+        assert!(error.starts_with("line 4:"), "{}", error);
+        assert!(parseObj("v 0 0 0\nf 0 1 1\n").is_err());
+        assert!(parseObj("v 0 0 0\nf 1 1\n").is_err());
+    }
+}
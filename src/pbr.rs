@@ -0,0 +1,361 @@
+#![allow(non_snake_case)]
+
+//! Metallic-roughness shading (`pbr.frag`) and image-based lighting baked from
+//! equirectangular HDR environments.
+//!
+//! Baking renders three cube maps per environment: the environment itself, its diffuse
+//! irradiance and a specular map prefiltered per roughness across the mip levels. The split
+//! sum BRDF lookup table is shared by all of them and integrated on the CPU. Everything is
+//! baked once and cached by path in `Ibl`.
+
+use crate::framebuffer::{Framebuffer, FullscreenTriangle};
+use crate::mesh::gltf_scene::{PbrMaterial, TextureRef};
+use crate::shader::Shader;
+use crate::texture::{self, Image, Pixels, SamplerState, Texture};
+use cgmath::{InnerSpace, vec2, vec3};
+use gl::types::{GLint, GLuint};
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+type Vector2 = cgmath::Vector2<f32>;
+type Vector3 = cgmath::Vector3<f32>;
+
+const ENVIRONMENT_SIZE: u32 = 512;
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTERED_SIZE: u32 = 128;
+/// Roughness 0, 0.25 .. 1, the last level being 8x8.
+const PREFILTERED_LEVELS: u32 = 5;
+const BRDF_LUT_SIZE: u32 = 64;
+const BRDF_SAMPLES: u32 = 128;
+
+/// Texture units used by `pbr.frag`, the material maps come first.
+const IRRADIANCE_UNIT: u32 = 5;
+const PREFILTERED_UNIT: u32 = 6;
+const BRDF_LUT_UNIT: u32 = 7;
+
+/// A material texture in `pbr.frag`, its value is the texture unit.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum MapSlot {
+    BaseColor = 0,
+    /// Roughness in green, metalness in blue.
+    MetallicRoughness = 1,
+    Normal = 2,
+    Occlusion = 3,
+    Emissive = 4,
+}
+
+pub const MAP_SLOTS: usize = 5;
+
+impl MapSlot {
+    pub const ALL: [MapSlot; MAP_SLOTS] = [
+        MapSlot::BaseColor,
+        MapSlot::MetallicRoughness,
+        MapSlot::Normal,
+        MapSlot::Occlusion,
+        MapSlot::Emissive,
+    ];
+
+    /// Color textures are stored in sRGB, the others hold linear data.
+    pub fn isSrgb(self) -> bool {
+        matches!(self, MapSlot::BaseColor | MapSlot::Emissive)
+    }
+
+    pub fn textureRef(self, material: &PbrMaterial) -> Option<TextureRef> {
+        match self {
+            MapSlot::BaseColor => material.baseColorTexture,
+            MapSlot::MetallicRoughness => material.metallicRoughnessTexture,
+            MapSlot::Normal => material.normalTexture,
+            MapSlot::Occlusion => material.occlusionTexture,
+            MapSlot::Emissive => material.emissiveTexture,
+        }
+    }
+
+    fn uniform(self) -> &'static str {
+        match self {
+            MapSlot::BaseColor => "u_baseColorMap",
+            MapSlot::MetallicRoughness => "u_metallicRoughnessMap",
+            MapSlot::Normal => "u_normalMap",
+            MapSlot::Occlusion => "u_occlusionMap",
+            MapSlot::Emissive => "u_emissiveMap",
+        }
+    }
+}
+
+/// Sets the factors of `material` and binds its `maps` (in `MapSlot` order) on the bound
+/// `pbr.frag` shader. Missing maps are flagged off in `u_maps`.
+pub fn bindMaterial(shader: &Shader, material: &PbrMaterial, maps: [Option<&Texture>; MAP_SLOTS]) {
+    shader.setUniform4fv("u_baseColor", &material.baseColor);
+    shader.setUniform1f("u_metallic", material.metallic);
+    shader.setUniform1f("u_roughness", material.roughness);
+    shader.setUniform3fv("u_emissive", &material.emissive);
+    let mut flags = 0;
+    for (slot, texture) in MapSlot::ALL.into_iter().zip(maps) {
+        if let Some(texture) = texture {
+            shader.setTexture(slot.uniform(), slot as u32, texture);
+            flags |= 1 << slot as u32;
+        }
+    }
+    shader.setUniform1ui("u_maps", flags);
+}
+
+/// Van der Corput sequence point `i` of `count`, the low-discrepancy samples used by
+/// the prefilter shader and `integrateBrdf`.
+pub fn hammersley(i: u32, count: u32) -> Vector2 {
+    vec2(i as f32 / count as f32, i.reverse_bits() as f32 * 2.328_306_4e-10)
+}
+
+/// Half vector around `n` distributed like the GGX lobe of `roughness`.
+pub fn importanceSampleGgx(xi: Vector2, n: Vector3, roughness: f32) -> Vector3 {
+    let a = roughness * roughness;
+    let phi = 2.0 * PI * xi.x;
+    let cosTheta = ((1.0 - xi.y) / (1.0 + (a * a - 1.0) * xi.y)).sqrt();
+    let sinTheta = (1.0 - cosTheta * cosTheta).sqrt();
+    let h = vec3(phi.cos() * sinTheta, phi.sin() * sinTheta, cosTheta);
+    let up = if n.z.abs() < 0.999 { vec3(0.0, 0.0, 1.0) } else { vec3(1.0, 0.0, 0.0) };
+    let tangent = up.cross(n).normalize();
+    let bitangent = n.cross(tangent);
+    (tangent * h.x + bitangent * h.y + n * h.z).normalize()
+}
+
+/// Smith-Schlick visibility with the IBL remapping k = a / 2.
+fn geometrySmith(nDotV: f32, nDotL: f32, roughness: f32) -> f32 {
+    let k = roughness * roughness / 2.0;
+    let g = |x: f32| x / (x * (1.0 - k) + k);
+    g(nDotV) * g(nDotL)
+}
+
+/// Scale and bias to F0 of the split sum specular integral, one texel of the BRDF LUT.
+pub fn integrateBrdf(nDotV: f32, roughness: f32, samples: u32) -> (f32, f32) {
+    let nDotV = nDotV.max(1e-4);
+    let v = vec3((1.0 - nDotV * nDotV).sqrt(), 0.0, nDotV);
+    let n = vec3(0.0, 0.0, 1.0);
+    let (mut scale, mut bias) = (0.0, 0.0);
+    for i in 0..samples {
+        let h = importanceSampleGgx(hammersley(i, samples), n, roughness);
+        let l = (h * 2.0 * v.dot(h) - v).normalize();
+        let (nDotL, nDotH, vDotH) = (l.z.max(0.0), h.z.max(0.0), v.dot(h).max(0.0));
+        if nDotL > 0.0 {
+            let visibility = geometrySmith(nDotV, nDotL, roughness) * vDotH / (nDotH * nDotV);
+            let fresnel = (1.0 - vDotH).powi(5);
+            scale += (1.0 - fresnel) * visibility;
+            bias += fresnel * visibility;
+        }
+    }
+    (scale / samples as f32, bias / samples as f32)
+}
+
+/// The BRDF LUT, NdotV along x and roughness along y, scale in red and bias in green.
+pub fn brdfLut(size: u32, samples: u32) -> Image {
+    let mut pixels = Vec::with_capacity((size * size * 4) as usize);
+    for y in 0..size {
+        for x in 0..size {
+            let (scale, bias) = integrateBrdf((x as f32 + 0.5) / size as f32, (y as f32 + 0.5) / size as f32, samples);
+            pixels.extend_from_slice(&[scale, bias, 0.0, 1.0]);
+        }
+    }
+    Image {
+        width: size,
+        height: size,
+        pixels: Pixels::RgbaF32(pixels),
+    }
+}
+
+/// Baked lighting of one environment.
+pub struct Environment {
+    pub cube: Texture,
+    pub irradiance: Texture,
+    pub prefiltered: Texture,
+}
+
+impl Environment {
+    fn destroy(&self) {
+        self.cube.destroy();
+        self.irradiance.destroy();
+        self.prefiltered.destroy();
+    }
+}
+
+/// Bakes and caches environments, and owns the shared BRDF LUT.
+pub struct Ibl {
+    equirectShader: Shader,
+    irradianceShader: Shader,
+    prefilterShader: Shader,
+    triangle: FullscreenTriangle,
+    brdfLut: Texture,
+    environments: HashMap<String, Environment>,
+}
+
+#[allow(dead_code)]
+impl Ibl {
+    pub fn new() -> Ibl {
+        // Bakes only run when an environment is picked, so a broken shader is caught here instead
+        let shader = |fragment| {
+            let shader = Shader::new("resources/shaders/fullscreen.vert", fragment);
+            assert!(shader.isLinked(), "Failed to build IBL bake shader {}", fragment);
+            shader
+        };
+        Ibl {
+            equirectShader: shader("resources/shaders/ibl_equirect.frag"),
+            irradianceShader: shader("resources/shaders/ibl_irradiance.frag"),
+            prefilterShader: shader("resources/shaders/ibl_prefilter.frag"),
+            triangle: FullscreenTriangle::new(),
+            brdfLut: Texture::new2D(&brdfLut(BRDF_LUT_SIZE, BRDF_SAMPLES), false, &SamplerState::clamped()),
+            environments: HashMap::new(),
+        }
+    }
+
+    /// The environment baked from the equirectangular HDR at `path`, baked on first use.
+    pub fn environment(&mut self, path: &str) -> Result<&Environment, String> {
+        if !self.environments.contains_key(path) {
+            let image = Image::load(path)?;
+            let environment = self.bake(&image);
+            self.environments.insert(path.to_string(), environment);
+        }
+        Ok(&self.environments[path])
+    }
+
+    /// An environment already baked by `environment`.
+    pub fn cached(&self, path: &str) -> Option<&Environment> {
+        self.environments.get(path)
+    }
+
+    /// Renders `shader` into every face of `level` of the cube `target`.
+    fn renderFaces(&self, framebuffer: &mut Framebuffer, shader: &Shader, target: &Texture, level: u32) {
+        for face in 0..6 {
+            framebuffer.attachLayer(0, target, level, face);
+            framebuffer.bind();
+            shader.setUniform1i("u_face", face as i32);
+            self.triangle.draw();
+        }
+    }
+
+    fn bake(&self, equirect: &Image) -> Environment {
+        let mut viewport: [GLint; 4] = [0; 4];
+        let mut previous: GLint = 0;
+        unsafe {
+            gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+            gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut previous);
+            gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
+        }
+        let mut framebuffer = Framebuffer::empty();
+
+        // Wraps around horizontally, clamps at the poles
+        let equirectSampler = SamplerState {
+            minFilter: gl::LINEAR,
+            wrapT: gl::CLAMP_TO_EDGE,
+            ..Default::default()
+        };
+        let source = Texture::new2D(equirect, false, &equirectSampler);
+        let cubeSampler = SamplerState::default().wrap(gl::CLAMP_TO_EDGE);
+        let levels = texture::mipLevels(ENVIRONMENT_SIZE, ENVIRONMENT_SIZE);
+        let cube = Texture::storage(gl::TEXTURE_CUBE_MAP, ENVIRONMENT_SIZE, ENVIRONMENT_SIZE, 6, levels, gl::RGBA16F);
+        cube.setSampler(&cubeSampler);
+        self.equirectShader.bind();
+        self.equirectShader.setTexture("u_equirect", 0, &source);
+        self.renderFaces(&mut framebuffer, &self.equirectShader, &cube, 0);
+        // The convolutions read blurrier levels to cut down on noise
+        cube.generateMipmaps();
+        source.destroy();
+
+        let irradiance = Texture::storage(gl::TEXTURE_CUBE_MAP, IRRADIANCE_SIZE, IRRADIANCE_SIZE, 6, 1, gl::RGBA16F);
+        irradiance.setSampler(&SamplerState::clamped());
+        self.irradianceShader.bind();
+        self.irradianceShader.setTexture("u_environment", 0, &cube);
+        self.renderFaces(&mut framebuffer, &self.irradianceShader, &irradiance, 0);
+
+        let prefiltered = Texture::storage(gl::TEXTURE_CUBE_MAP, PREFILTERED_SIZE, PREFILTERED_SIZE, 6, PREFILTERED_LEVELS, gl::RGBA16F);
+        prefiltered.setSampler(&cubeSampler);
+        self.prefilterShader.bind();
+        self.prefilterShader.setTexture("u_environment", 0, &cube);
+        self.prefilterShader.setUniform1f("u_resolution", ENVIRONMENT_SIZE as f32);
+        for level in 0..PREFILTERED_LEVELS {
+            let roughness = level as f32 / (PREFILTERED_LEVELS - 1) as f32;
+            self.prefilterShader.setUniform1f("u_roughness", roughness);
+            self.renderFaces(&mut framebuffer, &self.prefilterShader, &prefiltered, level);
+        }
+
+        framebuffer.destroy();
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, previous as GLuint);
+            gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+        }
+        Environment {
+            cube,
+            irradiance,
+            prefiltered,
+        }
+    }
+
+    /// Binds `environment` for the bound `pbr.frag` shader, or turns IBL off with `None`.
+    pub fn bind(&self, shader: &Shader, environment: Option<&Environment>, intensity: f32) {
+        shader.setUniform1i("u_hasEnvironment", environment.is_some() as i32);
+        shader.setUniform1f("u_environmentIntensity", intensity);
+        if let Some(environment) = environment {
+            shader.setTexture("u_irradiance", IRRADIANCE_UNIT, &environment.irradiance);
+            shader.setTexture("u_prefiltered", PREFILTERED_UNIT, &environment.prefiltered);
+            shader.setTexture("u_brdfLut", BRDF_LUT_UNIT, &self.brdfLut);
+            shader.setUniform1f("u_prefilteredLevels", PREFILTERED_LEVELS as f32);
+        }
+    }
+
+    pub fn destroy(&self) {
+        for environment in self.environments.values() {
+            environment.destroy();
+        }
+        self.brdfLut.destroy();
+        self.triangle.destroy();
+        self.equirectShader.delete();
+        self.irradianceShader.delete();
+        self.prefilterShader.delete();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Vector3, b: Vector3) -> bool {
+        (a - b).magnitude() < 1e-5
+    }
+
+    #[test]
+    fn hammersleyIsLowDiscrepancy() {
+        assert_eq!(hammersley(0, 4), vec2(0.0, 0.0));
+        assert_eq!(hammersley(1, 4), vec2(0.25, 0.5));
+        assert_eq!(hammersley(2, 4), vec2(0.5, 0.25));
+        assert_eq!(hammersley(3, 4), vec2(0.75, 0.75));
+    }
+
+    #[test]
+    fn ggxSamplesStayInTheHemisphere() {
+        let n = vec3(0.3, 0.9, -0.2).normalize();
+        for i in 0..64 {
+            let h = importanceSampleGgx(hammersley(i, 64), n, 0.6);
+            assert!((h.magnitude() - 1.0).abs() < 1e-5);
+            assert!(h.dot(n) >= 0.0);
+        }
+        // A mirror only ever picks the normal
+        assert!(close(importanceSampleGgx(vec2(0.3, 0.7), n, 0.0), n));
+    }
+
+    #[test]
+    fn brdfIntegralHasKnownLimits() {
+        // Smooth, head on: all energy in the scale term
+        let (scale, bias) = integrateBrdf(1.0, 0.0, 64);
+        assert!((scale - 1.0).abs() < 1e-3 && bias.abs() < 1e-3, "{} {}", scale, bias);
+        // Grazing angles get more Fresnel
+        let (_, grazingBias) = integrateBrdf(0.1, 0.2, 256);
+        assert!(grazingBias > 0.1, "{}", grazingBias);
+        // Rough surfaces lose energy to masking
+        let (roughScale, roughBias) = integrateBrdf(0.5, 1.0, 256);
+        assert!(roughScale + roughBias < 0.9);
+    }
+
+    #[test]
+    fn lutIsRgbaFloat() {
+        let lut = brdfLut(4, 16);
+        let Pixels::RgbaF32(pixels) = &lut.pixels else { unreachable!() };
+        assert_eq!(pixels.len(), 4 * 4 * 4);
+        assert!(pixels.chunks_exact(4).all(|p| p[0] >= 0.0 && p[0] <= 1.0 && p[3] == 1.0));
+    }
+}
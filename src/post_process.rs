@@ -0,0 +1,520 @@
+#![allow(non_snake_case)]
+
+//! The scene is drawn into an HDR `Framebuffer`, then a stack of full-screen passes turns it
+//! into the window image: bloom, tone mapping, color grading, vignette, gamma and FXAA. Passes
+//! run in list order, each reading the previous one's output, and can be toggled and reordered
+//! from ImGui.
+//!
+//! The scene target can be multisampled, it's resolved before the first pass. With the sRGB
+//! framebuffer option the window encodes the result itself and the gamma pass is skipped.
+
+use crate::framebuffer::{self, Framebuffer, FullscreenTriangle};
+use crate::shader::Shader;
+use crate::texture::{srgbToLinear, Texture};
+use gl::types::{GLenum, GLint};
+use imgui::Ui;
+
+/// Most blur taps on each side of the center, including it, see `post_blur.frag`.
+pub const MAX_BLUR_TAPS: usize = 8;
+const HDR_FORMAT: GLenum = gl::RGBA16F;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum PassKind {
+    Bloom,
+    Tonemap,
+    ColorGrading,
+    Vignette,
+    Gamma,
+    Fxaa,
+}
+
+impl PassKind {
+    pub const ALL: [PassKind; 6] = [
+        PassKind::Bloom,
+        PassKind::Tonemap,
+        PassKind::ColorGrading,
+        PassKind::Vignette,
+        PassKind::Gamma,
+        PassKind::Fxaa,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PassKind::Bloom => "Bloom",
+            PassKind::Tonemap => "Tone mapping",
+            PassKind::ColorGrading => "Color grading",
+            PassKind::Vignette => "Vignette",
+            PassKind::Gamma => "Gamma",
+            PassKind::Fxaa => "FXAA",
+        }
+    }
+
+    fn fragmentPath(self) -> &'static str {
+        match self {
+            PassKind::Bloom => "resources/shaders/post_bloom_composite.frag",
+            PassKind::Tonemap => "resources/shaders/post_tonemap.frag",
+            PassKind::ColorGrading => "resources/shaders/post_color_grading.frag",
+            PassKind::Vignette => "resources/shaders/post_vignette.frag",
+            PassKind::Gamma => "resources/shaders/post_gamma.frag",
+            PassKind::Fxaa => "resources/shaders/post_fxaa.frag",
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Pass {
+    pub kind: PassKind,
+    pub enabled: bool,
+}
+
+/// Tone mapping curve, its value is `u_tonemapper` in `post_tonemap.frag`.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Tonemapper {
+    Reinhard = 0,
+    Aces = 1,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct PostSettings {
+    pub exposure: f32,
+    pub tonemapper: Tonemapper,
+    pub gamma: f32,
+    /// Brightness where bloom starts, with a soft knee of `bloomKnee` around it.
+    pub bloomThreshold: f32,
+    pub bloomKnee: f32,
+    pub bloomIntensity: f32,
+    /// Separable blur rounds at half resolution, each widens the glow.
+    pub bloomBlurPasses: u32,
+    pub bloomSigma: f32,
+    pub brightness: f32,
+    pub contrast: f32,
+    pub saturation: f32,
+    /// Picked as sRGB, decoded when bound.
+    pub tint: [f32; 3],
+    pub vignetteRadius: f32,
+    pub vignetteSoftness: f32,
+    pub vignetteStrength: f32,
+    pub fxaaEdgeThreshold: f32,
+    pub fxaaEdgeThresholdMin: f32,
+    pub fxaaSubpixel: f32,
+    /// Lets the window do the linear to sRGB conversion (`GL_FRAMEBUFFER_SRGB`) instead of
+    /// the gamma pass. FXAA then sees linear values.
+    pub srgbFramebuffer: bool,
+}
+
+impl Default for PostSettings {
+    fn default() -> Self {
+        PostSettings {
+            exposure: 1.0,
+            tonemapper: Tonemapper::Aces,
+            gamma: 2.2,
+            bloomThreshold: 1.0,
+            bloomKnee: 0.5,
+            bloomIntensity: 0.3,
+            bloomBlurPasses: 3,
+            bloomSigma: 3.0,
+            brightness: 0.0,
+            contrast: 1.0,
+            saturation: 1.0,
+            tint: [1.0; 3],
+            vignetteRadius: 0.8,
+            vignetteSoftness: 0.6,
+            vignetteStrength: 0.3,
+            fxaaEdgeThreshold: 0.125,
+            fxaaEdgeThresholdMin: 0.0312,
+            fxaaSubpixel: 1.0,
+            srgbFramebuffer: false,
+        }
+    }
+}
+
+/// Passes in their usual order: bloom and tone mapping on HDR values, grading and vignette
+/// on display values, then gamma, with FXAA last since it expects perceptual luma.
+pub fn defaultPasses() -> Vec<Pass> {
+    PassKind::ALL
+        .iter()
+        .map(|&kind| Pass {
+            kind,
+            enabled: !matches!(kind, PassKind::ColorGrading | PassKind::Vignette),
+        })
+        .collect()
+}
+
+/// Swaps pass `index` with its neighbour `offset` away (-1 up, 1 down), if there is one.
+pub fn movePass(passes: &mut [Pass], index: usize, offset: isize) -> bool {
+    match index.checked_add_signed(offset) {
+        Some(other) if other < passes.len() && index < passes.len() => {
+            passes.swap(index, other);
+            true
+        }
+        _ => false,
+    }
+}
+
+/// MSAA sample count for a request of `samples`: a power of two no larger than `max`, 1
+/// meaning off.
+pub fn clampSamples(samples: u32, max: u32) -> u32 {
+    let samples = samples.clamp(1, max.max(1));
+    1 << (31 - samples.leading_zeros())
+}
+
+/// Normalized one-sided Gaussian weights of `taps` samples, center first, as the separable
+/// blur adds both sides.
+pub fn gaussianWeights(taps: usize, sigma: f32) -> Vec<f32> {
+    let weights: Vec<f32> = (0..taps).map(|i| (-((i * i) as f32) / (2.0 * sigma * sigma)).exp()).collect();
+    let total = weights[0] + 2.0 * weights[1..].iter().sum::<f32>();
+    weights.iter().map(|w| w / total).collect()
+}
+
+/// Render targets sized to the window, sharing one format.
+struct Targets {
+    /// Where the scene is drawn, with depth.
+    scene: Framebuffer,
+    /// The samples of `scene` averaged, when it's multisampled.
+    resolved: Framebuffer,
+    /// Ping-pong outputs of the passes.
+    passes: [Framebuffer; 2],
+    /// Half resolution bright parts and their blur.
+    bloom: [Framebuffer; 2],
+}
+
+impl Targets {
+    fn new(width: u32, height: u32, samples: u32) -> Targets {
+        let (halfWidth, halfHeight) = ((width / 2).max(1), (height / 2).max(1));
+        Targets {
+            scene: Framebuffer::multisampled(width, height, samples, &[HDR_FORMAT], Some(gl::DEPTH_COMPONENT32F)),
+            resolved: Framebuffer::new(width, height, &[HDR_FORMAT], None),
+            passes: [(); 2].map(|_| Framebuffer::new(width, height, &[HDR_FORMAT], None)),
+            bloom: [(); 2].map(|_| Framebuffer::new(halfWidth, halfHeight, &[HDR_FORMAT], None)),
+        }
+    }
+
+    fn resize(&mut self, width: u32, height: u32) {
+        self.scene.resize(width, height);
+        self.resolved.resize(width, height);
+        for target in self.passes.iter_mut() {
+            target.resize(width, height);
+        }
+        for target in self.bloom.iter_mut() {
+            target.resize((width / 2).max(1), (height / 2).max(1));
+        }
+    }
+
+    fn destroy(&self) {
+        self.scene.destroy();
+        self.resolved.destroy();
+        for target in self.passes.iter().chain(self.bloom.iter()) {
+            target.destroy();
+        }
+    }
+}
+
+/// The offscreen scene target and the pass stack drawn from it onto the window.
+pub struct PostStack {
+    pub passes: Vec<Pass>,
+    pub settings: PostSettings,
+    targets: Targets,
+    shaders: Vec<Shader>,
+    thresholdShader: Shader,
+    blurShader: Shader,
+    copyShader: Shader,
+    triangle: FullscreenTriangle,
+}
+
+#[allow(dead_code)]
+impl PostStack {
+    pub fn new(width: u32, height: u32) -> PostStack {
+        let shader = |fragment| Shader::new("resources/shaders/fullscreen.vert", fragment);
+        PostStack {
+            passes: defaultPasses(),
+            settings: PostSettings::default(),
+            targets: Targets::new(width.max(1), height.max(1), 1),
+            shaders: PassKind::ALL.iter().map(|kind| shader(kind.fragmentPath())).collect(),
+            thresholdShader: shader("resources/shaders/post_bloom_threshold.frag"),
+            blurShader: shader("resources/shaders/post_blur.frag"),
+            copyShader: shader("resources/shaders/post_copy.frag"),
+            triangle: FullscreenTriangle::new(),
+        }
+    }
+
+    /// Follows the window's framebuffer size, ignoring minimized (0 x 0) windows.
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.targets.resize(width, height);
+        }
+    }
+
+    /// MSAA samples of the scene target, 1 when off.
+    pub fn samples(&self) -> u32 {
+        self.targets.scene.samples()
+    }
+
+    /// Recreates the scene target with `samples` per pixel, clamped to what's supported.
+    pub fn setSamples(&mut self, samples: u32) {
+        let samples = clampSamples(samples, framebuffer::maxSamples());
+        if samples == self.samples() {
+            return;
+        }
+        let scene = &self.targets.scene;
+        let multisampled = Framebuffer::multisampled(scene.width(), scene.height(), samples, &[HDR_FORMAT], Some(gl::DEPTH_COMPONENT32F));
+        scene.destroy();
+        self.targets.scene = multisampled;
+    }
+
+    /// Binds the HDR scene target, everything until `finish` draws into it.
+    pub fn begin(&self) {
+        self.targets.scene.bind();
+    }
+
+    pub fn scene(&self) -> &Framebuffer {
+        &self.targets.scene
+    }
+
+    fn shader(&self, kind: PassKind) -> &Shader {
+        &self.shaders[kind as usize]
+    }
+
+    /// Draws `shader` reading `source` into `target`.
+    fn run(&self, shader: &Shader, source: &Texture, target: &Framebuffer) {
+        target.bind();
+        shader.setTexture("u_source", 0, source);
+        self.triangle.draw();
+    }
+
+    /// Bright parts of `source`, blurred into `bloom[0]`.
+    fn bloom(&self, source: &Texture) {
+        let settings = &self.settings;
+        let [first, second] = &self.targets.bloom;
+        self.thresholdShader.bind();
+        self.thresholdShader.setUniform1f("u_threshold", settings.bloomThreshold);
+        self.thresholdShader.setUniform1f("u_knee", settings.bloomKnee);
+        self.run(&self.thresholdShader, source, first);
+
+        let weights = gaussianWeights(MAX_BLUR_TAPS, settings.bloomSigma);
+        self.blurShader.bind();
+        for (i, weight) in weights.iter().enumerate() {
+            self.blurShader.setUniform1f(&format!("u_weights[{}]", i), *weight);
+        }
+        self.blurShader.setUniform1i("u_taps", MAX_BLUR_TAPS as i32);
+        let texel = (1.0 / first.width() as f32, 1.0 / first.height() as f32);
+        for _ in 0..settings.bloomBlurPasses {
+            self.blurShader.setUniform2f("u_direction", texel.0, 0.0);
+            self.run(&self.blurShader, first.color(0), second);
+            self.blurShader.setUniform2f("u_direction", 0.0, texel.1);
+            self.run(&self.blurShader, second.color(0), first);
+        }
+    }
+
+    fn setUniforms(&self, kind: PassKind, shader: &Shader, width: u32, height: u32) {
+        let settings = &self.settings;
+        match kind {
+            PassKind::Bloom => {
+                shader.setTexture("u_bloom", 1, self.targets.bloom[0].color(0));
+                shader.setUniform1f("u_intensity", settings.bloomIntensity);
+            }
+            PassKind::Tonemap => {
+                shader.setUniform1f("u_exposure", settings.exposure);
+                shader.setUniform1i("u_tonemapper", settings.tonemapper as i32);
+            }
+            PassKind::ColorGrading => {
+                shader.setUniform1f("u_brightness", settings.brightness);
+                shader.setUniform1f("u_contrast", settings.contrast);
+                shader.setUniform1f("u_saturation", settings.saturation);
+                shader.setUniform3fv("u_tint", &settings.tint.map(srgbToLinear).into());
+            }
+            PassKind::Vignette => {
+                shader.setUniform1f("u_radius", settings.vignetteRadius);
+                shader.setUniform1f("u_softness", settings.vignetteSoftness);
+                shader.setUniform1f("u_strength", settings.vignetteStrength);
+                shader.setUniform2f("u_resolution", width as f32, height as f32);
+            }
+            PassKind::Gamma => {
+                shader.setUniform1f("u_gamma", settings.gamma);
+            }
+            PassKind::Fxaa => {
+                shader.setUniform2f("u_resolution", width as f32, height as f32);
+                shader.setUniform1f("u_edgeThreshold", settings.fxaaEdgeThreshold);
+                shader.setUniform1f("u_edgeThresholdMin", settings.fxaaEdgeThresholdMin);
+                shader.setUniform1f("u_subpixel", settings.fxaaSubpixel);
+            }
+        }
+    }
+
+    /// Runs the enabled passes on the scene and copies the result to the window, which is
+    /// left bound with a `width` x `height` viewport.
+    pub fn finish(&self, width: u32, height: u32) {
+        let mut polygonMode: [GLint; 2] = [0; 2];
+        let depthTest = unsafe {
+            gl::GetIntegerv(gl::POLYGON_MODE, polygonMode.as_mut_ptr());
+            gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+            let enabled = gl::IsEnabled(gl::DEPTH_TEST) == gl::TRUE;
+            gl::Disable(gl::DEPTH_TEST);
+            enabled
+        };
+
+        let (sceneWidth, sceneHeight) = (self.targets.scene.width(), self.targets.scene.height());
+        let mut current = &self.targets.scene;
+        if current.samples() > 1 {
+            current.resolve(&self.targets.resolved);
+            current = &self.targets.resolved;
+        }
+        let mut next = 0;
+        let srgb = self.settings.srgbFramebuffer;
+        for pass in self.passes.iter().filter(|pass| pass.enabled && !(srgb && pass.kind == PassKind::Gamma)) {
+            if pass.kind == PassKind::Bloom {
+                self.bloom(current.color(0));
+            }
+            let shader = self.shader(pass.kind);
+            shader.bind();
+            self.setUniforms(pass.kind, shader, sceneWidth, sceneHeight);
+            let target = &self.targets.passes[next];
+            self.run(shader, current.color(0), target);
+            current = target;
+            next = 1 - next;
+        }
+
+        // Drawn rather than blitted, so the window may be multisampled or sRGB encode it
+        Framebuffer::bindDefault(width, height);
+        self.copyShader.bind();
+        self.copyShader.setTexture("u_source", 0, current.color(0));
+        unsafe {
+            if srgb {
+                gl::Enable(gl::FRAMEBUFFER_SRGB);
+            }
+            self.triangle.draw();
+            // ImGui colors are display values already
+            gl::Disable(gl::FRAMEBUFFER_SRGB);
+            gl::PolygonMode(gl::FRONT_AND_BACK, polygonMode[0] as GLenum);
+            if depthTest {
+                gl::Enable(gl::DEPTH_TEST);
+            }
+        }
+    }
+
+    pub fn destroy(&self) {
+        self.targets.destroy();
+        for shader in self.shaders.iter() {
+            shader.delete();
+        }
+        self.thresholdShader.delete();
+        self.blurShader.delete();
+        self.copyShader.delete();
+        self.triangle.destroy();
+    }
+}
+
+/// ImGui editor for the pass list and its settings: a checkbox and up/down arrows per pass.
+pub fn editPostStack(ui: &Ui, stack: &mut PostStack) {
+    let sampleCounts = [1, 2, 4, 8];
+    let mut samplesItem = sampleCounts.iter().position(|&samples| samples == stack.samples()).unwrap_or(0);
+    if ui.combo_simple_string("MSAA", &mut samplesItem, &["Off", "2x", "4x", "8x"]) {
+        stack.setSamples(sampleCounts[samplesItem]);
+    }
+    ui.checkbox("sRGB framebuffer", &mut stack.settings.srgbFramebuffer);
+    ui.separator();
+
+    let mut moved = None;
+    let count = stack.passes.len();
+    for (i, pass) in stack.passes.iter_mut().enumerate() {
+        let _id = ui.push_id_usize(i);
+        ui.checkbox(pass.kind.label(), &mut pass.enabled);
+        if pass.kind == PassKind::Gamma && stack.settings.srgbFramebuffer {
+            ui.same_line();
+            ui.text_disabled("(sRGB)");
+        }
+        ui.same_line_with_pos(160.0);
+        if ui.arrow_button("up", imgui::Direction::Up) && i > 0 {
+            moved = Some((i, -1));
+        }
+        ui.same_line();
+        if ui.arrow_button("down", imgui::Direction::Down) && i + 1 < count {
+            moved = Some((i, 1));
+        }
+    }
+    if let Some((i, offset)) = moved {
+        movePass(&mut stack.passes, i, offset);
+    }
+    if ui.button("Reset order") {
+        stack.passes = defaultPasses();
+    }
+
+    let settings = &mut stack.settings;
+    if ui.collapsing_header("Bloom", imgui::TreeNodeFlags::empty()) {
+        ui.slider("Threshold", 0.0, 4.0, &mut settings.bloomThreshold);
+        ui.slider("Knee", 0.0, 2.0, &mut settings.bloomKnee);
+        ui.slider("Intensity", 0.0, 2.0, &mut settings.bloomIntensity);
+        ui.slider("Blur passes", 1, 8, &mut settings.bloomBlurPasses);
+        ui.slider("Blur sigma", 0.5, 4.0, &mut settings.bloomSigma);
+    }
+    if ui.collapsing_header("Tone mapping", imgui::TreeNodeFlags::empty()) {
+        ui.slider("Exposure", 0.1, 8.0, &mut settings.exposure);
+        let mut item = settings.tonemapper as usize;
+        if ui.combo_simple_string("Curve", &mut item, &["Reinhard", "ACES"]) {
+            settings.tonemapper = [Tonemapper::Reinhard, Tonemapper::Aces][item];
+        }
+    }
+    if ui.collapsing_header("Color grading", imgui::TreeNodeFlags::empty()) {
+        ui.slider("Brightness", -0.5, 0.5, &mut settings.brightness);
+        ui.slider("Contrast", 0.0, 2.0, &mut settings.contrast);
+        ui.slider("Saturation", 0.0, 2.0, &mut settings.saturation);
+        ui.color_edit3("Tint", &mut settings.tint);
+    }
+    if ui.collapsing_header("Vignette", imgui::TreeNodeFlags::empty()) {
+        ui.slider("Radius", 0.0, 2.0, &mut settings.vignetteRadius);
+        ui.slider("Softness", 0.01, 2.0, &mut settings.vignetteSoftness);
+        ui.slider("Strength", 0.0, 1.0, &mut settings.vignetteStrength);
+    }
+    if ui.collapsing_header("Gamma", imgui::TreeNodeFlags::empty()) {
+        ui.slider("Gamma", 1.0, 3.0, &mut settings.gamma);
+    }
+    if ui.collapsing_header("FXAA", imgui::TreeNodeFlags::empty()) {
+        ui.slider("Edge threshold", 0.063, 0.333, &mut settings.fxaaEdgeThreshold);
+        ui.slider("Edge threshold min", 0.0, 0.1, &mut settings.fxaaEdgeThresholdMin);
+        ui.slider("Subpixel", 0.0, 1.0, &mut settings.fxaaSubpixel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaultOrderTonemapsBeforeGamma() {
+        let passes = defaultPasses();
+        let position = |kind| passes.iter().position(|pass| pass.kind == kind).unwrap();
+        assert!(position(PassKind::Bloom) < position(PassKind::Tonemap));
+        assert!(position(PassKind::Tonemap) < position(PassKind::Gamma));
+        assert_eq!(passes.last().unwrap().kind, PassKind::Fxaa);
+        assert!(!passes[position(PassKind::Vignette)].enabled);
+    }
+
+    #[test]
+    fn passesMoveWithinBounds() {
+        let mut passes = defaultPasses();
+        assert!(!movePass(&mut passes, 0, -1));
+        let last = passes.len() - 1;
+        assert!(!movePass(&mut passes, last, 1));
+        assert!(movePass(&mut passes, 0, 1));
+        assert_eq!(passes[0].kind, PassKind::Tonemap);
+        assert_eq!(passes[1].kind, PassKind::Bloom);
+        assert!(movePass(&mut passes, 1, -1));
+        assert_eq!(passes, defaultPasses());
+    }
+
+    #[test]
+    fn samplesArePowersOfTwo() {
+        assert_eq!(clampSamples(0, 8), 1);
+        assert_eq!(clampSamples(4, 8), 4);
+        assert_eq!(clampSamples(6, 8), 4);
+        assert_eq!(clampSamples(8, 4), 4);
+        assert_eq!(clampSamples(16, 1), 1);
+    }
+
+    #[test]
+    fn blurWeightsAreNormalized() {
+        let weights = gaussianWeights(MAX_BLUR_TAPS, 2.0);
+        let total = weights[0] + 2.0 * weights[1..].iter().sum::<f32>();
+        assert!((total - 1.0).abs() < 1e-5);
+        assert!(weights.windows(2).all(|w| w[0] > w[1]));
+    }
+}
@@ -0,0 +1,116 @@
+#![allow(non_snake_case)]
+
+use gl::types::{GLbitfield, GLintptr, GLsizeiptr, GLsync, GLuint};
+use std::ptr;
+
+/// Frames in flight, the GPU can still be reading the previous two while the CPU writes.
+pub const SEGMENTS: usize = 3;
+const STORAGE_FLAGS: GLbitfield = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT;
+const MAP_FLAGS: GLbitfield = STORAGE_FLAGS | gl::MAP_FLUSH_EXPLICIT_BIT;
+
+/// A persistently mapped buffer split into `SEGMENTS` per-frame segments.
+///
+/// The CPU writes into the current segment while the GPU reads older ones. Each segment is
+/// fenced when its frame is submitted and waited on before it is written to again, so
+/// writes never stall on (or race with) draws still in flight.
+///
+/// The mapping is write-only and not coherent: written bytes are handed to the GPU with
+/// `flush` before drawing from them.
+pub struct PersistentRing {
+    buffer: GLuint,
+    mapped: *mut u8,
+    segmentBytes: usize,
+    current: usize,
+    fences: [GLsync; SEGMENTS],
+}
+
+#[allow(dead_code)]
+impl PersistentRing {
+    pub fn new(segmentBytes: usize) -> PersistentRing {
+        let segmentBytes = segmentBytes.max(256);
+        let mut ring = PersistentRing {
+            buffer: 0,
+            mapped: ptr::null_mut(),
+            segmentBytes,
+            current: 0,
+            fences: [ptr::null(); SEGMENTS],
+        };
+        let size = (segmentBytes * SEGMENTS) as GLsizeiptr;
+        unsafe {
+            gl::CreateBuffers(1, &mut ring.buffer);
+            gl::NamedBufferStorage(ring.buffer, size, ptr::null(), STORAGE_FLAGS);
+            ring.mapped = gl::MapNamedBufferRange(ring.buffer, 0, size, MAP_FLAGS) as *mut u8;
+        }
+        assert!(!ring.mapped.is_null(), "Failed to map ring buffer ({} bytes)", size);
+        ring
+    }
+
+    pub fn buffer(&self) -> GLuint {
+        self.buffer
+    }
+
+    pub fn segmentBytes(&self) -> usize {
+        self.segmentBytes
+    }
+
+    /// Byte offset of the current segment inside `buffer()`, for binding and draw offsets.
+    pub fn segmentOffset(&self) -> usize {
+        self.current * self.segmentBytes
+    }
+
+    /// Start of the current segment in mapped memory.
+    pub fn segmentPtr(&self) -> *mut u8 {
+        unsafe { self.mapped.add(self.segmentOffset()) }
+    }
+
+    /// Makes the first `bytes` written to the current segment visible to the GPU.
+    pub fn flush(&self, bytes: usize) {
+        if bytes == 0 {
+            return;
+        }
+        unsafe {
+            gl::FlushMappedNamedBufferRange(self.buffer, self.segmentOffset() as GLintptr, bytes as GLsizeiptr);
+        }
+    }
+
+    /// Fences the current segment after the frame's draws were issued and moves on to the
+    /// next one, waiting until the GPU has finished with it.
+    pub fn advance(&mut self) {
+        unsafe {
+            self.fences[self.current] = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+            self.current = (self.current + 1) % SEGMENTS;
+            self.wait(self.current);
+        }
+    }
+
+    unsafe fn wait(&mut self, segment: usize) {
+        let fence = self.fences[segment];
+        if fence.is_null() {
+            return;
+        }
+        unsafe {
+            loop {
+                let result = gl::ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, 1_000_000);
+                if result != gl::TIMEOUT_EXPIRED {
+                    break;
+                }
+            }
+            gl::DeleteSync(fence);
+        }
+        self.fences[segment] = ptr::null();
+    }
+
+    pub fn destroy(&mut self) {
+        unsafe {
+            for fence in self.fences.iter_mut() {
+                if !fence.is_null() {
+                    gl::DeleteSync(*fence);
+                    *fence = ptr::null();
+                }
+            }
+            gl::UnmapNamedBuffer(self.buffer);
+            gl::DeleteBuffers(1, &self.buffer);
+        }
+        self.mapped = ptr::null_mut();
+    }
+}
@@ -0,0 +1,427 @@
+#![allow(non_snake_case)]
+
+//! Node hierarchy with local translation/rotation/scale. World matrices are cached per node
+//! and recomputed lazily: changing a node's local transform or parent marks it and everything
+//! below it dirty.
+//!
+//! Nodes carry components referring into `main`'s lists (meshes, lights) or describing a
+//! camera, placed at the node's world transform.
+
+use crate::lights::editVector;
+use cgmath::{Deg, Euler, Matrix4, Quaternion, Rad, SquareMatrix, vec3};
+use imgui::{TreeNodeFlags, Ui};
+
+type Vector3 = cgmath::Vector3<f32>;
+
+pub type NodeId = usize;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Transform {
+    pub translation: Vector3,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3,
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform {
+            translation: vec3(0.0, 0.0, 0.0),
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            scale: vec3(1.0, 1.0, 1.0),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl Transform {
+    pub fn fromTranslation(translation: Vector3) -> Transform {
+        Transform {
+            translation,
+            ..Transform::default()
+        }
+    }
+
+    /// Scale, then rotate, then translate.
+    pub fn matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+}
+
+/// What a node places in the world.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Component {
+    /// Index into the loaded models.
+    Mesh(usize),
+    /// Index into the light list, its position and direction being local to the node.
+    Light(usize),
+    /// Looks down the node's -Z, vertical field of view in degrees.
+    Camera { fov: f32 },
+}
+
+pub struct Node {
+    pub name: String,
+    pub components: Vec<Component>,
+    local: Transform,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    world: Matrix4<f32>,
+    dirty: bool,
+}
+
+#[allow(dead_code)]
+impl Node {
+    pub fn local(&self) -> &Transform {
+        &self.local
+    }
+
+    pub fn parent(&self) -> Option<NodeId> {
+        self.parent
+    }
+
+    pub fn children(&self) -> &[NodeId] {
+        &self.children
+    }
+}
+
+/// Nodes live in one list and refer to each other by index.
+pub struct SceneGraph {
+    nodes: Vec<Node>,
+}
+
+#[allow(dead_code)]
+impl SceneGraph {
+    pub fn new() -> SceneGraph {
+        SceneGraph { nodes: Vec::new() }
+    }
+
+    pub fn add(&mut self, name: &str, parent: Option<NodeId>, local: Transform) -> NodeId {
+        let id = self.nodes.len();
+        self.nodes.push(Node {
+            name: name.to_string(),
+            components: Vec::new(),
+            local,
+            parent,
+            children: Vec::new(),
+            world: Matrix4::identity(),
+            dirty: true,
+        });
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(id);
+        }
+        id
+    }
+
+    /// `add` with one component.
+    pub fn addWith(&mut self, name: &str, parent: Option<NodeId>, local: Transform, component: Component) -> NodeId {
+        let id = self.add(name, parent, local);
+        self.nodes[id].components.push(component);
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id]
+    }
+
+    /// For the name and components, transforms go through `setLocal` and `setParent`.
+    pub fn nodeMut(&mut self, id: NodeId) -> &mut Node {
+        &mut self.nodes[id]
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = NodeId> + '_ {
+        (0..self.nodes.len()).filter(|&id| self.nodes[id].parent.is_none())
+    }
+
+    pub fn setLocal(&mut self, id: NodeId, local: Transform) {
+        self.nodes[id].local = local;
+        self.markDirty(id);
+    }
+
+    /// Whether `ancestor` is `id` or above it.
+    pub fn isAncestor(&self, ancestor: NodeId, id: NodeId) -> bool {
+        let mut current = Some(id);
+        while let Some(node) = current {
+            if node == ancestor {
+                return true;
+            }
+            current = self.nodes[node].parent;
+        }
+        false
+    }
+
+    /// Moves `id` under `parent` (a root for `None`), keeping its local transform. Refused,
+    /// returning false, when `parent` is inside `id`'s own subtree.
+    pub fn setParent(&mut self, id: NodeId, parent: Option<NodeId>) -> bool {
+        if parent.is_some_and(|parent| self.isAncestor(id, parent)) {
+            return false;
+        }
+        if let Some(old) = self.nodes[id].parent {
+            self.nodes[old].children.retain(|&child| child != id);
+        }
+        self.nodes[id].parent = parent;
+        if let Some(parent) = parent {
+            self.nodes[parent].children.push(id);
+        }
+        self.markDirty(id);
+        true
+    }
+
+    /// A dirty node's subtree is dirty already, since nodes are only cleaned parent first.
+    fn markDirty(&mut self, id: NodeId) {
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let node = &mut self.nodes[id];
+            if node.dirty {
+                continue;
+            }
+            node.dirty = true;
+            stack.extend_from_slice(&node.children);
+        }
+    }
+
+    pub fn isDirty(&self, id: NodeId) -> bool {
+        self.nodes[id].dirty
+    }
+
+    /// Parent world times local, recomputing the dirty ancestors first.
+    pub fn world(&mut self, id: NodeId) -> Matrix4<f32> {
+        if !self.nodes[id].dirty {
+            return self.nodes[id].world;
+        }
+        let parentWorld = match self.nodes[id].parent {
+            Some(parent) => self.world(parent),
+            None => Matrix4::identity(),
+        };
+        let node = &mut self.nodes[id];
+        node.world = parentWorld * node.local.matrix();
+        node.dirty = false;
+        node.world
+    }
+
+    /// Drops `Light(index)` components and moves later light indices down by one, after that
+    /// light was removed from the list. Its nodes stay, as plain transforms.
+    pub fn lightRemoved(&mut self, index: usize) {
+        for node in self.nodes.iter_mut() {
+            node.components.retain(|&component| component != Component::Light(index));
+            for component in node.components.iter_mut() {
+                if let Component::Light(i) = component
+                    && *i > index
+                {
+                    *i -= 1;
+                }
+            }
+        }
+    }
+
+    /// Every component with its node's world matrix, in node order.
+    pub fn components(&mut self) -> Vec<(NodeId, Matrix4<f32>, Component)> {
+        let mut found = Vec::new();
+        for id in 0..self.nodes.len() {
+            if self.nodes[id].components.is_empty() {
+                continue;
+            }
+            let world = self.world(id);
+            found.extend(self.nodes[id].components.iter().map(|&component| (id, world, component)));
+        }
+        found
+    }
+}
+
+fn editHierarchy(ui: &Ui, graph: &SceneGraph, id: NodeId, selected: &mut Option<NodeId>) {
+    let node = graph.node(id);
+    let mut flags = TreeNodeFlags::OPEN_ON_ARROW | TreeNodeFlags::DEFAULT_OPEN | TreeNodeFlags::SPAN_AVAIL_WIDTH;
+    if node.children.is_empty() {
+        flags |= TreeNodeFlags::LEAF;
+    }
+    if *selected == Some(id) {
+        flags |= TreeNodeFlags::SELECTED;
+    }
+    let open = ui.tree_node_config(format!("{}##node{}", node.name, id)).flags(flags).push();
+    if ui.is_item_clicked() {
+        *selected = Some(id);
+    }
+    if let Some(_open) = open {
+        for &child in node.children.iter() {
+            editHierarchy(ui, graph, child, selected);
+        }
+    }
+}
+
+/// ImGui hierarchy and inspector for the `selected` node. Returns a camera node to look
+/// through when its button is pressed. `meshNames` and `lightNames` label the components.
+pub fn editSceneGraph(ui: &Ui, graph: &mut SceneGraph, selected: &mut Option<NodeId>, meshNames: &[&str], lightNames: &[String]) -> Option<NodeId> {
+    let roots: Vec<NodeId> = graph.roots().collect();
+    for root in roots {
+        editHierarchy(ui, graph, root, selected);
+    }
+    if ui.button("Add node") {
+        let id = graph.add(&format!("Node {}", graph.len()), *selected, Transform::default());
+        *selected = Some(id);
+    }
+
+    let id = (*selected).filter(|&id| id < graph.len())?;
+    ui.separator();
+    ui.input_text("Name", &mut graph.nodeMut(id).name).build();
+
+    // Parent candidates exclude the node's subtree
+    let candidates: Vec<Option<NodeId>> =
+        std::iter::once(None).chain((0..graph.len()).filter(|&other| !graph.isAncestor(id, other)).map(Some)).collect();
+    let names: Vec<String> = candidates.iter().map(|c| c.map_or("(none)".to_string(), |c| graph.node(c).name.clone())).collect();
+    let mut parentItem = candidates.iter().position(|&c| c == graph.node(id).parent).unwrap_or(0);
+    if ui.combo("Parent", &mut parentItem, &names, |name| name.into()) {
+        graph.setParent(id, candidates[parentItem]);
+    }
+
+    let mut local = *graph.node(id).local();
+    let mut changed = editVector(ui, "Translation", &mut local.translation, 0.05);
+    // Euler angles only for editing, the rotation is stored as a quaternion
+    let euler = Euler::from(local.rotation);
+    let mut degrees = vec3(Deg::from(euler.x).0, Deg::from(euler.y).0, Deg::from(euler.z).0);
+    if editVector(ui, "Rotation", &mut degrees, 0.5) {
+        local.rotation = Quaternion::from(Euler::new(Rad::from(Deg(degrees.x)), Rad::from(Deg(degrees.y)), Rad::from(Deg(degrees.z))));
+        changed = true;
+    }
+    changed |= editVector(ui, "Scale", &mut local.scale, 0.01);
+    if changed {
+        graph.setLocal(id, local);
+    }
+
+    let mut viewThrough = None;
+    for (i, component) in graph.nodeMut(id).components.iter_mut().enumerate() {
+        let _id = ui.push_id_usize(i);
+        match component {
+            Component::Mesh(index) => {
+                ui.combo_simple_string("Mesh", index, meshNames);
+            }
+            Component::Light(index) => {
+                ui.text(format!("Light: {}", lightNames.get(*index).map_or("(removed)", String::as_str)));
+            }
+            Component::Camera { fov } => {
+                ui.slider("Camera fov", 1.0, 45.0, fov);
+                if ui.button("Look through") {
+                    viewThrough = Some(id);
+                }
+            }
+        }
+    }
+    viewThrough
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{InnerSpace, Point3, Rotation3, Transform as _};
+
+    fn close(a: Matrix4<f32>, b: Matrix4<f32>) -> bool {
+        let (a, b): ([[f32; 4]; 4], [[f32; 4]; 4]) = (a.into(), b.into());
+        a.iter().flatten().zip(b.iter().flatten()).all(|(x, y)| (x - y).abs() < 1e-5)
+    }
+
+    fn origin(graph: &mut SceneGraph, id: NodeId) -> Point3<f32> {
+        graph.world(id).transform_point(Point3::new(0.0, 0.0, 0.0))
+    }
+
+    #[test]
+    fn worldComposesParentFirst() {
+        let mut graph = SceneGraph::new();
+        let parent = graph.add(
+            "parent",
+            None,
+            Transform {
+                translation: vec3(1.0, 0.0, 0.0),
+                rotation: Quaternion::from_angle_y(Deg(90.0)),
+                scale: vec3(2.0, 2.0, 2.0),
+            },
+        );
+        let child = graph.add("child", Some(parent), Transform::fromTranslation(vec3(1.0, 0.0, 0.0)));
+        // +X rotated a quarter turn about Y is -Z, then scaled and moved
+        let p = origin(&mut graph, child);
+        assert!((p - Point3::new(1.0, 0.0, -2.0)).magnitude() < 1e-5, "{:?}", p);
+        let expected = graph.node(parent).local().matrix() * graph.node(child).local().matrix();
+        assert!(close(graph.world(child), expected));
+    }
+
+    #[test]
+    fn movingAParentDirtiesTheSubtree() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add("root", None, Transform::default());
+        let child = graph.add("child", Some(root), Transform::fromTranslation(vec3(0.0, 1.0, 0.0)));
+        let grandchild = graph.add("grandchild", Some(child), Transform::fromTranslation(vec3(0.0, 1.0, 0.0)));
+        let other = graph.add("other", None, Transform::default());
+        assert_eq!(origin(&mut graph, grandchild), Point3::new(0.0, 2.0, 0.0));
+        graph.world(other);
+        assert!(!graph.isDirty(child) && !graph.isDirty(grandchild) && !graph.isDirty(other));
+
+        graph.setLocal(root, Transform::fromTranslation(vec3(5.0, 0.0, 0.0)));
+        assert!(graph.isDirty(root) && graph.isDirty(child) && graph.isDirty(grandchild));
+        assert!(!graph.isDirty(other));
+        assert_eq!(origin(&mut graph, grandchild), Point3::new(5.0, 2.0, 0.0));
+        // Computing the grandchild cleaned its ancestors on the way
+        assert!(!graph.isDirty(root) && !graph.isDirty(child));
+    }
+
+    #[test]
+    fn dirtyMarksReachCleanedDescendants() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add("root", None, Transform::default());
+        let child = graph.add("child", Some(root), Transform::default());
+        let grandchild = graph.add("grandchild", Some(child), Transform::default());
+        graph.world(grandchild);
+        graph.setLocal(child, Transform::fromTranslation(vec3(1.0, 0.0, 0.0)));
+        graph.world(child);
+        // The grandchild stayed dirty under a clean parent, and must be recomputed
+        assert!(graph.isDirty(grandchild));
+        graph.setLocal(root, Transform::fromTranslation(vec3(0.0, 0.0, 1.0)));
+        assert_eq!(origin(&mut graph, grandchild), Point3::new(1.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn reparentingKeepsLocalAndRejectsCycles() {
+        let mut graph = SceneGraph::new();
+        let a = graph.add("a", None, Transform::fromTranslation(vec3(10.0, 0.0, 0.0)));
+        let b = graph.add("b", None, Transform::default());
+        let c = graph.add("c", Some(b), Transform::fromTranslation(vec3(0.0, 1.0, 0.0)));
+        assert_eq!(origin(&mut graph, c), Point3::new(0.0, 1.0, 0.0));
+
+        assert!(graph.setParent(b, Some(a)));
+        assert_eq!(graph.node(a).children(), &[b]);
+        assert_eq!(origin(&mut graph, c), Point3::new(10.0, 1.0, 0.0));
+        assert!(!graph.setParent(a, Some(c)));
+        assert!(!graph.setParent(b, Some(b)));
+        assert_eq!(graph.node(a).parent(), None);
+
+        assert!(graph.setParent(c, None));
+        assert!(graph.node(b).children().is_empty());
+        assert_eq!(origin(&mut graph, c), Point3::new(0.0, 1.0, 0.0));
+        assert_eq!(graph.roots().collect::<Vec<_>>(), vec![a, c]);
+    }
+
+    #[test]
+    fn componentsCarryTheirWorld() {
+        let mut graph = SceneGraph::new();
+        let root = graph.add("root", None, Transform::fromTranslation(vec3(0.0, 3.0, 0.0)));
+        graph.addWith("mesh", Some(root), Transform::default(), Component::Mesh(2));
+        graph.addWith("camera", None, Transform::default(), Component::Camera { fov: 45.0 });
+        let components = graph.components();
+        assert_eq!(components.len(), 2);
+        assert_eq!(components[0].2, Component::Mesh(2));
+        assert!(close(components[0].1, Matrix4::from_translation(vec3(0.0, 3.0, 0.0))));
+        assert_eq!(components[1].2, Component::Camera { fov: 45.0 });
+    }
+
+    #[test]
+    fn removedLightsAreDroppedAndLaterOnesRenumbered() {
+        let mut graph = SceneGraph::new();
+        let lights: Vec<NodeId> =
+            (0..3).map(|i| graph.addWith("light", None, Transform::default(), Component::Light(i))).collect();
+        graph.lightRemoved(1);
+        assert_eq!(graph.node(lights[0]).components, [Component::Light(0)]);
+        assert!(graph.node(lights[1]).components.is_empty());
+        assert_eq!(graph.node(lights[2]).components, [Component::Light(1)]);
+    }
+}
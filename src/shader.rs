@@ -1,6 +1,7 @@
 #![allow(non_snake_case)]
 /* Based on https://github.com/bwasty/learn-opengl-rs/blob/master/src/shader.rs */
 
+use crate::texture::Texture;
 use cgmath::{Matrix, Matrix4, Vector2, Vector3, Vector4};
 use gl;
 use gl::types::*;
@@ -8,6 +9,7 @@ use gl::types::*;
 use std::ffi::CString;
 use std::fs::File;
 use std::io::Read;
+use std::path::Path;
 use std::ptr;
 use std::str;
 
@@ -78,6 +80,15 @@ impl Shader {
         }
     }
 
+    /// False when compiling or linking failed, the errors were printed already.
+    pub fn isLinked(&self) -> bool {
+        let mut status = gl::FALSE as GLint;
+        unsafe {
+            gl::GetProgramiv(self.id, gl::LINK_STATUS, &mut status);
+        }
+        status == gl::TRUE as GLint
+    }
+
     pub fn getAttribLocation(&self, name: &str) -> GLint {
         unsafe {
             let c_str = CString::new(name.as_bytes()).unwrap();
@@ -155,12 +166,15 @@ impl Shader {
         }
     }
 
+    /// Binds `texture` to `unit` and points the sampler uniform `name` at it.
+    pub fn setTexture(&self, name: &str, unit: u32, texture: &Texture) {
+        texture.bind(unit);
+        self.setUniform1i(name, unit as i32);
+    }
+
     // Utility
     unsafe fn compileShader(&self, path: &str, shaderType: GLenum) -> GLuint {
-        let mut file = File::open(path).unwrap_or_else(|_| panic!("Failed to open {}", path));
-        let mut code = String::new();
-        file.read_to_string(&mut code)
-            .expect("Failed to read vertex shader");
+        let code = readSource(path);
         let source = CString::new(code.as_bytes()).unwrap();
 
         unsafe {
@@ -224,3 +238,38 @@ impl Shader {
         }
     }
 }
+
+/// Reads a shader file, replacing `#include "file"` lines with that file's contents. Includes
+/// are relative to the including file and may not nest.
+fn readSource(path: &str) -> String {
+    let read = |path: &Path| {
+        let mut file = File::open(path).unwrap_or_else(|_| panic!("Failed to open {}", path.display()));
+        let mut code = String::new();
+        file.read_to_string(&mut code)
+            .unwrap_or_else(|_| panic!("Failed to read {}", path.display()));
+        code
+    };
+    let path = Path::new(path);
+    let mut code = String::new();
+    for line in read(path).lines() {
+        match line.trim().strip_prefix("#include") {
+            Some(include) => code.push_str(&read(&path.with_file_name(include.trim().trim_matches('"')))),
+            None => code.push_str(line),
+        }
+        code.push('\n');
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn includesArePastedInPlace() {
+        let code = readSource("resources/shaders/line_renderer.vert");
+        assert!(code.starts_with("#version"));
+        assert!(!code.contains("#include"));
+        assert!(code.contains("vec3 srgbToLinear(vec3 c)"));
+    }
+}
@@ -0,0 +1,435 @@
+#![allow(non_snake_case)]
+
+//! Shadow maps for directional and spot lights, all layers of one depth texture array.
+//!
+//! The first shadow casting directional light gets up to `MAX_CASCADES` cascades fitted to
+//! slices of the camera frustum, the next `MAX_SPOT_SHADOWS` spot lights one perspective map
+//! each. `lit.frag` and `pbr.frag` find a light's first layer in its packed `cone.z` and
+//! filter the comparisons with PCF.
+
+use crate::camera::Camera;
+use crate::framebuffer::Framebuffer;
+use crate::lights::{Light, LightKind};
+use crate::line_renderer::shapes::DebugShapes;
+use crate::shader::Shader;
+use crate::texture::{SamplerState, Texture};
+use cgmath::{Deg, EuclideanSpace, InnerSpace, Matrix4, Point3, SquareMatrix, Transform, Vector4, ortho, perspective, vec3};
+use gl::types::{GLint, GLuint};
+use imgui::Ui;
+
+type Vector3 = cgmath::Vector3<f32>;
+
+pub const MAX_CASCADES: usize = 4;
+pub const MAX_SPOT_SHADOWS: usize = 4;
+pub const SHADOW_LAYERS: usize = MAX_CASCADES + MAX_SPOT_SHADOWS;
+/// Texture unit of `u_shadowMap`, after the PBR material and IBL maps.
+pub const SHADOW_UNIT: u32 = 8;
+/// How far behind a cascade, towards the light, casters are still rendered.
+const CASTER_MARGIN: f32 = 20.0;
+const SPOT_NEAR: f32 = 0.05;
+
+const CASCADE_COLORS: [Vector3; MAX_CASCADES] = [
+    Vector3::new(1.0, 0.3, 0.3),
+    Vector3::new(0.3, 1.0, 0.3),
+    Vector3::new(0.3, 0.5, 1.0),
+    Vector3::new(1.0, 1.0, 0.3),
+];
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ShadowSettings {
+    pub enabled: bool,
+    /// Size of each layer, square.
+    pub resolution: u32,
+    pub cascades: usize,
+    /// Shadows of the directional light stop this far from the camera.
+    pub distance: f32,
+    /// Blend of logarithmic (1) and uniform (0) cascade splits.
+    pub splitLambda: f32,
+    /// Depth bias in shadow map depth units, and the extra bias at grazing angles.
+    pub constantBias: f32,
+    pub slopeBias: f32,
+    /// World units the lookup moves along the normal, at grazing angles.
+    pub normalBias: f32,
+    /// PCF kernel half size in texels, 0 is a single comparison.
+    pub pcfRadius: i32,
+    /// Draws the camera slice and light volume of each cascade.
+    pub showCascades: bool,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        ShadowSettings {
+            enabled: true,
+            resolution: 2048,
+            cascades: MAX_CASCADES,
+            distance: 30.0,
+            splitLambda: 0.75,
+            constantBias: 0.0005,
+            slopeBias: 0.002,
+            normalBias: 0.02,
+            pcfRadius: 1,
+            showCascades: false,
+        }
+    }
+}
+
+/// First shadow map layer of each light in `lights`, `None` for lights without shadows.
+/// Cascades always take the first `MAX_CASCADES` layers, whatever their count.
+pub fn shadowLayers(lights: &[Light]) -> Vec<Option<u32>> {
+    let mut directional = false;
+    let mut spots = 0;
+    lights
+        .iter()
+        .map(|light| {
+            if !light.enabled || !light.castsShadows {
+                return None;
+            }
+            match light.kind {
+                LightKind::Directional if !directional => {
+                    directional = true;
+                    Some(0)
+                }
+                LightKind::Spot if spots < MAX_SPOT_SHADOWS => {
+                    spots += 1;
+                    Some((MAX_CASCADES + spots - 1) as u32)
+                }
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// Far distance of each of `count` cascades between `near` and `far`, the practical split
+/// scheme: `lambda` blends logarithmic and uniform splits.
+pub fn cascadeSplits(near: f32, far: f32, count: usize, lambda: f32) -> Vec<f32> {
+    (1..=count)
+        .map(|i| {
+            let t = i as f32 / count as f32;
+            let logarithmic = near * (far / near).powf(t);
+            let uniform = near + (far - near) * t;
+            lambda * logarithmic + (1.0 - lambda) * uniform
+        })
+        .collect()
+}
+
+/// Orthographic view-projection of a directional light travelling along `direction` that
+/// covers the bounding sphere of `corners`. The sphere keeps the size constant while the
+/// camera turns and its center is snapped to whole texels, so shadow edges don't shimmer.
+pub fn fitCascade(corners: &[Vector3; 8], direction: Vector3, resolution: u32) -> Matrix4<f32> {
+    let center = corners.iter().fold(vec3(0.0, 0.0, 0.0), |sum, &c| sum + c) / 8.0;
+    let radius = corners.iter().map(|&c| (c - center).magnitude()).fold(0.0, f32::max);
+    // Rounded up so floating point noise doesn't change the texel size either
+    let radius = (radius * 16.0).ceil() / 16.0;
+    let direction = direction.normalize();
+    let up = if direction.y.abs() > 0.99 { vec3(1.0, 0.0, 0.0) } else { vec3(0.0, 1.0, 0.0) };
+    let view = Matrix4::look_at_rh(Point3::origin(), Point3::from_vec(direction), up);
+    let lightCenter = view.transform_point(Point3::from_vec(center));
+    let texel = 2.0 * radius / resolution as f32;
+    let (x, y) = ((lightCenter.x / texel).floor() * texel, (lightCenter.y / texel).floor() * texel);
+    let z = lightCenter.z;
+    ortho(x - radius, x + radius, y - radius, y + radius, -(z + radius + CASTER_MARGIN), -(z - radius)) * view
+}
+
+/// World space corners of the frustum `viewProjection`, near face first.
+pub fn frustumCorners(viewProjection: &Matrix4<f32>) -> [Vector3; 8] {
+    let inverse = viewProjection.invert().unwrap_or(Matrix4::identity());
+    let mut corners = [vec3(0.0, 0.0, 0.0); 8];
+    for (i, corner) in corners.iter_mut().enumerate() {
+        let ndc = Vector4::new(
+            if i & 1 == 0 { -1.0 } else { 1.0 },
+            if i & 2 == 0 { -1.0 } else { 1.0 },
+            if i & 4 == 0 { -1.0 } else { 1.0 },
+            1.0,
+        );
+        let world = inverse * ndc;
+        *corner = world.truncate() / world.w;
+    }
+    corners
+}
+
+/// Perspective view-projection covering the outer cone of a spot light.
+pub fn spotMatrix(light: &Light) -> Matrix4<f32> {
+    let direction = light.direction.normalize();
+    let up = if direction.y.abs() > 0.99 { vec3(1.0, 0.0, 0.0) } else { vec3(0.0, 1.0, 0.0) };
+    let position = Point3::from_vec(light.position);
+    // A little wider than the cone so PCF doesn't read past the edge
+    let fov = Deg((light.outerAngle * 2.0 + 2.0).min(170.0));
+    perspective(fov, 1.0, SPOT_NEAR, light.range.max(SPOT_NEAR * 2.0)) * Matrix4::look_at_rh(position, position + direction, up)
+}
+
+/// One cascade of the directional light.
+#[derive(Copy, Clone, Debug)]
+pub struct Cascade {
+    /// Distance from the camera where the cascade ends.
+    pub far: f32,
+    /// The camera frustum slice it covers.
+    pub slice: Matrix4<f32>,
+    pub viewProjection: Matrix4<f32>,
+}
+
+/// Cascades of `direction` over the camera frustum from `near` to `settings.distance`.
+pub fn cascades(camera: &Camera, aspect: f32, near: f32, direction: Vector3, settings: &ShadowSettings) -> Vec<Cascade> {
+    let view = camera.getViewMatrix();
+    let count = settings.cascades.clamp(1, MAX_CASCADES);
+    let mut sliceNear = near;
+    cascadeSplits(near, settings.distance.max(near * 2.0), count, settings.splitLambda)
+        .into_iter()
+        .map(|far| {
+            let slice = perspective(Deg(camera.zoom), aspect, sliceNear, far) * view;
+            sliceNear = far;
+            Cascade {
+                far,
+                slice,
+                viewProjection: fitCascade(&frustumCorners(&slice), direction, settings.resolution),
+            }
+        })
+        .collect()
+}
+
+/// Cascades of the frame and the light matrices of every layer, for the shaders.
+struct Frame {
+    cascades: Vec<Cascade>,
+    matrices: [Matrix4<f32>; SHADOW_LAYERS],
+    used: [bool; SHADOW_LAYERS],
+}
+
+/// The depth texture array and the pass rendering into it.
+pub struct ShadowMaps {
+    pub settings: ShadowSettings,
+    depth: Texture,
+    framebuffer: Framebuffer,
+    shader: Shader,
+    frame: Frame,
+}
+
+#[allow(dead_code)]
+impl ShadowMaps {
+    pub fn new() -> ShadowMaps {
+        let settings = ShadowSettings::default();
+        ShadowMaps {
+            settings,
+            depth: ShadowMaps::allocate(settings.resolution),
+            framebuffer: Framebuffer::empty(),
+            shader: Shader::new("resources/shaders/shadow_depth.vert", "resources/shaders/shadow_depth.frag"),
+            frame: Frame {
+                cascades: Vec::new(),
+                matrices: [Matrix4::identity(); SHADOW_LAYERS],
+                used: [false; SHADOW_LAYERS],
+            },
+        }
+    }
+
+    fn allocate(resolution: u32) -> Texture {
+        let depth = Texture::storage(gl::TEXTURE_2D_ARRAY, resolution, resolution, SHADOW_LAYERS as u32, 1, gl::DEPTH_COMPONENT32F);
+        depth.setSampler(&SamplerState::clamped());
+        unsafe {
+            // Hardware comparison, bilinear filtering then blends four of them
+            gl::TextureParameteri(depth.id(), gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE as GLint);
+            gl::TextureParameteri(depth.id(), gl::TEXTURE_COMPARE_FUNC, gl::LEQUAL as GLint);
+        }
+        depth
+    }
+
+    /// Renders the shadow casters of the enabled `lights`. `drawScene` draws every caster
+    /// after setting `u_model` on the shader it's given.
+    pub fn render(&mut self, lights: &[Light], camera: &Camera, aspect: f32, near: f32, drawScene: impl Fn(&Shader)) {
+        self.frame.cascades.clear();
+        self.frame.used = [false; SHADOW_LAYERS];
+        if !self.settings.enabled {
+            return;
+        }
+        if self.depth.width() != self.settings.resolution {
+            self.depth.destroy();
+            self.depth = ShadowMaps::allocate(self.settings.resolution);
+        }
+        for (light, layer) in lights.iter().zip(shadowLayers(lights)) {
+            let Some(layer) = layer else {
+                continue;
+            };
+            let layer = layer as usize;
+            if light.kind == LightKind::Directional {
+                self.frame.cascades = cascades(camera, aspect, near, light.direction, &self.settings);
+                for (i, cascade) in self.frame.cascades.iter().enumerate() {
+                    self.frame.matrices[layer + i] = cascade.viewProjection;
+                    self.frame.used[layer + i] = true;
+                }
+            } else {
+                self.frame.matrices[layer] = spotMatrix(light);
+                self.frame.used[layer] = true;
+            }
+        }
+
+        let mut viewport: [GLint; 4] = [0; 4];
+        let mut polygonMode: [GLint; 2] = [0; 2];
+        let mut previous: GLint = 0;
+        unsafe {
+            gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+            gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut previous);
+            // Wireframe mode would leave holes in the depth
+            gl::GetIntegerv(gl::POLYGON_MODE, polygonMode.as_mut_ptr());
+            gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+        }
+        self.shader.bind();
+        for layer in (0..SHADOW_LAYERS).filter(|&layer| self.frame.used[layer]) {
+            self.framebuffer.attachDepthLayer(&self.depth, layer as u32);
+            self.framebuffer.bind();
+            unsafe {
+                gl::Clear(gl::DEPTH_BUFFER_BIT);
+            }
+            self.shader.setMatrix4f("u_lightViewProjection", &self.frame.matrices[layer]);
+            drawScene(&self.shader);
+        }
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, previous as GLuint);
+            gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+            gl::PolygonMode(gl::FRONT_AND_BACK, polygonMode[0] as u32);
+        }
+    }
+
+    /// Sets the shadow uniforms on the bound `lit.frag` or `pbr.frag` shader.
+    pub fn bind(&self, shader: &Shader, view: &Matrix4<f32>) {
+        shader.setUniform1i("u_shadows", self.settings.enabled as i32);
+        if !self.settings.enabled {
+            return;
+        }
+        shader.setTexture("u_shadowMap", SHADOW_UNIT, &self.depth);
+        for (i, matrix) in self.frame.matrices.iter().enumerate() {
+            shader.setMatrix4f(&format!("u_shadowMatrices[{}]", i), matrix);
+        }
+        let mut splits = [0.0; MAX_CASCADES];
+        for (split, cascade) in splits.iter_mut().zip(self.frame.cascades.iter()) {
+            *split = cascade.far;
+        }
+        shader.setUniform4f("u_cascadeSplits", splits[0], splits[1], splits[2], splits[3]);
+        shader.setUniform1i("u_cascadeCount", self.frame.cascades.len() as i32);
+        shader.setMatrix4f("u_view", view);
+        shader.setUniform1f("u_shadowBias", self.settings.constantBias);
+        shader.setUniform1f("u_shadowSlopeBias", self.settings.slopeBias);
+        shader.setUniform1f("u_shadowNormalBias", self.settings.normalBias);
+        shader.setUniform1i("u_pcfRadius", self.settings.pcfRadius);
+    }
+
+    /// The cascades rendered last frame, empty without a shadow casting directional light.
+    pub fn cascades(&self) -> &[Cascade] {
+        &self.frame.cascades
+    }
+
+    /// Each cascade's camera slice, and the light volume around it in a dimmer color.
+    pub fn pushCascadeFrusta(&self, lines: &mut impl DebugShapes) {
+        for (cascade, color) in self.frame.cascades.iter().zip(CASCADE_COLORS) {
+            lines.pushFrustum(&cascade.slice, color);
+            lines.pushFrustum(&cascade.viewProjection, color * 0.5);
+        }
+    }
+
+    pub fn destroy(&self) {
+        self.depth.destroy();
+        self.framebuffer.destroy();
+        self.shader.delete();
+    }
+}
+
+/// ImGui controls for `settings`.
+pub fn editShadows(ui: &Ui, settings: &mut ShadowSettings) {
+    ui.checkbox("Shadows", &mut settings.enabled);
+    let resolutions = [512, 1024, 2048, 4096];
+    let mut resolutionItem = resolutions.iter().position(|&r| r == settings.resolution).unwrap_or(2);
+    if ui.combo_simple_string("Resolution", &mut resolutionItem, &["512", "1024", "2048", "4096"]) {
+        settings.resolution = resolutions[resolutionItem];
+    }
+    let mut cascades = settings.cascades as i32;
+    if ui.slider("Cascades", 1, MAX_CASCADES as i32, &mut cascades) {
+        settings.cascades = cascades as usize;
+    }
+    ui.slider("Distance", 5.0, 100.0, &mut settings.distance);
+    ui.slider("Split lambda", 0.0, 1.0, &mut settings.splitLambda);
+    imgui::Drag::new("Constant bias").speed(0.00005).range(0.0, 0.01).display_format("%.5f").build(ui, &mut settings.constantBias);
+    imgui::Drag::new("Slope bias").speed(0.0001).range(0.0, 0.05).display_format("%.4f").build(ui, &mut settings.slopeBias);
+    ui.slider("Normal bias", 0.0, 0.2, &mut settings.normalBias);
+    ui.slider("PCF radius", 0, 3, &mut settings.pcfRadius);
+    ui.checkbox("Show cascades", &mut settings.showCascades);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::vec4;
+
+    const WHITE: Vector3 = Vector3::new(1.0, 1.0, 1.0);
+
+    fn inside(viewProjection: &Matrix4<f32>, p: Vector3) -> bool {
+        let clip = viewProjection * p.extend(1.0);
+        let ndc = clip.truncate() / clip.w;
+        ndc.x.abs() <= 1.0 + 1e-4 && ndc.y.abs() <= 1.0 + 1e-4 && ndc.z.abs() <= 1.0 + 1e-4
+    }
+
+    #[test]
+    fn splitsEndAtTheFarPlane() {
+        let uniform = cascadeSplits(1.0, 9.0, 4, 0.0);
+        assert_eq!(uniform, vec![3.0, 5.0, 7.0, 9.0]);
+        let logarithmic = cascadeSplits(1.0, 16.0, 4, 1.0);
+        for (split, expected) in logarithmic.iter().zip([2.0, 4.0, 8.0, 16.0]) {
+            assert!((split - expected).abs() < 1e-4, "{} != {}", split, expected);
+        }
+        assert!(cascadeSplits(0.1, 30.0, 3, 0.5).windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn cascadeCoversItsSlice() {
+        let camera = Camera { pos: Point3::new(3.0, 2.0, 5.0), ..Camera::default() };
+        let settings = ShadowSettings::default();
+        let direction = vec3(-0.4, -1.0, -0.6);
+        let cascades = cascades(&camera, 4.0 / 3.0, 0.1, direction, &settings);
+        assert_eq!(cascades.len(), settings.cascades);
+        assert!((cascades.last().unwrap().far - settings.distance).abs() < 1e-4);
+        for cascade in cascades.iter() {
+            for corner in frustumCorners(&cascade.slice) {
+                assert!(inside(&cascade.viewProjection, corner), "{:?} outside cascade {}", corner, cascade.far);
+            }
+        }
+    }
+
+    #[test]
+    fn castersTowardsTheLightAreKept() {
+        let corners = frustumCorners(&ortho(-1.0, 1.0, -1.0, 1.0, -1.0, 1.0));
+        let matrix = fitCascade(&corners, vec3(0.0, -1.0, 0.0), 1024);
+        assert!(inside(&matrix, vec3(0.0, 10.0, 0.0)));
+        // Behind the receivers nothing can cast onto them
+        assert!(!inside(&matrix, vec3(0.0, -10.0, 0.0)));
+    }
+
+    #[test]
+    fn cascadeOriginSnapsToTexels() {
+        let corners = frustumCorners(&ortho(-4.0, 4.0, -4.0, 4.0, -4.0, 4.0));
+        let a = fitCascade(&corners, vec3(0.0, -1.0, 0.0), 64);
+        // Moving less than a texel keeps the same projection
+        let moved = corners.map(|c| c + vec3(0.01, 0.0, 0.0));
+        let b = fitCascade(&moved, vec3(0.0, -1.0, 0.0), 64);
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn layersGoToTheFirstCasters() {
+        let sun = Light::directional(vec3(0.0, -1.0, 0.0), WHITE, 1.0);
+        let spot = Light::spot(vec3(0.0, 3.0, 0.0), vec3(0.0, -1.0, 0.0), WHITE, 1.0, 8.0, 20.0, 30.0);
+        let point = Light::point(vec3(0.0, 1.0, 0.0), WHITE, 1.0, 5.0);
+        let mut quiet = spot;
+        quiet.castsShadows = false;
+        let lights = [point, spot, sun, sun, quiet, spot, spot, spot, spot];
+        let layers = shadowLayers(&lights);
+        let expected = [None, Some(4), Some(0), None, None, Some(5), Some(6), Some(7), None];
+        assert_eq!(layers, expected);
+    }
+
+    #[test]
+    fn spotMapLooksDownTheCone() {
+        let spot = Light::spot(vec3(0.0, 3.0, 0.0), vec3(0.0, -1.0, 0.0), WHITE, 1.0, 8.0, 20.0, 30.0);
+        let matrix = spotMatrix(&spot);
+        let below = matrix * vec4(0.0, 0.0, 0.0, 1.0);
+        assert!((below.x / below.w).abs() < 1e-5 && (below.y / below.w).abs() < 1e-5);
+        assert!(inside(&matrix, vec3(0.0, 0.0, 0.0)));
+        // Beyond the range and outside the cone
+        assert!(!inside(&matrix, vec3(0.0, -6.0, 0.0)));
+        assert!(!inside(&matrix, vec3(3.0, 1.0, 0.0)));
+    }
+}
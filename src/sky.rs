@@ -0,0 +1,231 @@
+#![allow(non_snake_case)]
+
+//! Scene background: a flat clear color, the selected environment's cube map or a procedural
+//! sky whose sun follows the first directional light.
+//!
+//! Both skies are one full-screen triangle at the far plane, drawn after the opaque meshes
+//! with depth writes off, so only uncovered pixels run the sky shader.
+
+use crate::framebuffer::FullscreenTriangle;
+use crate::lights::{Light, LightKind};
+use crate::shader::Shader;
+use crate::texture::{srgbToLinear, Texture};
+use cgmath::{InnerSpace, Matrix3, Matrix4, SquareMatrix};
+use gl::types::{GLenum, GLint};
+use imgui::Ui;
+
+type Vector3 = cgmath::Vector3<f32>;
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum SkyMode {
+    Color,
+    /// The environment picked for image-based lighting, see `pbr::Environment`.
+    Cubemap,
+    Procedural,
+}
+
+impl SkyMode {
+    pub const ALL: [SkyMode; 3] = [SkyMode::Color, SkyMode::Cubemap, SkyMode::Procedural];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SkyMode::Color => "Color",
+            SkyMode::Cubemap => "Cube map",
+            SkyMode::Procedural => "Procedural",
+        }
+    }
+}
+
+/// Colors are picked as sRGB and converted when drawing.
+#[derive(Clone, Debug)]
+pub struct SkySettings {
+    pub mode: SkyMode,
+    pub color: [f32; 3],
+    pub zenith: [f32; 3],
+    pub horizon: [f32; 3],
+    pub ground: [f32; 3],
+    /// Added around the sun near the horizon.
+    pub sunset: [f32; 3],
+    /// Angular radius of the sun disc, in degrees.
+    pub sunSize: f32,
+    pub sunIntensity: f32,
+    /// Scales the whole sky, it's drawn in HDR.
+    pub intensity: f32,
+}
+
+impl Default for SkySettings {
+    fn default() -> SkySettings {
+        SkySettings {
+            mode: SkyMode::Procedural,
+            color: [0.2, 0.3, 0.3],
+            zenith: [0.25, 0.45, 0.8],
+            horizon: [0.75, 0.85, 0.95],
+            ground: [0.3, 0.28, 0.25],
+            sunset: [1.0, 0.5, 0.2],
+            sunSize: 0.8,
+            sunIntensity: 20.0,
+            intensity: 1.0,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl SkySettings {
+    /// `color` in linear RGB, for `glClearColor`.
+    pub fn clearColor(&self) -> [f32; 3] {
+        self.color.map(srgbToLinear)
+    }
+}
+
+/// Direction towards the sun: against the first enabled directional light. `None` without one.
+pub fn sunDirection(lights: &[Light]) -> Option<Vector3> {
+    lights
+        .iter()
+        .find(|light| light.enabled && light.kind == LightKind::Directional)
+        .map(|light| -light.direction.normalize())
+}
+
+/// Inverse of `projection * view` without the view's translation: takes NDC points to world
+/// space directions, the sky being infinitely far away.
+pub fn inverseSkyMatrix(view: &Matrix4<f32>, projection: &Matrix4<f32>) -> Matrix4<f32> {
+    let rotation = Matrix4::from(Matrix3::from_cols(view.x.truncate(), view.y.truncate(), view.z.truncate()));
+    (projection * rotation).invert().unwrap_or_else(Matrix4::identity)
+}
+
+pub struct Sky {
+    pub settings: SkySettings,
+    cubemapShader: Shader,
+    proceduralShader: Shader,
+    triangle: FullscreenTriangle,
+}
+
+#[allow(dead_code)]
+impl Sky {
+    pub fn new() -> Sky {
+        Sky {
+            settings: SkySettings::default(),
+            cubemapShader: Shader::new("resources/shaders/sky.vert", "resources/shaders/sky_cubemap.frag"),
+            proceduralShader: Shader::new("resources/shaders/sky.vert", "resources/shaders/sky_procedural.frag"),
+            triangle: FullscreenTriangle::new(),
+        }
+    }
+
+    /// Fills the pixels no mesh covered. Falls back to the clear color without an
+    /// `environment` in cube map mode.
+    pub fn draw(&self, view: &Matrix4<f32>, projection: &Matrix4<f32>, lights: &[Light], environment: Option<&Texture>) {
+        let settings = &self.settings;
+        let shader = match (settings.mode, environment) {
+            (SkyMode::Cubemap, Some(cube)) => {
+                self.cubemapShader.bind();
+                self.cubemapShader.setTexture("u_sky", 0, cube);
+                &self.cubemapShader
+            }
+            (SkyMode::Procedural, _) => {
+                let shader = &self.proceduralShader;
+                shader.bind();
+                let color = |value: [f32; 3]| Vector3::from(value.map(srgbToLinear));
+                shader.setUniform3fv("u_zenith", &color(settings.zenith));
+                shader.setUniform3fv("u_horizon", &color(settings.horizon));
+                shader.setUniform3fv("u_ground", &color(settings.ground));
+                shader.setUniform3fv("u_sunset", &color(settings.sunset));
+                let sun = lights.iter().find(|light| light.enabled && light.kind == LightKind::Directional);
+                shader.setUniform1i("u_hasSun", sun.is_some() as i32);
+                if let (Some(light), Some(direction)) = (sun, sunDirection(lights)) {
+                    shader.setUniform3fv("u_sunDirection", &direction);
+                    shader.setUniform3fv("u_sunColor", &(light.color.map(srgbToLinear) * light.intensity));
+                }
+                shader.setUniform1f("u_sunCos", settings.sunSize.to_radians().cos());
+                shader.setUniform1f("u_sunIntensity", settings.sunIntensity);
+                shader
+            }
+            _ => return,
+        };
+        shader.setMatrix4f("u_inverseSky", &inverseSkyMatrix(view, projection));
+        shader.setUniform1f("u_intensity", settings.intensity);
+
+        let mut polygonMode: [GLint; 2] = [0; 2];
+        unsafe {
+            gl::GetIntegerv(gl::POLYGON_MODE, polygonMode.as_mut_ptr());
+            gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+            gl::DepthMask(gl::FALSE);
+            // The triangle sits exactly at the far plane, where the depth buffer was cleared to
+            gl::DepthFunc(gl::LEQUAL);
+            self.triangle.draw();
+            gl::DepthFunc(gl::LESS);
+            gl::DepthMask(gl::TRUE);
+            gl::PolygonMode(gl::FRONT_AND_BACK, polygonMode[0] as GLenum);
+        }
+    }
+
+    pub fn destroy(&self) {
+        self.cubemapShader.delete();
+        self.proceduralShader.delete();
+        self.triangle.destroy();
+    }
+}
+
+/// ImGui editor for the background. `hasEnvironment` tells whether cube map mode has a cube
+/// map to show.
+pub fn editSky(ui: &Ui, settings: &mut SkySettings, hasEnvironment: bool) {
+    let mut item = settings.mode as usize;
+    if ui.combo_simple_string("Background", &mut item, &SkyMode::ALL.map(SkyMode::label)) {
+        settings.mode = SkyMode::ALL[item];
+    }
+    match settings.mode {
+        SkyMode::Color => {
+            ui.color_edit3("Color", &mut settings.color);
+        }
+        SkyMode::Cubemap => {
+            if !hasEnvironment {
+                ui.text_disabled("Pick an environment first");
+            }
+            ui.slider("Sky intensity", 0.0, 4.0, &mut settings.intensity);
+        }
+        SkyMode::Procedural => {
+            ui.color_edit3("Zenith", &mut settings.zenith);
+            ui.color_edit3("Horizon", &mut settings.horizon);
+            ui.color_edit3("Ground", &mut settings.ground);
+            ui.color_edit3("Sunset", &mut settings.sunset);
+            ui.slider("Sun size", 0.1, 5.0, &mut settings.sunSize);
+            ui.slider("Sun intensity", 0.0, 100.0, &mut settings.sunIntensity);
+            ui.slider("Sky intensity", 0.0, 4.0, &mut settings.intensity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{perspective, vec3, Deg, Point3, Transform};
+
+    #[test]
+    fn sunFollowsFirstEnabledDirectionalLight() {
+        let white = vec3(1.0, 1.0, 1.0);
+        let mut lights = vec![
+            Light::point(vec3(0.0, 1.0, 0.0), white, 1.0, 5.0),
+            Light::directional(vec3(0.0, -1.0, 0.0), white, 1.0),
+            Light::directional(vec3(1.0, 0.0, 0.0), white, 1.0),
+        ];
+        assert_eq!(sunDirection(&lights), Some(vec3(0.0, 1.0, 0.0)));
+        lights[1].enabled = false;
+        assert_eq!(sunDirection(&lights), Some(vec3(-1.0, 0.0, 0.0)));
+        lights.truncate(1);
+        assert_eq!(sunDirection(&lights), None);
+    }
+
+    #[test]
+    fn screenCenterLooksForward() {
+        let eye = Point3::new(3.0, 2.0, -5.0);
+        let forward = vec3(1.0, 0.5, 2.0).normalize();
+        let view = Matrix4::look_to_rh(eye, forward, vec3(0.0, 1.0, 0.0));
+        let projection = perspective(Deg(60.0), 1.5, 0.1, 100.0);
+        let inverse = inverseSkyMatrix(&view, &projection);
+        // sky.vert puts the triangle on the far plane
+        let direction = inverse.transform_point(Point3::new(0.0, 0.0, 1.0));
+        let direction = vec3(direction.x, direction.y, direction.z).normalize();
+        assert!((direction - forward).magnitude() < 1e-4, "{:?}", direction);
+        // Top of the screen looks further up
+        let up = inverse.transform_point(Point3::new(0.0, 1.0, 1.0));
+        assert!(vec3(up.x, up.y, up.z).normalize().y > direction.y);
+    }
+}
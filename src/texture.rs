@@ -0,0 +1,526 @@
+#![allow(non_snake_case)]
+
+use gl::types::{GLenum, GLfloat, GLint, GLsizei, GLuint};
+use std::os::raw::c_void;
+use std::path::Path;
+
+// Core since 4.6, missing from the generated bindings
+const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FF;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Pixels {
+    Rgba8(Vec<u8>),
+    /// Linear HDR values.
+    RgbaF32(Vec<f32>),
+}
+
+/// Decoded image, rows top to bottom.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Pixels,
+}
+
+#[allow(dead_code)]
+impl Image {
+    pub fn rgba8(width: u32, height: u32, pixels: Vec<u8>) -> Image {
+        assert_eq!(pixels.len(), (width * height * 4) as usize, "Pixel data doesn't match {}x{}", width, height);
+        Image {
+            width,
+            height,
+            pixels: Pixels::Rgba8(pixels),
+        }
+    }
+
+    /// Loads a PNG, JPEG or Radiance HDR file. HDR images stay floating point, everything
+    /// else is converted to 8-bit RGBA.
+    pub fn load(path: &str) -> Result<Image, String> {
+        let image = image::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        let (width, height) = (image.width(), image.height());
+        let pixels = match image.color() {
+            image::ColorType::Rgb32F | image::ColorType::Rgba32F => Pixels::RgbaF32(image.into_rgba32f().into_raw()),
+            _ => Pixels::Rgba8(image.into_rgba8().into_raw()),
+        };
+        Ok(Image { width, height, pixels })
+    }
+
+    pub fn isHdr(&self) -> bool {
+        matches!(self.pixels, Pixels::RgbaF32(_))
+    }
+
+    /// Flips the rows, for sources whose V coordinate points up (OBJ). OpenGL takes the first
+    /// row as V = 0.
+    pub fn flipVertically(&mut self) {
+        let row = self.width as usize * 4;
+        match &mut self.pixels {
+            Pixels::Rgba8(pixels) => flipRows(pixels, row),
+            Pixels::RgbaF32(pixels) => flipRows(pixels, row),
+        }
+    }
+
+    fn dataPtr(&self) -> *const c_void {
+        match &self.pixels {
+            Pixels::Rgba8(pixels) => pixels.as_ptr() as *const c_void,
+            Pixels::RgbaF32(pixels) => pixels.as_ptr() as *const c_void,
+        }
+    }
+
+    fn dataType(&self) -> GLenum {
+        match self.pixels {
+            Pixels::Rgba8(_) => gl::UNSIGNED_BYTE,
+            Pixels::RgbaF32(_) => gl::FLOAT,
+        }
+    }
+
+    /// `srgb` only applies to 8-bit images, HDR ones are linear already.
+    fn internalFormat(&self, srgb: bool) -> GLenum {
+        match self.pixels {
+            Pixels::Rgba8(_) if srgb => gl::SRGB8_ALPHA8,
+            Pixels::Rgba8(_) => gl::RGBA8,
+            Pixels::RgbaF32(_) => gl::RGBA16F,
+        }
+    }
+}
+
+fn flipRows<T>(pixels: &mut [T], row: usize) {
+    let height = pixels.len() / row;
+    for y in 0..height / 2 {
+        let (top, bottom) = pixels.split_at_mut((height - 1 - y) * row);
+        top[y * row..(y + 1) * row].swap_with_slice(&mut bottom[..row]);
+    }
+}
+
+/// Decodes an sRGB encoded channel to linear, as sampling an sRGB texture does.
+pub fn srgbToLinear(c: f32) -> f32 {
+    if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+}
+
+/// Levels in a full mip chain down to 1x1.
+pub fn mipLevels(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// Size shared by all `images`, panics if they differ or there are none.
+fn commonSize(images: &[Image]) -> (u32, u32) {
+    let first = images.first().expect("No images given");
+    for image in images {
+        assert_eq!(
+            (image.width, image.height),
+            (first.width, first.height),
+            "Layers must all be the same size"
+        );
+        assert_eq!(image.isHdr(), first.isHdr(), "Layers must all be HDR or all 8-bit");
+    }
+    (first.width, first.height)
+}
+
+/// Filtering and wrapping, applied to a texture with `Texture::setSampler` or kept in a
+/// separate `Sampler` object.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct SamplerState {
+    pub minFilter: GLenum,
+    pub magFilter: GLenum,
+    pub wrapS: GLenum,
+    pub wrapT: GLenum,
+    pub wrapR: GLenum,
+    /// Maximum anisotropy, 1 disables it. Clamped to what the driver supports.
+    pub anisotropy: f32,
+}
+
+impl Default for SamplerState {
+    fn default() -> Self {
+        SamplerState {
+            minFilter: gl::LINEAR_MIPMAP_LINEAR,
+            magFilter: gl::LINEAR,
+            wrapS: gl::REPEAT,
+            wrapT: gl::REPEAT,
+            wrapR: gl::REPEAT,
+            anisotropy: 1.0,
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl SamplerState {
+    /// Unfiltered and without mipmaps, for pixel art and lookup tables.
+    pub fn nearest() -> SamplerState {
+        SamplerState {
+            minFilter: gl::NEAREST,
+            magFilter: gl::NEAREST,
+            ..Default::default()
+        }
+    }
+
+    /// Bilinear, clamped and without mipmaps, for render targets and cube maps.
+    pub fn clamped() -> SamplerState {
+        SamplerState {
+            minFilter: gl::LINEAR,
+            ..Default::default()
+        }
+        .wrap(gl::CLAMP_TO_EDGE)
+    }
+
+    pub fn wrap(mut self, mode: GLenum) -> SamplerState {
+        (self.wrapS, self.wrapT, self.wrapR) = (mode, mode, mode);
+        self
+    }
+
+    pub fn anisotropic(mut self, anisotropy: f32) -> SamplerState {
+        self.anisotropy = anisotropy;
+        self
+    }
+
+    /// Whether the minification filter reads mip levels, so textures need a full chain.
+    pub fn usesMipmaps(&self) -> bool {
+        !matches!(self.minFilter, gl::NEAREST | gl::LINEAR)
+    }
+
+    unsafe fn apply(
+        &self,
+        id: GLuint,
+        setInt: unsafe fn(GLuint, GLenum, GLint),
+        setFloat: unsafe fn(GLuint, GLenum, GLfloat),
+    ) {
+        unsafe {
+            setInt(id, gl::TEXTURE_MIN_FILTER, self.minFilter as GLint);
+            setInt(id, gl::TEXTURE_MAG_FILTER, self.magFilter as GLint);
+            setInt(id, gl::TEXTURE_WRAP_S, self.wrapS as GLint);
+            setInt(id, gl::TEXTURE_WRAP_T, self.wrapT as GLint);
+            setInt(id, gl::TEXTURE_WRAP_R, self.wrapR as GLint);
+            if self.anisotropy > 1.0 {
+                let mut max = 1.0;
+                gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+                setFloat(id, TEXTURE_MAX_ANISOTROPY, self.anisotropy.min(max));
+            } else {
+                setFloat(id, TEXTURE_MAX_ANISOTROPY, 1.0);
+            }
+        }
+    }
+}
+
+/// Sampler object, overrides the sampling state of whatever texture shares its unit.
+pub struct Sampler {
+    id: GLuint,
+}
+
+#[allow(dead_code)]
+impl Sampler {
+    pub fn new(state: &SamplerState) -> Sampler {
+        let mut id = 0;
+        unsafe {
+            gl::CreateSamplers(1, &mut id);
+            state.apply(id, gl::SamplerParameteri, gl::SamplerParameterf);
+        }
+        Sampler { id }
+    }
+
+    pub fn bind(&self, unit: GLuint) {
+        unsafe {
+            gl::BindSampler(unit, self.id);
+        }
+    }
+
+    pub fn unbind(unit: GLuint) {
+        unsafe {
+            gl::BindSampler(unit, 0);
+        }
+    }
+
+    pub fn destroy(&self) {
+        unsafe {
+            gl::DeleteSamplers(1, &self.id);
+        }
+    }
+}
+
+/// Immutable-storage texture: 2D, cube map or 2D array.
+pub struct Texture {
+    id: GLuint,
+    target: GLenum,
+    width: u32,
+    height: u32,
+    layers: u32,
+    levels: u32,
+    internalFormat: GLenum,
+    samples: u32,
+}
+
+#[allow(dead_code)]
+impl Texture {
+    /// Allocates storage without contents, e.g. for render targets. `layers` is 6 for cube
+    /// maps and 1 for plain 2D textures.
+    pub fn storage(target: GLenum, width: u32, height: u32, layers: u32, levels: u32, internalFormat: GLenum) -> Texture {
+        let mut id = 0;
+        unsafe {
+            gl::CreateTextures(target, 1, &mut id);
+            match target {
+                gl::TEXTURE_2D_ARRAY => gl::TextureStorage3D(
+                    id,
+                    levels as GLsizei,
+                    internalFormat,
+                    width as GLsizei,
+                    height as GLsizei,
+                    layers as GLsizei,
+                ),
+                _ => gl::TextureStorage2D(id, levels as GLsizei, internalFormat, width as GLsizei, height as GLsizei),
+            }
+        }
+        Texture {
+            id,
+            target,
+            width,
+            height,
+            layers,
+            levels,
+            internalFormat,
+            samples: 1,
+        }
+    }
+
+    /// Multisampled render target, resolved with a blit. It can't be sampled with filtering,
+    /// so it takes no sampler state.
+    pub fn storageMultisample(width: u32, height: u32, samples: u32, internalFormat: GLenum) -> Texture {
+        let mut id = 0;
+        unsafe {
+            gl::CreateTextures(gl::TEXTURE_2D_MULTISAMPLE, 1, &mut id);
+            gl::TextureStorage2DMultisample(id, samples as GLsizei, internalFormat, width as GLsizei, height as GLsizei, gl::TRUE);
+        }
+        Texture {
+            id,
+            target: gl::TEXTURE_2D_MULTISAMPLE,
+            width,
+            height,
+            layers: 1,
+            levels: 1,
+            internalFormat,
+            samples,
+        }
+    }
+
+    /// Uploads `layers` (one for 2D, the +X, -X, +Y, -Y, +Z, -Z faces for cube maps) and
+    /// builds the mip chain if `sampler` needs one.
+    fn upload(target: GLenum, layers: &[Image], srgb: bool, sampler: &SamplerState) -> Texture {
+        let (width, height) = commonSize(layers);
+        let levels = if sampler.usesMipmaps() { mipLevels(width, height) } else { 1 };
+        let format = layers[0].internalFormat(srgb);
+        let texture = Texture::storage(target, width, height, layers.len() as u32, levels, format);
+        unsafe {
+            // Rows of RGBA8 are always 4-byte aligned, but reset in case someone changed it
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+            for (layer, image) in layers.iter().enumerate() {
+                if target == gl::TEXTURE_2D {
+                    gl::TextureSubImage2D(
+                        texture.id,
+                        0,
+                        0,
+                        0,
+                        width as GLsizei,
+                        height as GLsizei,
+                        gl::RGBA,
+                        image.dataType(),
+                        image.dataPtr(),
+                    );
+                } else {
+                    // Cube faces are addressed as layers with DSA
+                    gl::TextureSubImage3D(
+                        texture.id,
+                        0,
+                        0,
+                        0,
+                        layer as GLint,
+                        width as GLsizei,
+                        height as GLsizei,
+                        1,
+                        gl::RGBA,
+                        image.dataType(),
+                        image.dataPtr(),
+                    );
+                }
+            }
+        }
+        texture.setSampler(sampler);
+        texture.generateMipmaps();
+        texture
+    }
+
+    /// `srgb` marks 8-bit color data (albedo, UI), not data like normals or roughness.
+    pub fn new2D(image: &Image, srgb: bool, sampler: &SamplerState) -> Texture {
+        Texture::upload(gl::TEXTURE_2D, std::slice::from_ref(image), srgb, sampler)
+    }
+
+    pub fn load2D(path: &str, srgb: bool, sampler: &SamplerState) -> Result<Texture, String> {
+        Ok(Texture::new2D(&Image::load(path)?, srgb, sampler))
+    }
+
+    /// Faces in +X, -X, +Y, -Y, +Z, -Z order. They must be square and the same size.
+    pub fn newCube(faces: &[Image; 6], srgb: bool, sampler: &SamplerState) -> Texture {
+        assert_eq!(faces[0].width, faces[0].height, "Cube map faces must be square");
+        unsafe {
+            gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS);
+        }
+        Texture::upload(gl::TEXTURE_CUBE_MAP, faces, srgb, sampler)
+    }
+
+    pub fn loadCube(paths: [&str; 6], srgb: bool, sampler: &SamplerState) -> Result<Texture, String> {
+        let mut faces = Vec::with_capacity(6);
+        for path in paths {
+            faces.push(Image::load(path)?);
+        }
+        let faces: [Image; 6] = faces.try_into().unwrap();
+        Ok(Texture::newCube(&faces, srgb, sampler))
+    }
+
+    /// Loads the six faces named `px`, `nx`, `py`, `ny`, `pz`, `nz` with `extension` from
+    /// `directory`.
+    pub fn loadCubeDirectory(directory: &str, extension: &str, srgb: bool, sampler: &SamplerState) -> Result<Texture, String> {
+        let paths = ["px", "nx", "py", "ny", "pz", "nz"]
+            .map(|face| Path::new(directory).join(format!("{}.{}", face, extension)).to_string_lossy().to_string());
+        Texture::loadCube(paths.each_ref().map(String::as_str), srgb, sampler)
+    }
+
+    pub fn newArray(layers: &[Image], srgb: bool, sampler: &SamplerState) -> Texture {
+        Texture::upload(gl::TEXTURE_2D_ARRAY, layers, srgb, sampler)
+    }
+
+    /// Rebuilds levels 1.. from level 0, a no-op without mipmaps.
+    pub fn generateMipmaps(&self) {
+        if self.levels > 1 {
+            unsafe {
+                gl::GenerateTextureMipmap(self.id);
+            }
+        }
+    }
+
+    /// A texture created without mipmaps keeps a single level, so mipmapped minification
+    /// filters would leave it incomplete.
+    pub fn setSampler(&self, sampler: &SamplerState) {
+        let mut sampler = *sampler;
+        if self.levels == 1 && sampler.usesMipmaps() {
+            sampler.minFilter = gl::LINEAR;
+        }
+        unsafe {
+            sampler.apply(self.id, gl::TextureParameteri, gl::TextureParameterf);
+        }
+    }
+
+    pub fn bind(&self, unit: GLuint) {
+        unsafe {
+            gl::BindTextureUnit(unit, self.id);
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn target(&self) -> GLenum {
+        self.target
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn layers(&self) -> u32 {
+        self.layers
+    }
+
+    pub fn levels(&self) -> u32 {
+        self.levels
+    }
+
+    pub fn internalFormat(&self) -> GLenum {
+        self.internalFormat
+    }
+
+    /// 1 unless created with `storageMultisample`.
+    pub fn samples(&self) -> u32 {
+        self.samples
+    }
+
+    pub fn destroy(&self) {
+        unsafe {
+            gl::DeleteTextures(1, &self.id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mipChainsEndAtOnePixel() {
+        assert_eq!(mipLevels(1, 1), 1);
+        assert_eq!(mipLevels(2, 2), 2);
+        assert_eq!(mipLevels(256, 256), 9);
+        assert_eq!(mipLevels(300, 17), 9);
+        assert_eq!(mipLevels(1, 1024), 11);
+    }
+
+    #[test]
+    fn srgbDecodesToLinear() {
+        assert_eq!(srgbToLinear(0.0), 0.0);
+        assert!((srgbToLinear(1.0) - 1.0).abs() < 1e-6);
+        // Middle grey on screen is about a fifth of the light
+        assert!((srgbToLinear(0.5) - 0.214).abs() < 1e-3);
+    }
+
+    #[test]
+    fn pngsLoadAsRgba8() {
+        // RGB checker, alpha is added
+        let image = Image::load("resources/models/checker.png").unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert!(!image.isHdr());
+        assert_eq!(image.internalFormat(true), gl::SRGB8_ALPHA8);
+        let Pixels::Rgba8(pixels) = &image.pixels else { unreachable!() };
+        assert_eq!(&pixels[..8], &[255, 255, 255, 255, 0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn hdrsStayFloatingPoint() {
+        let image = Image::load("resources/textures/test.hdr").unwrap();
+        assert!(image.isHdr());
+        assert_eq!(image.internalFormat(true), gl::RGBA16F);
+        let Pixels::RgbaF32(pixels) = &image.pixels else { unreachable!() };
+        let expected = [1.0, 0.5, 0.25, 1.0, 4.0, 0.0, 0.0, 1.0];
+        for (value, expected) in pixels.iter().zip(expected) {
+            assert!((value - expected).abs() < 0.02, "{:?}", pixels);
+        }
+    }
+
+    #[test]
+    fn missingFilesReportThePath() {
+        let error = Image::load("resources/textures/missing.png").err().unwrap();
+        assert!(error.starts_with("resources/textures/missing.png"), "{}", error);
+    }
+
+    #[test]
+    fn flipSwapsRows() {
+        let mut image = Image::rgba8(1, 3, vec![1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3]);
+        image.flipVertically();
+        assert_eq!(image.pixels, Pixels::Rgba8(vec![3, 3, 3, 3, 2, 2, 2, 2, 1, 1, 1, 1]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn layersMustMatchInSize() {
+        commonSize(&[Image::rgba8(1, 1, vec![0; 4]), Image::rgba8(2, 1, vec![0; 8])]);
+    }
+
+    #[test]
+    fn samplerPresetsKnowAboutMipmaps() {
+        assert!(SamplerState::default().usesMipmaps());
+        assert!(!SamplerState::nearest().usesMipmaps());
+        let clamped = SamplerState::clamped();
+        assert!(!clamped.usesMipmaps());
+        assert_eq!((clamped.wrapS, clamped.wrapT, clamped.wrapR), (gl::CLAMP_TO_EDGE, gl::CLAMP_TO_EDGE, gl::CLAMP_TO_EDGE));
+        assert_eq!(SamplerState::default().anisotropic(8.0).anisotropy, 8.0);
+    }
+}